@@ -1,4 +1,10 @@
 fn main() {
-    println!("cargo:rustc-link-arg-bin=winbang=/MANIFEST:EMBED");
-    println!("cargo:rustc-link-arg-bin=winbang=/MANIFESTINPUT:app.manifest");
+    // The manifest is embedded with MSVC linker flags; other toolchains
+    // (and non-Windows builds) don't understand them.
+    if std::env::var("CARGO_CFG_TARGET_ENV").as_deref() == Ok("msvc") {
+        println!("cargo:rustc-link-arg-bin=winbang=/MANIFEST:EMBED");
+        println!(
+            "cargo:rustc-link-arg-bin=winbang=/MANIFESTINPUT:app.manifest"
+        );
+    }
 }
@@ -0,0 +1,271 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::path::{self, Path};
+use std::process::{Command, Stdio};
+
+use crate::audit::{self, Operation};
+use crate::cmdline::{quote_arg, split_args, split_command_line};
+use crate::config::{Config, VerbAction};
+use crate::dispatch::{build_command, run_to_exit};
+use crate::error::WinbangError;
+use crate::log_debug;
+use crate::platform::run_elevated;
+use crate::policy::check_launch;
+use crate::script::get_script_metadata;
+use crate::template::{is_batch_file, to_wsl};
+use crate::zone::NtfsStreams;
+
+/// Run one of Winbang's built-in verb actions on a script.
+///
+/// # Arguments
+///
+/// * `action`: The action configured for the verb.
+/// * `file_path`: The script the verb was invoked on.
+/// * `extra_args`: Arguments passed after the file by the shell.
+/// * `config`: Active configuration.
+///
+/// returns: Result<i32, WinbangError> — the exit code of what the action
+/// ran.
+///
+/// # Examples
+///
+/// ```
+/// let code = run_action(VerbAction::CopyCommand, &file, &[], &config)?;
+/// ```
+pub(crate) fn run_action(
+    action: VerbAction,
+    file_path: &Path,
+    extra_args: &[String],
+    config: &Config,
+) -> Result<i32, WinbangError> {
+    log_debug!(
+        "run_action: {:?} on {:?} with {:?}",
+        action,
+        file_path,
+        extra_args
+    );
+
+    let result = match action {
+        VerbAction::RunWithArgs => run_with_args(file_path, extra_args, config),
+        VerbAction::RunInWsl => run_in_wsl(file_path, extra_args, config),
+        VerbAction::RunElevated => {
+            run_elevated_keep_open(file_path, extra_args, config)
+        }
+        VerbAction::CopyCommand => copy_command(file_path, extra_args, config),
+    };
+
+    result.inspect_err(|e| log_debug!("run_action: {:?} failed: {}", action, e))
+}
+
+/// The command Winbang would run for the script with these arguments.
+fn script_command(
+    file_path: &Path,
+    args: Vec<String>,
+    config: &Config,
+) -> Result<Command, WinbangError> {
+    let script = get_script_metadata(
+        &file_path.to_string_lossy().to_string(),
+        config.file_associations.as_deref().unwrap_or(&[]),
+    );
+    let args = if args.is_empty() { None } else { Some(args) };
+    build_command(&script, args, config)
+}
+
+fn run_with_args(
+    file_path: &Path,
+    extra_args: &[String],
+    config: &Config,
+) -> Result<i32, WinbangError> {
+    audit::record_operation(Operation::Execute);
+    let name = file_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    print!("Arguments for {}: ", name);
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+
+    let mut args = extra_args.to_vec();
+    args.extend(split_args(line.trim_end_matches(['\r', '\n'])));
+    let mut command = script_command(file_path, args, config)?;
+    run_to_exit(&mut command)
+}
+
+/// Run the script through WSL from its own directory, letting its shebang
+/// pick the interpreter on the Linux side. Policy sees `wsl.exe` as the
+/// interpreter.
+fn run_in_wsl(
+    file_path: &Path,
+    extra_args: &[String],
+    config: &Config,
+) -> Result<i32, WinbangError> {
+    audit::record_operation(Operation::Execute);
+    check_launch(config, "wsl.exe", file_path, &NtfsStreams)
+        .map_err(|blocked| WinbangError::blocked(file_path, blocked))?;
+    let absolute = path::absolute(file_path)?;
+    let mut command = Command::new("wsl.exe");
+    if let Some(dir) = absolute.parent() {
+        command.arg("--cd").arg(to_wsl(&dir.to_string_lossy()));
+    }
+    command
+        .arg("--exec")
+        .arg(to_wsl(&absolute.to_string_lossy()))
+        .args(extra_args);
+    audit::record_command(&command);
+    run_to_exit(&mut command)
+}
+
+/// Start Winbang elevated to run the script with `keep-open`, so its
+/// console stays open after it exits. No shell sits in between to read the
+/// file name or arguments, and it runs from the script's directory rather
+/// than System32.
+fn run_elevated_keep_open(
+    file_path: &Path,
+    extra_args: &[String],
+    config: &Config,
+) -> Result<i32, WinbangError> {
+    audit::record_operation(Operation::Elevate);
+    // Fail here, unelevated, when the script can't or mustn't run.
+    script_command(file_path, extra_args.to_vec(), config)?;
+    let absolute = path::absolute(file_path)?;
+    let exe = env::current_exe()?;
+    let params = keep_open_params(&absolute, extra_args);
+    let mut command = Command::new(&exe);
+    command.args(split_command_line(&params));
+    audit::record_command(&command);
+    run_elevated(&exe, &params, absolute.parent())
+        .map_err(|e| WinbangError::spawn(&exe, e))?;
+    Ok(0)
+}
+
+/// Winbang's command line for `keep-open` on `file_path`, without the
+/// program.
+fn keep_open_params(file_path: &Path, extra_args: &[String]) -> String {
+    let mut parts = vec![
+        "keep-open".to_string(),
+        "--file".to_string(),
+        quote_arg(&file_path.to_string_lossy()),
+        "--".to_string(),
+    ];
+    parts.extend(extra_args.iter().map(|arg| quote_arg(arg)));
+    parts.join(" ")
+}
+
+/// Run the script in this console, as `keep-open` does once elevated.
+/// Returns its exit code.
+pub(crate) fn keep_open(
+    file_path: &Path,
+    extra_args: &[String],
+    config: &Config,
+) -> Result<i32, WinbangError> {
+    let mut command = script_command(file_path, extra_args.to_vec(), config)?;
+    run_to_exit(&mut command)
+}
+
+/// Wait for Enter, so the console of a `keep-open` run stays readable.
+pub(crate) fn pause(code: i32) {
+    print!("\n[exit code {}] Press Enter to close.", code);
+    let _ = io::stdout().flush();
+    let _ = io::stdin().lock().read_line(&mut String::new());
+}
+
+fn copy_command(
+    file_path: &Path,
+    extra_args: &[String],
+    config: &Config,
+) -> Result<i32, WinbangError> {
+    audit::record_operation(Operation::Copy);
+    // The recorded argv is the script's command, the text copied.
+    let command = script_command(file_path, extra_args.to_vec(), config)?;
+    let text = render_command(&command);
+
+    let mut clip = Command::new("clip.exe")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| WinbangError::spawn("clip.exe", e))?;
+    if let Some(mut stdin) = clip.stdin.take() {
+        // clip.exe reads the console code page unless given a UTF-16 BOM.
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        stdin.write_all(&bytes)?;
+    }
+    Ok(clip.wait()?.code().unwrap_or(1))
+}
+
+/// Render a command as a single command line. Arguments to a batch file were
+/// already escaped by `build_command` and are kept as-is.
+///
+/// # Arguments
+///
+/// * `command`: The command to render.
+///
+/// returns: String
+///
+/// # Examples
+///
+/// ```
+/// let line = render_command(Command::new("python").arg("a b.py"));
+/// assert_eq!(line, r#"python "a b.py""#);
+/// ```
+pub(crate) fn render_command(command: &Command) -> String {
+    let program = command.get_program().to_string_lossy().to_string();
+    let batch = is_batch_file(&program);
+
+    let mut parts = vec![quote_arg(&program)];
+    for arg in command.get_args() {
+        let arg = arg.to_string_lossy();
+        parts.push(if batch {
+            arg.to_string()
+        } else {
+            quote_arg(&arg)
+        });
+    }
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{self, Cli};
+    use clap::Parser;
+
+    #[test]
+    fn render_command_round_trips() {
+        let mut command = Command::new(r"C:\Program Files\Python\python.exe");
+        command.args(["C:\\my scripts\\x.py", "plain", "", r#"say "hi""#]);
+
+        let line = render_command(&command);
+        assert_eq!(
+            split_command_line(&line),
+            [
+                r"C:\Program Files\Python\python.exe",
+                "C:\\my scripts\\x.py",
+                "plain",
+                "",
+                r#"say "hi""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_open_passes_metacharacters_through_untouched() {
+        let file = Path::new(r"C:\Users\me\a&calc.py");
+        let extras = ["x|y".to_string(), "%PATH%".into(), "-v".into()];
+        let params = keep_open_params(file, &extras);
+
+        let mut argv = vec!["winbang.exe".to_string()];
+        argv.extend(split_command_line(&params));
+        let cli = Cli::try_parse_from(argv).unwrap();
+        let Some(cli::Command::KeepOpen {
+            file: parsed,
+            extras: parsed_extras,
+        }) = cli.command
+        else {
+            panic!("expected keep-open");
+        };
+        assert_eq!(parsed, file);
+        assert_eq!(parsed_extras, extras);
+    }
+}
@@ -0,0 +1,334 @@
+//! `winbang assoc`: per-user or machine-wide file associations that add
+//! Winbang to an extension's "Open with" list without taking over the
+//! extension, so verb pass-through can still find the original handler.
+
+use crate::install::{Scope, progid_for};
+use crate::manifest;
+use crate::registry::{RegValue, RegistryBackend, Root};
+use crate::setup::Icon;
+
+const CLASSES: &str = "Software\\Classes";
+const FILE_EXTS: &str =
+    "Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\FileExts";
+
+/// The state of one extension associated with Winbang.
+#[derive(Debug, PartialEq)]
+pub(crate) struct AssocStatus {
+    /// Extension with its leading dot, lowercased.
+    pub ext: String,
+    /// Whether the current user's Explorer UserChoice already picks
+    /// Winbang. Until it does, double-clicking still opens the previous
+    /// handler; only Explorer's "Open with -> Always" prompt can set it.
+    pub user_choice: bool,
+}
+
+/// Normalize an extension argument to `.ext`, lowercased.
+fn normalize(ext: &str) -> Result<String, String> {
+    let bare = ext.trim().trim_start_matches('.');
+    if bare.is_empty() || bare.contains(['\\', '/', ' ', '.']) {
+        return Err(format!("invalid extension {:?}", ext));
+    }
+    Ok(format!(".{}", bare.to_ascii_lowercase()))
+}
+
+fn progid_key(exe_name: &str) -> String {
+    format!("{}\\{}", CLASSES, progid_for(exe_name))
+}
+
+fn user_choice_is(
+    registry: &dyn RegistryBackend,
+    ext: &str,
+    progid: &str,
+) -> bool {
+    registry
+        .read_string(
+            Root::CurrentUser,
+            &format!("{}\\{}\\UserChoice", FILE_EXTS, ext),
+            "ProgId",
+        )
+        .is_some_and(|id| id.eq_ignore_ascii_case(progid))
+}
+
+/// Write a value, recording it in the manifest if Winbang creates it.
+/// `shared` keys belong to the extension rather than Winbang, so only the
+/// value is recorded.
+fn write_tracked(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    key: &str,
+    name: &str,
+    value: &RegValue,
+    shared: bool,
+) {
+    if shared {
+        manifest::track_shared_value(registry, root, key, name);
+    } else {
+        manifest::track_value(registry, root, key, name);
+    }
+    registry.write_value(root, key, name, value);
+}
+
+/// Associate an extension with Winbang in `scope`'s hive: list Winbang
+/// under `.ext\OpenWithProgids` and in its ProgID's `SupportedTypes`, and
+/// give the ProgID a `DefaultIcon`. `.ext\(Default)` is never written.
+///
+/// # Arguments
+///
+/// * `registry`: Registry to write.
+/// * `scope`: Per-user or machine-wide.
+/// * `exe_name`: Winbang's exe file name, which names its ProgID.
+/// * `ext`: Extension, with or without the leading dot.
+///
+/// returns: Result<AssocStatus, String>
+///
+/// # Examples
+///
+/// ```
+/// let status = add(&registry, Scope::User, "winbang.exe", "py")?;
+/// if !status.user_choice {
+///     println!("Pick Winbang with Open with -> Always");
+/// }
+/// ```
+pub(crate) fn add(
+    registry: &dyn RegistryBackend,
+    scope: Scope,
+    exe_name: &str,
+    ext: &str,
+) -> Result<AssocStatus, String> {
+    let root = scope.root();
+    let ext = normalize(ext)?;
+    let progid = progid_for(exe_name);
+    let progid_key = progid_key(exe_name);
+    let empty = RegValue::Sz(String::new());
+
+    write_tracked(
+        registry,
+        root,
+        &format!("{}\\{}\\OpenWithProgids", CLASSES, ext),
+        &progid,
+        &empty,
+        true,
+    );
+    write_tracked(
+        registry,
+        root,
+        &format!("{}\\SupportedTypes", progid_key),
+        &ext,
+        &empty,
+        false,
+    );
+    let icon_key = format!("{}\\DefaultIcon", progid_key);
+    if !registry.value_exists(root, &icon_key, "") {
+        write_tracked(
+            registry,
+            root,
+            &icon_key,
+            "",
+            &RegValue::ExpandSz(Icon::Script.resource()),
+            false,
+        );
+    }
+
+    Ok(AssocStatus {
+        user_choice: user_choice_is(registry, &ext, &progid),
+        ext,
+    })
+}
+
+/// Undo `add` for one extension. UserChoice is left alone: Windows protects
+/// it with a hash, so only Explorer can change it.
+///
+/// returns: Result<AssocStatus, String> — `user_choice` tells whether
+/// Explorer still opens the extension with Winbang.
+pub(crate) fn remove(
+    registry: &dyn RegistryBackend,
+    scope: Scope,
+    exe_name: &str,
+    ext: &str,
+) -> Result<AssocStatus, String> {
+    let root = scope.root();
+    let ext = normalize(ext)?;
+    let progid = progid_for(exe_name);
+
+    let open_with = format!("{}\\{}\\OpenWithProgids", CLASSES, ext);
+    for name in registry.value_names(root, &open_with) {
+        if name.eq_ignore_ascii_case(&progid) {
+            registry.delete_value(root, &open_with, &name);
+        }
+    }
+    let supported = format!("{}\\SupportedTypes", progid_key(exe_name));
+    for name in registry.value_names(root, &supported) {
+        if name.eq_ignore_ascii_case(&ext) {
+            registry.delete_value(root, &supported, &name);
+        }
+    }
+
+    Ok(AssocStatus {
+        user_choice: user_choice_is(registry, &ext, &progid),
+        ext,
+    })
+}
+
+/// The extensions Winbang's ProgID in `scope`'s hive lists as supported.
+pub(crate) fn list(
+    registry: &dyn RegistryBackend,
+    scope: Scope,
+    exe_name: &str,
+) -> Vec<AssocStatus> {
+    let progid = progid_for(exe_name);
+    let supported = format!("{}\\SupportedTypes", progid_key(exe_name));
+    registry
+        .value_names(scope.root(), &supported)
+        .into_iter()
+        .filter(|ext| !ext.is_empty())
+        .map(|ext| AssocStatus {
+            user_choice: user_choice_is(registry, &ext, &progid),
+            ext,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{MemoryRegistry, PlanRegistry, regfile};
+
+    const EXE: &str = "winbang.exe";
+
+    #[test]
+    fn add_registers_without_touching_the_default() {
+        let reg = MemoryRegistry::new();
+        regfile::load(&reg, include_str!("../test_files/registry/python.reg"))
+            .unwrap();
+        let before = reg.read_string(Root::ClassesRoot, ".py", "");
+
+        let status = add(&reg, Scope::User, EXE, "PY").unwrap();
+        assert_eq!(status.ext, ".py");
+        assert_eq!(reg.read_string(Root::ClassesRoot, ".py", ""), before);
+        assert!(reg.value_exists(
+            Root::ClassesRoot,
+            ".py\\OpenWithProgids",
+            "Applications\\winbang.exe"
+        ));
+        assert_eq!(
+            reg.read_value(
+                Root::ClassesRoot,
+                "Applications\\winbang.exe\\DefaultIcon",
+                ""
+            ),
+            Some(RegValue::ExpandSz(Icon::Script.resource()))
+        );
+        assert_eq!(list(&reg, Scope::User, EXE), [status]);
+    }
+
+    #[test]
+    fn reports_user_choice() {
+        let reg = MemoryRegistry::new();
+        assert!(!add(&reg, Scope::User, EXE, ".rb").unwrap().user_choice);
+
+        reg.write_string(
+            Root::CurrentUser,
+            &format!("{}\\.rb\\UserChoice", FILE_EXTS),
+            "ProgId",
+            "Applications\\WinBang.exe",
+        );
+        assert!(list(&reg, Scope::User, EXE)[0].user_choice);
+        assert!(remove(&reg, Scope::User, EXE, "rb").unwrap().user_choice);
+    }
+
+    #[test]
+    fn remove_keeps_other_handlers() {
+        let reg = MemoryRegistry::new();
+        let open_with = format!("{}\\.sh\\OpenWithProgids", CLASSES);
+        reg.write_string(Root::CurrentUser, &open_with, "sh_auto_file", "");
+
+        add(&reg, Scope::User, EXE, "sh").unwrap();
+        add(&reg, Scope::User, EXE, "zsh").unwrap();
+        remove(&reg, Scope::User, EXE, ".sh").unwrap();
+
+        assert_eq!(
+            reg.value_names(Root::CurrentUser, &open_with),
+            ["sh_auto_file"]
+        );
+        let exts: Vec<String> = list(&reg, Scope::User, EXE)
+            .into_iter()
+            .map(|s| s.ext)
+            .collect();
+        assert_eq!(exts, [".zsh"]);
+    }
+
+    #[test]
+    fn uninstall_removes_what_add_created() {
+        let reg = MemoryRegistry::new();
+        let open_with = format!("{}\\.sh\\OpenWithProgids", CLASSES);
+        reg.write_string(Root::CurrentUser, &open_with, "sh_auto_file", "");
+        add(&reg, Scope::User, EXE, "sh").unwrap();
+
+        for entry in manifest::removals(&reg, Root::CurrentUser) {
+            manifest::remove(&reg, &entry);
+        }
+        assert_eq!(
+            reg.value_names(Root::CurrentUser, &open_with),
+            ["sh_auto_file"]
+        );
+        assert!(
+            !reg.key_exists(Root::ClassesRoot, "Applications\\winbang.exe")
+        );
+    }
+
+    #[test]
+    fn rejects_bad_extensions() {
+        let reg = MemoryRegistry::new();
+        assert!(add(&reg, Scope::User, EXE, "").is_err());
+        assert!(add(&reg, Scope::User, EXE, ".").is_err());
+        assert!(add(&reg, Scope::User, EXE, "tar.gz").is_err());
+        assert!(remove(&reg, Scope::User, EXE, "a b").is_err());
+    }
+
+    #[test]
+    fn emitted_reg_round_trips() {
+        let reg = MemoryRegistry::new();
+        add(&reg, Scope::User, EXE, "sh").unwrap();
+
+        let plan = PlanRegistry::new(&reg);
+        add(&plan, Scope::User, EXE, "py").unwrap();
+        remove(&plan, Scope::User, EXE, "sh").unwrap();
+        assert_eq!(list(&reg, Scope::User, EXE).len(), 1);
+
+        regfile::load(&reg, &plan.to_reg()).unwrap();
+        assert_eq!(list(&reg, Scope::User, EXE), list(&plan, Scope::User, EXE));
+        assert_eq!(
+            reg.read_value(
+                Root::ClassesRoot,
+                "Applications\\winbang.exe\\DefaultIcon",
+                ""
+            ),
+            Some(RegValue::ExpandSz(Icon::Script.resource()))
+        );
+        assert!(!reg.value_exists(
+            Root::ClassesRoot,
+            ".sh\\OpenWithProgids",
+            "Applications\\winbang.exe"
+        ));
+        assert_eq!(
+            manifest::entries(&reg, Root::CurrentUser),
+            manifest::entries(&plan, Root::CurrentUser)
+        );
+    }
+
+    #[test]
+    fn machine_scope_writes_hklm() {
+        let reg = MemoryRegistry::new();
+        add(&reg, Scope::Machine, EXE, "sh").unwrap();
+
+        assert!(reg.value_exists(
+            Root::LocalMachine,
+            &format!("{}\\.sh\\OpenWithProgids", CLASSES),
+            "Applications\\winbang.exe"
+        ));
+        assert!(list(&reg, Scope::User, EXE).is_empty());
+        assert_eq!(list(&reg, Scope::Machine, EXE).len(), 1);
+        assert_eq!(manifest::entries(&reg, Root::CurrentUser), []);
+        assert!(!manifest::entries(&reg, Root::LocalMachine).is_empty());
+    }
+}
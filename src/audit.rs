@@ -0,0 +1,509 @@
+//! The audit log: one JSON object per line for every script launch, when
+//! `[audit]` is enabled. Records are written when Winbang exits, so they
+//! carry the child's exit code and how long the launch took.
+//!
+//! Every record has these fields (`schema` 1):
+//!
+//! * `schema`: Record layout version, bumped when a field changes meaning.
+//! * `time`: When Winbang started, in RFC 3339 UTC.
+//! * `user`: `DOMAIN\name` of the user running Winbang.
+//! * `pid`, `parent`: Winbang's PID, and its parent's `pid` and `name`.
+//! * `context`: `gui` (started from a GUI shell), `console` or `verb`.
+//! * `verb`: The shell verb, in the `verb` context.
+//! * `script`, `sha256`: The script's path and the hash of its contents.
+//! * `association`: The matched `[[file_associations]]` entry's `extension`,
+//!   `shebang_interpreter` and `exec_runtime`.
+//! * `argv`: The command line launched, after redaction.
+//! * `operation`: `execute`, `run`, `edit`, `cancel`, `open`, `fallback`,
+//!   `elevate`, `copy`, `blocked` or `failed`.
+//! * `exit_code`: The launched program's exit code.
+//! * `duration_ms`: Milliseconds from start to exit.
+//!
+//! Fields that don't apply or can't be read are `null`.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+use std::{env, process};
+
+use regex_lite::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, FileAssociation};
+use crate::env_expand::{current_env, expand_env_vars};
+use crate::logging::{rfc3339, rotate};
+use crate::platform::parent_process;
+use crate::trust::sha256_hex;
+use crate::{log_error, log_warn};
+
+/// Version of the record layout, written as `schema`.
+pub(crate) const SCHEMA: u32 = 1;
+
+/// What replaces redacted text in `argv`.
+const REDACTED: &str = "***";
+
+/// `[audit]`: whether and where launches are recorded.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AuditConfig {
+    pub(crate) enabled: bool,
+    /// Log file; `%VAR%` is expanded. Defaults to
+    /// `%APPDATA%\Winbang\audit.jsonl`, and there is none when `APPDATA`
+    /// isn't set.
+    pub(crate) path: Option<String>,
+    /// Size in KiB at which the log is rotated.
+    pub(crate) max_size_kb: u64,
+    /// Rotated logs kept, as `audit.jsonl.1` (newest) and up.
+    pub(crate) keep: usize,
+    /// Regular expressions redacted from every argument: the text of each
+    /// capture group, or the whole match when there are none.
+    pub(crate) redact: Vec<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            enabled: false,
+            path: None,
+            max_size_kb: 10 * 1024,
+            keep: 5,
+            redact: Vec::new(),
+        }
+    }
+}
+
+/// How Winbang was started.
+#[derive(Copy, Clone, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Context {
+    /// From a GUI shell such as Explorer.
+    Gui,
+    /// From a console.
+    Console,
+    /// Through a shell verb (`dispatch-verb`).
+    Verb,
+}
+
+/// What became of the launch.
+#[derive(Copy, Clone, Debug, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Operation {
+    /// Ran without a prompt.
+    Execute,
+    /// The user chose Run at the prompt.
+    Run,
+    /// The user chose Edit at the prompt.
+    Edit,
+    /// The user closed the prompt.
+    Cancel,
+    /// Opened in the editor by `default_operation = "open"`.
+    Open,
+    /// No interpreter matched, so the fallback viewer opened it.
+    Fallback,
+    /// Started elevated by the run-elevated action, without waiting for
+    /// it; no exit code is recorded.
+    Elevate,
+    /// Its command line was copied by the copy-command action; nothing ran.
+    Copy,
+    /// Refused by policy.
+    Blocked,
+    /// Could not be launched.
+    Failed,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Serialize)]
+pub(crate) struct Record {
+    pub(crate) schema: u32,
+    pub(crate) time: String,
+    pub(crate) user: Option<String>,
+    pub(crate) pid: u32,
+    pub(crate) parent: Option<Parent>,
+    pub(crate) context: Context,
+    pub(crate) verb: Option<String>,
+    pub(crate) script: String,
+    pub(crate) sha256: Option<String>,
+    pub(crate) association: Option<Association>,
+    pub(crate) argv: Vec<String>,
+    pub(crate) operation: Option<Operation>,
+    pub(crate) exit_code: Option<i32>,
+    pub(crate) duration_ms: u64,
+}
+
+/// The process that started Winbang.
+#[derive(Debug, Serialize)]
+pub(crate) struct Parent {
+    pub(crate) pid: u32,
+    pub(crate) name: Option<String>,
+}
+
+/// The `[[file_associations]]` entry that matched.
+#[derive(Debug, Serialize)]
+pub(crate) struct Association {
+    pub(crate) extension: Option<String>,
+    pub(crate) shebang_interpreter: Option<String>,
+    pub(crate) exec_runtime: String,
+}
+
+/// An audit log file, rotated by size.
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    redact: Vec<Regex>,
+}
+
+impl AuditLog {
+    /// The log `config` describes, or `None` when it names no file and
+    /// `APPDATA` isn't set. Redaction patterns that don't compile are
+    /// logged and skipped.
+    pub(crate) fn new(config: &AuditConfig) -> Option<AuditLog> {
+        let path = match &config.path {
+            Some(path) => PathBuf::from(expand_env_vars(path, &current_env())),
+            None => {
+                let appdata =
+                    env::var_os("APPDATA").filter(|a| !a.is_empty())?;
+                PathBuf::from(appdata).join("Winbang").join("audit.jsonl")
+            }
+        };
+        let redact = config
+            .redact
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    log_warn!(
+                        "Ignoring audit redact pattern {:?}: {}",
+                        pattern,
+                        e
+                    );
+                    None
+                }
+            })
+            .collect();
+        Some(AuditLog {
+            path,
+            max_bytes: config.max_size_kb.saturating_mul(1024),
+            keep: config.keep,
+            redact,
+        })
+    }
+
+    /// Redact `record`'s arguments and append it, rotating the log first
+    /// when the record would take it over `max_size_kb`.
+    ///
+    /// # Arguments
+    ///
+    /// * `record`: The launch to record.
+    ///
+    /// returns: Result<(), Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// if let Some(log) = AuditLog::new(&config.audit) {
+    ///     log.append(record)?;
+    /// }
+    /// ```
+    pub(crate) fn append(&self, mut record: Record) -> io::Result<()> {
+        record.argv = record.argv.iter().map(|arg| self.redact(arg)).collect();
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            rotate(&self.path, self.keep)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())
+    }
+
+    fn redact(&self, arg: &str) -> String {
+        let mut arg = arg.to_string();
+        for regex in &self.redact {
+            let mut spans: Vec<Range<usize>> = Vec::new();
+            for caps in regex.captures_iter(&arg) {
+                if regex.captures_len() == 1 {
+                    spans.extend(caps.get(0).map(|m| m.range()));
+                } else {
+                    spans.extend(
+                        caps.iter().skip(1).flatten().map(|m| m.range()),
+                    );
+                }
+            }
+            // Nested or overlapping groups are redacted once, as their
+            // union, and from the end so earlier offsets stay valid.
+            spans.sort_by_key(|span| span.start);
+            let mut merged: Vec<Range<usize>> = Vec::new();
+            for span in spans {
+                match merged.last_mut() {
+                    Some(last) if span.start < last.end => {
+                        last.end = last.end.max(span.end);
+                    }
+                    _ => merged.push(span),
+                }
+            }
+            for range in merged.into_iter().rev() {
+                arg.replace_range(range, REDACTED);
+            }
+        }
+        arg
+    }
+}
+
+/// The launch being recorded, from `begin` until `finish`.
+struct Pending {
+    record: Record,
+    started: Instant,
+    log: AuditLog,
+}
+
+static PENDING: Mutex<Option<Pending>> = Mutex::new(None);
+
+fn update(f: impl FnOnce(&mut Record)) {
+    if let Ok(mut pending) = PENDING.lock()
+        && let Some(pending) = pending.as_mut()
+    {
+        f(&mut pending.record);
+    }
+}
+
+/// Start recording a launch of `script`, if `[audit]` is enabled. The
+/// `record_*` functions fill in the rest, and `finish` writes it.
+///
+/// # Arguments
+///
+/// * `config`: Active configuration.
+/// * `context`: How Winbang was started.
+/// * `verb`: The shell verb, in the `verb` context.
+/// * `script`: The script being launched.
+///
+/// returns: ()
+///
+/// # Examples
+///
+/// ```
+/// audit::begin(&config, Context::Console, None, &script.file_path);
+/// // ...
+/// audit::finish();
+/// ```
+pub(crate) fn begin(
+    config: &Config,
+    context: Context,
+    verb: Option<&str>,
+    script: &Path,
+) {
+    if !config.audit.enabled {
+        return;
+    }
+    let Some(log) = AuditLog::new(&config.audit) else {
+        log_error!(
+            "Not auditing {}: [audit] sets no path and %APPDATA% is not set",
+            script.display()
+        );
+        return;
+    };
+    let record = Record {
+        schema: SCHEMA,
+        time: rfc3339(SystemTime::now()),
+        user: current_user(),
+        pid: process::id(),
+        parent: parent_process().map(|(pid, name)| Parent { pid, name }),
+        context,
+        verb: verb.map(str::to_string),
+        script: std::path::absolute(script)
+            .unwrap_or_else(|_| script.to_path_buf())
+            .display()
+            .to_string(),
+        sha256: fs::read(script).ok().map(|bytes| sha256_hex(&bytes)),
+        association: None,
+        argv: Vec::new(),
+        operation: None,
+        exit_code: None,
+        duration_ms: 0,
+    };
+    if let Ok(mut pending) = PENDING.lock() {
+        *pending = Some(Pending {
+            record,
+            started: Instant::now(),
+            log,
+        });
+    }
+}
+
+/// Record the association that matched the script.
+pub(crate) fn record_association(assoc: &FileAssociation) {
+    update(|record| {
+        record.association = Some(Association {
+            extension: assoc.extension.clone(),
+            shebang_interpreter: assoc.shebang_interpreter.clone(),
+            exec_runtime: assoc.exec_runtime.clone(),
+        })
+    });
+}
+
+/// Record the command line about to be launched.
+pub(crate) fn record_command(command: &Command) {
+    update(|record| {
+        record.argv = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+    });
+}
+
+/// Record what became of the launch.
+pub(crate) fn record_operation(operation: Operation) {
+    update(|record| record.operation = Some(operation));
+}
+
+/// Record the launched program's exit code.
+pub(crate) fn record_exit(code: Option<i32>) {
+    update(|record| record.exit_code = code);
+}
+
+/// Write the launch started by `begin`. Failures are logged but don't
+/// change Winbang's exit code.
+pub(crate) fn finish() {
+    let Some(mut pending) = PENDING.lock().ok().and_then(|mut p| p.take())
+    else {
+        return;
+    };
+    pending.record.duration_ms = pending.started.elapsed().as_millis() as u64;
+    if let Err(e) = pending.log.append(pending.record) {
+        log_error!(
+            "Cannot write the audit log {}: {}",
+            pending.log.path.display(),
+            e
+        );
+    }
+}
+
+/// `DOMAIN\name` of the current user, or just the name outside a domain.
+fn current_user() -> Option<String> {
+    let name = env::var("USERNAME").or_else(|_| env::var("USER")).ok()?;
+    match env::var("USERDOMAIN") {
+        Ok(domain) if !domain.is_empty() => {
+            Some(format!("{}\\{}", domain, name))
+        }
+        _ => Some(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::rotated;
+    use std::time::UNIX_EPOCH;
+
+    fn record(argv: &[&str]) -> Record {
+        Record {
+            schema: SCHEMA,
+            time: rfc3339(UNIX_EPOCH),
+            user: Some("CORP\\alice".to_string()),
+            pid: 42,
+            parent: Some(Parent {
+                pid: 7,
+                name: Some("explorer.exe".to_string()),
+            }),
+            context: Context::Gui,
+            verb: None,
+            script: "C:\\Repos\\deploy.sh".to_string(),
+            sha256: None,
+            association: Some(Association {
+                extension: Some("sh".to_string()),
+                shebang_interpreter: Some("bash".to_string()),
+                exec_runtime: "bash".to_string(),
+            }),
+            argv: argv.iter().map(|a| a.to_string()).collect(),
+            operation: Some(Operation::Run),
+            exit_code: Some(0),
+            duration_ms: 15,
+        }
+    }
+
+    #[test]
+    fn redacts_groups_or_whole_matches() {
+        let log = AuditLog::new(&AuditConfig {
+            path: Some("audit.jsonl".to_string()),
+            redact: vec![
+                "(?i)--password=(.*)".to_string(),
+                "ghp_[A-Za-z0-9]+".to_string(),
+                "(".to_string(),
+            ],
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        assert_eq!(log.redact.len(), 2);
+        assert_eq!(log.redact("--PASSWORD=hunter2"), "--PASSWORD=***");
+        assert_eq!(
+            log.redact("token ghp_abc and ghp_def"),
+            "token *** and ***"
+        );
+        assert_eq!(log.redact("deploy.sh"), "deploy.sh");
+    }
+
+    #[test]
+    fn redacts_nested_groups_once() {
+        let log = AuditLog::new(&AuditConfig {
+            path: Some("audit.jsonl".to_string()),
+            redact: vec![
+                "((ü+)b)c".to_string(),
+                "clé=(([^,])([^,]+))".to_string(),
+            ],
+            ..AuditConfig::default()
+        })
+        .unwrap();
+        assert_eq!(log.redact("aüübc züb"), "a***c züb");
+        assert_eq!(log.redact("--clé=sécrét, ok"), "--clé=***, ok");
+    }
+
+    #[test]
+    fn appends_json_lines_and_rotates() {
+        let root = env::temp_dir()
+            .join(format!("winbang-audit-{}", std::process::id()));
+        let path = root.join("audit.jsonl");
+        let log = AuditLog::new(&AuditConfig {
+            enabled: true,
+            path: Some(path.to_string_lossy().to_string()),
+            max_size_kb: 1,
+            keep: 2,
+            redact: vec!["--token=(.*)".to_string()],
+        })
+        .unwrap();
+
+        log.append(record(&["bash", "deploy.sh", "--token=s3cret"]))
+            .unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let line: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(line["schema"], 1);
+        assert_eq!(line["time"], "1970-01-01T00:00:00.000Z");
+        assert_eq!(line["parent"]["name"], "explorer.exe");
+        assert_eq!(line["context"], "gui");
+        assert_eq!(line["association"]["exec_runtime"], "bash");
+        assert_eq!(line["argv"][2], "--token=***");
+        assert_eq!(line["operation"], "run");
+        assert_eq!(line["verb"], serde_json::Value::Null);
+
+        for _ in 0..8 {
+            log.append(record(&["bash", "deploy.sh"])).unwrap();
+        }
+        assert!(fs::metadata(&path).unwrap().len() <= 1024);
+        assert!(rotated(&path, 1).exists());
+        assert!(rotated(&path, 2).exists());
+        assert!(!rotated(&path, 3).exists());
+        let older = fs::read_to_string(rotated(&path, 1)).unwrap();
+        assert!(older.lines().all(|l| l.starts_with("{\"schema\":1,")));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,6 +1,10 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
+use crate::install::Scope;
+use crate::logging::Level;
+use crate::setup::Icon;
+
 #[derive(Parser)]
 #[command(
     name = "winbang",
@@ -12,6 +16,26 @@ pub struct Cli {
     #[arg(long)]
     pub reinstall_verbs: bool,
 
+    /// Write the registry changes to this `.reg` file instead of applying
+    /// them. Applies to `install`, `--reinstall-verbs`, `assoc`, `setup` and
+    /// `uninstall`.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub emit_reg: Option<PathBuf>,
+
+    /// Register for the current user or, from an elevated prompt, for every
+    /// user. Applies to the same commands as `--emit-reg`.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    pub scope: Scope,
+
+    /// Log messages at this level and above. Overrides `WINBANG_LOG`;
+    /// defaults to `warn`.
+    #[arg(long, global = true, value_enum, value_name = "LEVEL")]
+    pub log_level: Option<Level>,
+
+    /// Log to this file instead of stderr. Overrides `WINBANG_LOG_FILE`.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Option<Command>,
 }
@@ -30,8 +54,139 @@ pub enum Command {
         extras: Vec<String>,
     },
 
+    /// (Internal) Run a script and keep its console open until Enter is
+    /// pressed. Started elevated by the run-elevated verb action.
+    #[command(hide = true)]
+    KeepOpen {
+        #[arg(long)]
+        file: PathBuf,
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        extras: Vec<String>,
+    },
+
+    /// Register Winbang's shell verbs, filling in any that are missing.
+    Install,
+
+    /// Manage file associations with Winbang.
+    Assoc {
+        #[command(subcommand)]
+        action: AssocAction,
+    },
+
+    /// Set up Windows to open files with Winbang.
+    Setup {
+        #[command(subcommand)]
+        target: SetupTarget,
+    },
+
+    /// Show the settings in effect, where each came from, and how a script
+    /// would be handled.
+    Explain {
+        /// Script to explain.
+        script: Option<String>,
+    },
+
+    /// Manage the scripts trusted to run without prompting.
+    Trust {
+        #[command(subcommand)]
+        action: TrustAction,
+    },
+
+    /// Remove every registry key and value Winbang created.
+    Uninstall {
+        /// List what would be removed without removing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// Any non-subcommand first argument is treated as a script path; trailing
     /// arguments are passed to the resolved interpreter.
     #[command(external_subcommand)]
     Script(Vec<String>),
 }
+
+#[derive(Subcommand)]
+pub enum AssocAction {
+    /// Add Winbang to the "Open with" list of each extension.
+    Add {
+        #[arg(required = true)]
+        extensions: Vec<String>,
+    },
+    /// Remove Winbang from the "Open with" list of each extension.
+    Remove {
+        #[arg(required = true)]
+        extensions: Vec<String>,
+    },
+    /// List the extensions associated with Winbang.
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum TrustAction {
+    /// List trusted scripts with the SHA-256 they were trusted at.
+    List,
+    /// Trust scripts as they are now.
+    Add {
+        #[arg(required = true)]
+        scripts: Vec<PathBuf>,
+    },
+    /// Stop trusting scripts.
+    Revoke {
+        #[arg(required = true)]
+        scripts: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SetupTarget {
+    /// Open files without an extension with Winbang.
+    Extensionless {
+        /// Icon shown for extensionless files.
+        #[arg(long, value_enum, default_value_t)]
+        icon: Icon,
+    },
+}
+
+impl Cli {
+    /// Whether this run changes Winbang's registration rather than running
+    /// a script or verb.
+    pub fn manages_registry(&self) -> bool {
+        self.reinstall_verbs
+            || matches!(
+                self.command,
+                Some(
+                    Command::Install
+                        | Command::Assoc { .. }
+                        | Command::Setup { .. }
+                        | Command::Uninstall { .. }
+                )
+            )
+    }
+
+    /// Whether this run writes machine-wide registry entries and so needs
+    /// an elevated prompt. Emitting a `.reg` file and read-only commands
+    /// don't.
+    pub fn writes_machine_scope(&self) -> bool {
+        self.scope == Scope::Machine
+            && self.emit_reg.is_none()
+            && self.manages_registry()
+            && !matches!(
+                self.command,
+                Some(
+                    Command::Assoc {
+                        action: AssocAction::List
+                    } | Command::Uninstall { dry_run: true }
+                )
+            )
+    }
+
+    /// Whether the verbs Winbang registers on startup must be skipped:
+    /// the run either leaves the registry alone or does it itself.
+    pub fn skips_verb_sync(&self) -> bool {
+        self.emit_reg.is_some()
+            || matches!(
+                self.command,
+                Some(Command::Install | Command::Uninstall { .. })
+            )
+    }
+}
@@ -0,0 +1,425 @@
+/// A command line split into the program to run and its arguments.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ParsedCommand {
+    pub(crate) program: String,
+    pub(crate) args: Vec<String>,
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// Split a full command line the way `CommandLineToArgvW` does.
+///
+/// The program name (`argv[0]`) follows its own rule: a leading quote runs to
+/// the next quote with no escaping, otherwise it ends at the first blank.
+/// Everything after it is split with `split_args`.
+///
+/// # Arguments
+///
+/// * `line`: The command line.
+///
+/// returns: Vec<String>
+///
+/// # Examples
+///
+/// ```
+/// let argv = split_command_line(r#""C:\Program Files\App\app.exe" "%1""#);
+/// assert_eq!(argv[0], r"C:\Program Files\App\app.exe");
+/// ```
+pub(crate) fn split_command_line(line: &str) -> Vec<String> {
+    if line.is_empty() {
+        return Vec::new();
+    }
+
+    let (program, rest) = if let Some(quoted) = line.strip_prefix('"') {
+        match quoted.find('"') {
+            Some(end) => (&quoted[..end], &quoted[end + 1..]),
+            None => (quoted, ""),
+        }
+    } else {
+        match line.find(is_blank) {
+            Some(end) => (&line[..end], &line[end..]),
+            None => (line, ""),
+        }
+    };
+
+    let mut argv = vec![program.to_string()];
+    argv.extend(split_args(rest));
+    argv
+}
+
+/// Split the argument portion of a command line with the Microsoft C runtime
+/// rules that `CommandLineToArgvW` applies after `argv[0]`:
+///
+/// * blanks outside quotes separate arguments;
+/// * `2n` backslashes before a quote become `n` backslashes and the quote
+///   toggles quoting; `2n+1` backslashes become `n` and a literal quote;
+/// * backslashes not followed by a quote are literal;
+/// * `""` inside a quoted run is a literal quote.
+///
+/// # Arguments
+///
+/// * `args`: The argument portion of a command line.
+///
+/// returns: Vec<String>
+///
+/// # Examples
+///
+/// ```
+/// let args = split_args(r#""C:\dir\\" a\"b"#);
+/// assert_eq!(args, vec![r"C:\dir\", "a\"b"]);
+/// ```
+pub(crate) fn split_args(args: &str) -> Vec<String> {
+    let chars: Vec<char> = args.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < chars.len() && is_blank(chars[i]) {
+            i += 1;
+        }
+        if i == chars.len() {
+            return out;
+        }
+
+        let mut arg = String::new();
+        let mut in_quotes = false;
+        while i < chars.len() {
+            match chars[i] {
+                '\\' => {
+                    let run =
+                        chars[i..].iter().take_while(|&&c| c == '\\').count();
+                    i += run;
+                    if chars.get(i) == Some(&'"') {
+                        arg.extend(std::iter::repeat_n('\\', run / 2));
+                        if run % 2 == 1 {
+                            arg.push('"');
+                            i += 1;
+                        }
+                    } else {
+                        arg.extend(std::iter::repeat_n('\\', run));
+                    }
+                }
+                '"' if in_quotes && chars.get(i + 1) == Some(&'"') => {
+                    arg.push('"');
+                    i += 2;
+                }
+                '"' => {
+                    in_quotes = !in_quotes;
+                    i += 1;
+                }
+                c if is_blank(c) && !in_quotes => break,
+                c => {
+                    arg.push(c);
+                    i += 1;
+                }
+            }
+        }
+        out.push(arg);
+    }
+}
+
+/// Whether the last path component has an extension.
+fn has_extension(path: &str) -> bool {
+    let name = path.rsplit(['\\', '/']).next().unwrap_or(path);
+    name.rfind('.').is_some_and(|idx| idx > 0)
+}
+
+/// Parse a registry command string into a program and arguments.
+///
+/// A quoted program is taken literally. An unquoted program may contain
+/// spaces (`C:\Program Files\App\app.exe %1`), so, like `CreateProcess`, each
+/// blank-delimited prefix is tried in turn, shortest first, with `.exe`
+/// appended when the prefix has no extension. The first prefix for which
+/// `exists` returns true is the program. If none exist, the program ends at
+/// the first blank. Leading blanks are ignored.
+///
+/// # Arguments
+///
+/// * `line`: The command string.
+/// * `exists`: Whether a candidate program path is an existing file.
+///
+/// returns: Option<ParsedCommand>
+///
+/// # Examples
+///
+/// ```
+/// let parsed = parse_command(r"C:\Program Files\App\app.exe x", |p| {
+///     Path::new(p).is_file()
+/// });
+/// ```
+pub(crate) fn parse_command(
+    line: &str,
+    exists: impl Fn(&str) -> bool,
+) -> Option<ParsedCommand> {
+    let line = line.trim_start_matches(is_blank);
+    if line.is_empty() {
+        return None;
+    }
+
+    if !line.starts_with('"') {
+        let blanks = line.match_indices(is_blank).map(|(idx, _)| idx);
+        for end in blanks.chain(std::iter::once(line.len())) {
+            let candidate = &line[..end];
+            let probe = if has_extension(candidate) {
+                candidate.to_string()
+            } else {
+                format!("{}.exe", candidate)
+            };
+            if exists(&probe) {
+                return Some(ParsedCommand {
+                    program: probe,
+                    args: split_args(&line[end..]),
+                });
+            }
+        }
+    }
+
+    let mut argv = split_command_line(line).into_iter();
+    Some(ParsedCommand {
+        program: argv.next()?,
+        args: argv.collect(),
+    })
+}
+
+/// Quote one argument so `split_args` (and every program using the Microsoft
+/// C runtime rules) reads it back unchanged. Arguments without blanks or
+/// quotes are returned as-is.
+///
+/// # Arguments
+///
+/// * `arg`: The argument.
+///
+/// returns: String
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(quote_arg(r"C:\a b\"), r#""C:\a b\\""#);
+/// ```
+pub(crate) fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    format!("\"{}\"", escape_quoted(arg))
+}
+
+/// Escape text placed inside an existing quoted run: quotes become `\"` and
+/// backslashes that end up before a quote (including the closing one) are
+/// doubled.
+///
+/// # Arguments
+///
+/// * `text`: The text to embed.
+///
+/// returns: String
+///
+/// # Examples
+///
+/// ```
+/// let inner = escape_quoted("say \"hi\"");
+/// assert_eq!(inner, r#"say \"hi\""#);
+/// ```
+pub(crate) fn escape_quoted(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut backslashes = 0;
+    for c in text.chars() {
+        match c {
+            '\\' => {
+                backslashes += 1;
+                continue;
+            }
+            '"' => {
+                out.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                out.push('"');
+            }
+            _ => {
+                out.extend(std::iter::repeat_n('\\', backslashes));
+                out.push(c);
+            }
+        }
+        backslashes = 0;
+    }
+    out.extend(std::iter::repeat_n('\\', backslashes * 2));
+    out
+}
+
+/// Whether a registry command line runs the exe named `exe_name`, by file
+/// name only. A program without an extension also matches `<name>.exe`.
+///
+/// # Arguments
+///
+/// * `command`: The command string.
+/// * `exe_name`: File name of the exe, e.g. `winbang.exe`.
+///
+/// returns: bool
+///
+/// # Examples
+///
+/// ```
+/// assert!(launches(r#""C:\Tools\WinBang.exe" "%1""#, "winbang.exe"));
+/// ```
+pub(crate) fn launches(command: &str, exe_name: &str) -> bool {
+    let Some(program) =
+        split_command_line(command.trim_start()).into_iter().next()
+    else {
+        return false;
+    };
+    let name = program.rsplit(['\\', '/']).next().unwrap_or(&program);
+    name.eq_ignore_ascii_case(exe_name)
+        || format!("{}.exe", name).eq_ignore_ascii_case(exe_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn quoted_program_keeps_backslashes() {
+        assert_eq!(
+            split_command_line(r#""C:\Program Files\App\app.exe" "%1""#),
+            strings(&[r"C:\Program Files\App\app.exe", "%1"])
+        );
+    }
+
+    #[test]
+    fn program_backslash_before_quote_is_not_an_escape() {
+        assert_eq!(
+            split_command_line(r#""C:\dir\"x y"#),
+            strings(&[r"C:\dir\", "x", "y"])
+        );
+    }
+
+    #[test]
+    fn unquoted_program_ends_at_blank() {
+        assert_eq!(
+            split_command_line("notepad.exe\t/p  file.txt"),
+            strings(&["notepad.exe", "/p", "file.txt"])
+        );
+    }
+
+    #[test]
+    fn args_backslash_rules() {
+        assert_eq!(split_args(r#"a\\b"#), strings(&[r"a\\b"]));
+        assert_eq!(split_args(r#"a\"b"#), strings(&[r#"a"b"#]));
+        assert_eq!(split_args(r#"a\\"b c""#), strings(&[r"a\b c"]));
+        assert_eq!(split_args(r#"a\\\"b"#), strings(&[r#"a\"b"#]));
+        assert_eq!(
+            split_args(r#""C:\dir with space\\" next"#),
+            strings(&[r"C:\dir with space\", "next"])
+        );
+    }
+
+    #[test]
+    fn args_quote_rules() {
+        assert_eq!(split_args(r#""a""b""#), strings(&[r#"a"b"#]));
+        assert_eq!(split_args(r#""""#), strings(&[""]));
+        assert_eq!(split_args(r#"a"b c"d"#), strings(&["ab cd"]));
+        assert_eq!(
+            split_args(r#""unterminated x"#),
+            strings(&["unterminated x"])
+        );
+        assert_eq!(split_args("   "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn unquoted_program_with_spaces_uses_search_rules() {
+        let exists = |p: &str| p == r"C:\Program Files\App\app.exe";
+        assert_eq!(
+            parse_command(r#"C:\Program Files\App\app.exe "%1" /x"#, exists),
+            Some(ParsedCommand {
+                program: r"C:\Program Files\App\app.exe".to_string(),
+                args: strings(&["%1", "/x"]),
+            })
+        );
+    }
+
+    #[test]
+    fn search_appends_exe_and_prefers_shortest() {
+        let exists =
+            |p: &str| p == r"C:\Program.exe" || p == r"C:\Program Files\a.exe";
+        assert_eq!(
+            parse_command(r"C:\Program Files\a.exe x", exists).unwrap(),
+            ParsedCommand {
+                program: r"C:\Program.exe".to_string(),
+                args: strings(&[r"Files\a.exe", "x"]),
+            }
+        );
+
+        let exists = |p: &str| p == r"C:\My Tools\run.exe";
+        assert_eq!(
+            parse_command(r"C:\My Tools\run %1", exists)
+                .unwrap()
+                .program,
+            r"C:\My Tools\run.exe"
+        );
+    }
+
+    #[test]
+    fn search_falls_back_to_first_blank() {
+        assert_eq!(
+            parse_command(r"C:\Program Files\App\app.exe %1", |_| false),
+            Some(ParsedCommand {
+                program: r"C:\Program".to_string(),
+                args: strings(&[r"Files\App\app.exe", "%1"]),
+            })
+        );
+    }
+
+    #[test]
+    fn quoted_program_skips_search() {
+        let parsed =
+            parse_command(r#"  "C:\a b\c.exe" "x y""#, |_| true).unwrap();
+        assert_eq!(parsed.program, r"C:\a b\c.exe");
+        assert_eq!(parsed.args, strings(&["x y"]));
+    }
+
+    #[test]
+    fn empty_command() {
+        assert_eq!(parse_command("  ", |_| true), None);
+        assert!(split_command_line("").is_empty());
+    }
+
+    #[test]
+    fn quote_arg_round_trips() {
+        let cases = [
+            "plain",
+            "",
+            "with space",
+            r"C:\dir with space\",
+            r#"say "hi""#,
+            r#"back\"slash"#,
+            r"a\\b",
+            "tab\there",
+        ];
+        for case in cases {
+            assert_eq!(
+                split_args(&quote_arg(case)),
+                strings(&[case]),
+                "{case}"
+            );
+        }
+        assert_eq!(quote_arg("plain"), "plain");
+        assert_eq!(quote_arg(r"C:\a b\"), r#""C:\a b\\""#);
+    }
+
+    #[test]
+    fn escape_quoted_inside_template_quotes() {
+        let line = format!("\"{}\" next", escape_quoted(r#"C:\x\ "y"\"#));
+        assert_eq!(split_args(&line), strings(&[r#"C:\x\ "y"\"#, "next"]));
+    }
+
+    #[test]
+    fn launches_matches_by_file_name() {
+        assert!(launches(r#""C:\Tools\WinBang.exe" "%1""#, "winbang.exe"));
+        assert!(launches("  winbang %1", "winbang.exe"));
+        assert!(!launches(r"C:\winbang.exe.bak %1", "winbang.exe"));
+        assert!(!launches(r#""C:\winbang\other.exe""#, "winbang.exe"));
+        assert!(!launches("", "winbang.exe"));
+    }
+}
@@ -1,8 +1,13 @@
-use crate::log_debug;
+use crate::audit::AuditConfig;
+use crate::error::WinbangError;
+use crate::group_policy::GroupPolicy;
 use crate::platform::resolve_executable;
+use crate::policy::ExecPolicy;
+use crate::{log_debug, log_info};
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
-use std::{env, fs};
+use std::{env, fmt, fs, io};
 
 #[derive(Debug, Deserialize)]
 pub(crate) struct Config {
@@ -11,9 +16,60 @@ pub(crate) struct Config {
     pub(crate) default: Option<DefaultHandler>,
     pub(crate) default_large: Option<DefaultLargeHandler>,
     pub(crate) file_associations: Option<Vec<FileAssociation>>,
+    /// Extra context-menu verbs registered for every script
+    /// (`[context_menu.<verb>]`).
+    pub(crate) context_menu: Option<BTreeMap<String, VerbConfig>>,
+    /// Globs of locations whose scripts run without prompting.
+    #[serde(default)]
+    pub(crate) trusted_paths: Vec<String>,
+    /// Globs of locations whose scripts always prompt, even when trusted.
+    #[serde(default)]
+    pub(crate) untrusted_paths: Vec<String>,
+    /// Deny- and allow-lists checked before every launch (`[policy]`).
+    #[serde(default)]
+    pub(crate) policy: ExecPolicy,
+    /// Where launches are recorded (`[audit]`).
+    #[serde(default)]
+    pub(crate) audit: AuditConfig,
+    /// The `%PROGRAMDATA%` config's `[policy]` and its path, when another
+    /// config is in use. It applies regardless.
+    #[serde(skip)]
+    pub(crate) machine_policy: Option<(PathBuf, ExecPolicy)>,
+    /// Group Policy in effect, which overrides everything above. Never read
+    /// from the file.
+    #[serde(skip)]
+    pub(crate) group_policy: GroupPolicy,
+    /// The file this config was read from.
+    #[serde(skip)]
+    pub(crate) source: Option<PathBuf>,
 }
 
-#[derive(Copy, Clone, Debug, Deserialize)]
+impl Config {
+    /// Look up a `[context_menu]` entry by verb name, case-insensitively.
+    pub(crate) fn context_menu_verb(&self, name: &str) -> Option<&VerbConfig> {
+        self.context_menu
+            .as_ref()?
+            .iter()
+            .find(|(verb, _)| verb.eq_ignore_ascii_case(name))
+            .map(|(_, config)| config)
+    }
+
+    /// Every `[policy]` in effect, with where each is set.
+    pub(crate) fn exec_policies(&self) -> Vec<(&ExecPolicy, String)> {
+        let described = |path: Option<&PathBuf>| match path {
+            Some(path) => format!("[policy] in {}", path.display()),
+            None => "[policy]".to_string(),
+        };
+        let mut policies = Vec::new();
+        if let Some((path, policy)) = &self.machine_policy {
+            policies.push((policy, described(Some(path))));
+        }
+        policies.push((&self.policy, described(self.source.as_ref())));
+        policies
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub(crate) enum DefaultOperation {
     Prompt,
@@ -21,6 +77,62 @@ pub(crate) enum DefaultOperation {
     Execute,
 }
 
+impl fmt::Display for DefaultOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DefaultOperation::Prompt => "prompt",
+            DefaultOperation::Open => "open",
+            DefaultOperation::Execute => "execute",
+        })
+    }
+}
+
+/// Where an effective setting came from, as `winbang explain` reports it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Origin {
+    /// A Group Policy value, by name.
+    Policy(&'static str),
+    /// The script's Mark of the Web, by zone name.
+    MarkOfTheWeb(&'static str),
+    /// A `trusted_paths` rule.
+    TrustedPath(String),
+    /// An `untrusted_paths` rule.
+    UntrustedPath(String),
+    /// `require_signature_to_execute`, in the `[policy]` described.
+    Unsigned(String),
+    /// The script's `[[file_associations]]` entry.
+    Association,
+    /// A top-level setting in the config file.
+    Config,
+    /// Winbang's built-in default.
+    Default,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Policy(name) => {
+                write!(f, "policy {}", crate::group_policy::value_path(name))
+            }
+            Origin::MarkOfTheWeb(zone) => {
+                write!(f, "downloaded from the {} zone", zone)
+            }
+            Origin::TrustedPath(rule) => {
+                write!(f, "trusted_paths rule \"{}\"", rule)
+            }
+            Origin::UntrustedPath(rule) => {
+                write!(f, "untrusted_paths rule \"{}\"", rule)
+            }
+            Origin::Unsigned(source) => {
+                write!(f, "no valid signature, required by {}", source)
+            }
+            Origin::Association => f.write_str("file association"),
+            Origin::Config => f.write_str("config file"),
+            Origin::Default => f.write_str("default"),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct DefaultHandler {
     pub(crate) view_runtime: String,
@@ -35,6 +147,7 @@ pub(crate) struct DefaultLargeHandler {
 }
 
 #[derive(Clone, Debug, Deserialize)]
+#[serde(from = "RawFileAssociation")]
 pub(crate) struct FileAssociation {
     pub(crate) shebang_interpreter: Option<String>,
     pub(crate) exec_runtime: String,
@@ -42,81 +155,255 @@ pub(crate) struct FileAssociation {
     pub(crate) view_runtime: Option<String>,
     pub(crate) extension: Option<String>,
     pub(crate) default_operation: Option<DefaultOperation>,
-    pub(crate) verb_edit: Option<String>,
-    pub(crate) verb_print: Option<String>,
-    pub(crate) verb_printto: Option<String>,
-    pub(crate) verb_runas: Option<String>,
-    pub(crate) verb_uiaccess: Option<String>,
+    /// Shell verbs by name (`[file_associations.verbs]`).
+    pub(crate) verbs: BTreeMap<String, VerbConfig>,
+}
+
+impl FileAssociation {
+    /// Look up a verb by name. Verb names are case-insensitive, like the
+    /// registry keys they correspond to.
+    pub(crate) fn verb(&self, name: &str) -> Option<&VerbConfig> {
+        self.verbs
+            .iter()
+            .find(|(verb, _)| verb.eq_ignore_ascii_case(name))
+            .map(|(_, config)| config)
+    }
+}
+
+/// `FileAssociation` as written in the config file, including the legacy
+/// `verb_*` fields that predate `verbs`.
+#[derive(Deserialize)]
+struct RawFileAssociation {
+    shebang_interpreter: Option<String>,
+    exec_runtime: String,
+    exec_argv_override: Option<String>,
+    view_runtime: Option<String>,
+    extension: Option<String>,
+    default_operation: Option<DefaultOperation>,
+    #[serde(default)]
+    verbs: BTreeMap<String, VerbConfig>,
+    verb_edit: Option<String>,
+    verb_print: Option<String>,
+    verb_printto: Option<String>,
+    verb_runas: Option<String>,
+    verb_uiaccess: Option<String>,
+}
+
+impl From<RawFileAssociation> for FileAssociation {
+    fn from(raw: RawFileAssociation) -> Self {
+        let mut verbs = raw.verbs;
+        let legacy = [
+            ("edit", raw.verb_edit),
+            ("print", raw.verb_print),
+            ("printto", raw.verb_printto),
+            ("runas", raw.verb_runas),
+            ("UIAccess", raw.verb_uiaccess),
+        ];
+        for (name, template) in legacy {
+            let Some(template) = template else { continue };
+            // An entry under `verbs` wins over its legacy alias.
+            if !verbs.keys().any(|verb| verb.eq_ignore_ascii_case(name)) {
+                verbs.insert(name.to_string(), VerbConfig::from(template));
+            }
+        }
+
+        FileAssociation {
+            shebang_interpreter: raw.shebang_interpreter,
+            exec_runtime: raw.exec_runtime,
+            exec_argv_override: raw.exec_argv_override,
+            view_runtime: raw.view_runtime,
+            extension: raw.extension,
+            default_operation: raw.default_operation,
+            verbs,
+        }
+    }
+}
+
+/// One `[file_associations.verbs]` or `[context_menu]` entry: either a bare
+/// command template or a table with display options.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(from = "RawVerbConfig")]
+pub(crate) struct VerbConfig {
+    /// Command template, with the same `%` tokens as registry verbs. When
+    /// absent, the verb is resolved like any other pass-through verb.
+    pub(crate) template: Option<String>,
+    /// Built-in Winbang action to run instead of a template.
+    pub(crate) action: Option<VerbAction>,
+    /// Context-menu text (`(Default)` on the verb key).
+    pub(crate) label: Option<String>,
+    /// Context-menu icon, as an `Icon` value (`path,index`).
+    pub(crate) icon: Option<String>,
+    /// Placement in the context menu.
+    pub(crate) position: Option<VerbPosition>,
+    /// Only show the verb when Shift is held (the `Extended` value).
+    pub(crate) extended: bool,
+    /// What to do when no template or registry handler is found.
+    pub(crate) fallback: VerbFallback,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawVerbConfig {
+    Template(String),
+    Table {
+        template: Option<String>,
+        action: Option<VerbAction>,
+        label: Option<String>,
+        icon: Option<String>,
+        position: Option<VerbPosition>,
+        #[serde(default)]
+        extended: bool,
+        #[serde(default)]
+        fallback: VerbFallback,
+    },
+}
+
+impl From<RawVerbConfig> for VerbConfig {
+    fn from(raw: RawVerbConfig) -> Self {
+        match raw {
+            RawVerbConfig::Template(template) => VerbConfig::from(template),
+            RawVerbConfig::Table {
+                template,
+                action,
+                label,
+                icon,
+                position,
+                extended,
+                fallback,
+            } => VerbConfig {
+                template,
+                action,
+                label,
+                icon,
+                position,
+                extended,
+                fallback,
+            },
+        }
+    }
+}
+
+impl From<String> for VerbConfig {
+    fn from(template: String) -> Self {
+        VerbConfig {
+            template: Some(template),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum VerbPosition {
+    Top,
+    Bottom,
+}
+
+impl VerbPosition {
+    /// Data for the verb key's `Position` value.
+    pub(crate) fn as_registry_str(self) -> &'static str {
+        match self {
+            VerbPosition::Top => "Top",
+            VerbPosition::Bottom => "Bottom",
+        }
+    }
+}
+
+/// Winbang-internal verb actions, for entries with `action = "..."`.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum VerbAction {
+    /// Ask for arguments on the console, then run the script.
+    RunWithArgs,
+    /// Run the script inside WSL.
+    RunInWsl,
+    /// Run the script elevated in a console that stays open.
+    RunElevated,
+    /// Copy the command Winbang would run to the clipboard.
+    CopyCommand,
+}
+
+/// Where a verb goes when neither config nor the registry has a command.
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum VerbFallback {
+    /// Use the first `open` command along the handler chain.
+    #[default]
+    Open,
+    /// Open the file in the association's `view_runtime` (or the default
+    /// viewer).
+    Editor,
+    /// Report the verb as unresolved.
+    None,
 }
 
 /// Find the configuration file in the current directory, PROGRAMDATA, or APPDATA.
+/// Group Policy can rule out the current directory and APPDATA.
 ///
 /// # Arguments
 ///
-/// * None
+/// * `policy`: Group Policy in effect.
 ///
 /// returns: Result<(), Error>
 ///
 /// # Examples
 ///
 /// ```
-/// let config_path = find_config_path().unwrap_or_else(|| PathBuf::from("config.toml"));
+/// let config_path = find_config_path(&policy).unwrap_or_else(|| PathBuf::from("config.toml"));
 /// ```
-pub(crate) fn find_config_path() -> Option<PathBuf> {
+pub(crate) fn find_config_path(policy: &GroupPolicy) -> Option<PathBuf> {
     let current = Path::new("config.toml").to_path_buf();
     let mut selected: Option<PathBuf> = None;
 
-    if current.exists() {
+    if policy.disable_project_config {
+        log_debug!("Project config disabled by policy");
+    } else if current.exists() {
         selected = Some(current.clone());
 
-        log_debug!(&format!(
-            "Found config in current directory: {:?}",
-            current
-        ));
+        log_debug!("Found config in current directory: {:?}", current);
     }
 
-    if selected.is_none() {
-        if let Ok(programdata) = env::var("PROGRAMDATA") {
-            let pd_config =
-                Path::new(&programdata).join("Winbang").join("config.toml");
-            if pd_config.exists() {
-                selected = Some(pd_config.clone());
+    if selected.is_none()
+        && let Ok(programdata) = env::var("PROGRAMDATA")
+    {
+        let pd_config =
+            Path::new(&programdata).join("Winbang").join("config.toml");
+        if pd_config.exists() {
+            selected = Some(pd_config.clone());
 
-                log_debug!(&format!(
-                    "Found config in PROGRAMDATA: {:?}",
-                    pd_config
-                ));
-            }
+            log_debug!("Found config in PROGRAMDATA: {:?}", pd_config);
         }
     }
 
     // Regardless of earlier matches, APPDATA may override if explicitly allowed
-    if let Ok(appdata) = env::var("APPDATA") {
+    if policy.disable_user_config {
+        log_debug!("User config disabled by policy");
+    } else if let Ok(appdata) = env::var("APPDATA") {
         let ad_config = Path::new(&appdata).join("Winbang").join("config.toml");
-        if ad_config.exists() {
-            if let Ok(programdata) = env::var("PROGRAMDATA") {
-                let pd_config =
-                    Path::new(&programdata).join("Winbang").join("config.toml");
-                if let Ok(cfg_str) = fs::read_to_string(&pd_config) {
-                    if let Ok(cfg) = toml::from_str::<toml::Value>(&cfg_str) {
-                        let allow_user = cfg
-                            .get("allow_user_config")
-                            .and_then(|v| v.as_bool())
-                            .unwrap_or(false);
-
-                        if allow_user {
-                            log_debug!(&format!(
-                                "Overriding with APPDATA config: {:?}",
-                                ad_config
-                            ));
-
-                            return Some(ad_config);
-                        } else if cfg!(debug_assertions) {
-                            log_debug!(
-                                "APPDATA config found but disallowed by PROGRAMDATA setting"
-                            );
-                        }
-                    }
+        if ad_config.exists()
+            && let Ok(programdata) = env::var("PROGRAMDATA")
+        {
+            let pd_config =
+                Path::new(&programdata).join("Winbang").join("config.toml");
+            if let Ok(cfg_str) = fs::read_to_string(&pd_config)
+                && let Ok(cfg) = toml::from_str::<toml::Value>(&cfg_str)
+            {
+                let allow_user = cfg
+                    .get("allow_user_config")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+
+                if allow_user {
+                    log_debug!(
+                        "Overriding with APPDATA config: {:?}",
+                        ad_config
+                    );
+
+                    return Some(ad_config);
+                } else {
+                    log_info!(
+                        "APPDATA config found but disallowed by PROGRAMDATA setting"
+                    );
                 }
             }
         }
@@ -125,22 +412,68 @@ pub(crate) fn find_config_path() -> Option<PathBuf> {
     selected
 }
 
-/// Load the configuration from a file.
+/// Load the `[policy]` of the `%PROGRAMDATA%` config, unless that is the
+/// config in use anyway. A central policy applies even when users may bring
+/// their own config, so one that can't be read or parsed is an error rather
+/// than no policy.
+///
+/// # Arguments
+///
+/// * `selected`: The config file in use.
+///
+/// returns: Result<Option<(PathBuf, ExecPolicy)>, WinbangError>
+///
+/// # Examples
+///
+/// ```
+/// config.machine_policy = load_machine_policy(&config_path)?;
+/// ```
+pub(crate) fn load_machine_policy(
+    selected: &Path,
+) -> Result<Option<(PathBuf, ExecPolicy)>, WinbangError> {
+    let Ok(programdata) = env::var("PROGRAMDATA") else {
+        return Ok(None);
+    };
+    let path = Path::new(&programdata).join("Winbang").join("config.toml");
+    let Ok(machine) = fs::canonicalize(&path) else {
+        return Ok(None);
+    };
+    if fs::canonicalize(selected).is_ok_and(|selected| selected == machine) {
+        return Ok(None);
+    }
+    let invalid = |reason: String| WinbangError::Config {
+        path: path.clone(),
+        reason,
+    };
+    let text = fs::read_to_string(&path).map_err(|e| invalid(e.to_string()))?;
+    let table = toml::from_str::<toml::Table>(&text)
+        .map_err(|e| invalid(e.to_string().trim_end().to_string()))?;
+    let Some(policy) = table.get("policy") else {
+        return Ok(None);
+    };
+    let policy = policy.clone().try_into::<ExecPolicy>().map_err(|e| {
+        invalid(format!("[policy]: {}", e.to_string().trim_end()))
+    })?;
+    Ok(Some((path, policy)))
+}
+
+/// Load the configuration from a file, or the defaults when there is none.
 ///
 /// # Arguments
 ///
 /// * `config_path`: Path to the configuration file.
 ///
-/// returns: Config
+/// returns: Result<Config, WinbangError> — `WinbangError::Config` when the
+/// file exists but can't be read or parsed.
 ///
 /// # Examples
 ///
 /// ```
 /// let config_path = Path::new("config.toml");
-/// let config = load_config(config_path);
+/// let config = load_config(config_path)?;
 /// ```
-pub(crate) fn load_config(config_path: &Path) -> Config {
-    log_debug!(&format!("Loading config from: {:?}", config_path));
+pub(crate) fn load_config(config_path: &Path) -> Result<Config, WinbangError> {
+    log_debug!("Loading config from: {:?}", config_path);
     let default_config = Config {
         gui_shells: Some(vec!["explorer.exe".to_string()]),
         default_operation: Some(DefaultOperation::Prompt),
@@ -161,11 +494,7 @@ pub(crate) fn load_config(config_path: &Path) -> Config {
                 view_runtime: None,
                 default_operation: Option::from(DefaultOperation::Prompt),
                 exec_argv_override: None,
-                verb_edit: None,
-                verb_print: None,
-                verb_printto: None,
-                verb_runas: None,
-                verb_uiaccess: None,
+                verbs: BTreeMap::new(),
             },
             FileAssociation {
                 shebang_interpreter: Option::from("python".to_string()),
@@ -174,11 +503,7 @@ pub(crate) fn load_config(config_path: &Path) -> Config {
                 view_runtime: None,
                 default_operation: Option::from(DefaultOperation::Prompt),
                 exec_argv_override: None,
-                verb_edit: None,
-                verb_print: None,
-                verb_printto: None,
-                verb_runas: None,
-                verb_uiaccess: None,
+                verbs: BTreeMap::new(),
             },
             FileAssociation {
                 shebang_interpreter: if resolve_executable("deno").is_some() {
@@ -199,11 +524,7 @@ pub(crate) fn load_config(config_path: &Path) -> Config {
                 view_runtime: None,
                 default_operation: Option::from(DefaultOperation::Prompt),
                 exec_argv_override: None,
-                verb_edit: None,
-                verb_print: None,
-                verb_printto: None,
-                verb_runas: None,
-                verb_uiaccess: None,
+                verbs: BTreeMap::new(),
             },
             FileAssociation {
                 shebang_interpreter: if resolve_executable("deno").is_some() {
@@ -220,11 +541,7 @@ pub(crate) fn load_config(config_path: &Path) -> Config {
                 view_runtime: None,
                 default_operation: Option::from(DefaultOperation::Prompt),
                 exec_argv_override: None,
-                verb_edit: None,
-                verb_print: None,
-                verb_printto: None,
-                verb_runas: None,
-                verb_uiaccess: None,
+                verbs: BTreeMap::new(),
             },
             FileAssociation {
                 shebang_interpreter: Option::from("perl".to_string()),
@@ -233,11 +550,7 @@ pub(crate) fn load_config(config_path: &Path) -> Config {
                 view_runtime: None,
                 default_operation: Option::from(DefaultOperation::Prompt),
                 exec_argv_override: None,
-                verb_edit: None,
-                verb_print: None,
-                verb_printto: None,
-                verb_runas: None,
-                verb_uiaccess: None,
+                verbs: BTreeMap::new(),
             },
             FileAssociation {
                 shebang_interpreter: Option::from("bash".to_string()),
@@ -246,22 +559,147 @@ pub(crate) fn load_config(config_path: &Path) -> Config {
                 view_runtime: None,
                 default_operation: Option::from(DefaultOperation::Prompt),
                 exec_argv_override: None,
-                verb_edit: None,
-                verb_print: None,
-                verb_printto: None,
-                verb_runas: None,
-                verb_uiaccess: None,
+                verbs: BTreeMap::new(),
             },
         ]),
+        context_menu: None,
+        trusted_paths: Vec::new(),
+        untrusted_paths: Vec::new(),
+        policy: ExecPolicy::default(),
+        audit: AuditConfig::default(),
+        machine_policy: None,
+        group_policy: GroupPolicy::default(),
+        source: None,
     };
 
-    if let Ok(config_str) = fs::read_to_string(config_path) {
-        toml::from_str(&config_str).unwrap_or(default_config)
-    } else {
-        log_debug!(&format!(
-            "Error: Failed to read config file: {:?}",
-            config_path
-        ));
-        default_config
+    let invalid = |reason: String| WinbangError::Config {
+        path: config_path.to_path_buf(),
+        reason,
+    };
+    match fs::read_to_string(config_path) {
+        Ok(config_str) => {
+            let mut config = toml::from_str::<Config>(&config_str)
+                .map_err(|e| invalid(e.to_string().trim_end().to_string()))?;
+            config.source = Some(config_path.to_path_buf());
+            Ok(config)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            log_debug!("No config file at {:?}; using defaults", config_path);
+            Ok(default_config)
+        }
+        Err(e) => Err(invalid(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn association(toml: &str) -> FileAssociation {
+        let config: Config = toml::from_str(toml).unwrap();
+        config.file_associations.unwrap().remove(0)
+    }
+
+    #[test]
+    fn verbs_accept_strings_and_tables() {
+        let assoc = association(
+            r#"
+            [[file_associations]]
+            exec_runtime = "python"
+
+            [file_associations.verbs]
+            edit = "code.exe \"%1\""
+
+            [file_associations.verbs.lint]
+            template = "ruff.exe check \"%1\""
+            label = "Lint"
+            icon = "ruff.exe,0"
+            position = "bottom"
+            extended = true
+
+            [file_associations.verbs.print]
+            fallback = "none"
+            "#,
+        );
+
+        let edit = assoc.verb("EDIT").unwrap();
+        assert_eq!(edit.template.as_deref(), Some("code.exe \"%1\""));
+        assert_eq!(edit.fallback, VerbFallback::Open);
+        assert!(!edit.extended);
+
+        let lint = assoc.verb("lint").unwrap();
+        assert_eq!(lint.label.as_deref(), Some("Lint"));
+        assert_eq!(lint.icon.as_deref(), Some("ruff.exe,0"));
+        assert_eq!(lint.position, Some(VerbPosition::Bottom));
+        assert!(lint.extended);
+
+        let print = assoc.verb("print").unwrap();
+        assert_eq!(print.template, None);
+        assert_eq!(print.fallback, VerbFallback::None);
+
+        assert!(assoc.verb("runas").is_none());
+    }
+
+    #[test]
+    fn legacy_verb_fields_are_aliases() {
+        let assoc = association(
+            r#"
+            [[file_associations]]
+            exec_runtime = "python"
+            verb_edit = "legacy-edit %1"
+            verb_uiaccess = "legacy-ui %1"
+            verb_print = "legacy-print %1"
+
+            [file_associations.verbs]
+            Print = "new-print %1"
+            "#,
+        );
+
+        assert_eq!(
+            assoc.verb("edit").unwrap().template.as_deref(),
+            Some("legacy-edit %1")
+        );
+        assert_eq!(
+            assoc.verb("uiaccess").unwrap().template.as_deref(),
+            Some("legacy-ui %1")
+        );
+        assert_eq!(
+            assoc.verb("print").unwrap().template.as_deref(),
+            Some("new-print %1")
+        );
+        assert_eq!(assoc.verbs.len(), 3);
+    }
+
+    #[test]
+    fn unknown_fallback_is_rejected() {
+        let result = toml::from_str::<Config>(
+            r#"
+            [[file_associations]]
+            exec_runtime = "python"
+            verbs = { edit = { fallback = "explode" } }
+            "#,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn only_a_missing_config_falls_back_to_defaults() {
+        let dir = env::temp_dir()
+            .join(format!("winbang-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+
+        let config = load_config(&path).unwrap();
+        assert_eq!(config.source, None);
+        assert!(config.file_associations.is_some());
+
+        fs::write(&path, "default_operation = \"sometimes\"").unwrap();
+        let error = load_config(&path).unwrap_err();
+        assert_eq!(error.exit_code(), 78);
+        assert!(error.to_string().contains("sometimes"));
+
+        fs::write(&path, "default_operation = \"execute\"").unwrap();
+        assert_eq!(load_config(&path).unwrap().source, Some(path));
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
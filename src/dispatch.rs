@@ -1,9 +1,22 @@
-use crate::config::{Config, DefaultOperation};
-use crate::gui::{UserChoice, interactive_prompt};
-use crate::log_debug;
+use crate::audit::{self, Operation};
+use crate::config::{Config, DefaultOperation, Origin};
+use crate::error::WinbangError;
+use crate::gui::{Notice, UserChoice, interactive_prompt};
+use crate::location::{trusted_rule, untrusted_rule};
 use crate::platform::resolve_executable;
+use crate::policy::{
+    MarkOfTheWeb, check_launch, mark_of_the_web, unsigned_execute,
+};
 use crate::script::ScriptMetadata;
+use crate::signature::SignedScript;
+use crate::template::{
+    FilterContext, expand_placeholders, is_batch_file, quote_batch_arg,
+};
+use crate::trust::{Trust, TrustStore, store_path};
+use crate::zone::{NtfsStreams, ZoneInfo, zone_of};
+use crate::{log_debug, log_info, log_trace, log_warn};
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::{fs, io};
@@ -11,6 +24,11 @@ use std::{fs, io};
 /// Build a command to execute the script.
 ///
 /// Constructs a command to execute the script using the specified interpreter.
+/// When the interpreter resolves to a `.bat`/`.cmd` file, every argument is
+/// escaped for cmd.exe, which re-parses the command line. Launches that
+/// Group Policy or `[policy]` block are refused with
+/// `WinbangError::Blocked`, and scripts without an association or with a
+/// path that isn't valid Unicode with `WinbangError::Unresolved`.
 ///
 /// # Arguments
 ///
@@ -18,7 +36,7 @@ use std::{fs, io};
 /// * `extra_args`: Optional additional arguments to pass to the command.
 /// * `config`: Configuration object containing file associations and defaults.
 ///
-/// returns: Command
+/// returns: Result<Command, WinbangError>
 ///
 /// # Examples
 ///
@@ -28,29 +46,47 @@ use std::{fs, io};
 pub(crate) fn build_command(
     script: &ScriptMetadata,
     extra_args: Option<Vec<String>>,
-    _config: &Config,
-) -> Command {
-    log_debug!("build_command({:?}, {:?})", script, &_config);
-
-    let mut command =
-        Command::new(&script.association.as_ref().unwrap().exec_runtime);
+    config: &Config,
+) -> Result<Command, WinbangError> {
+    log_trace!("build_command({:?}, {:?})", script, &config);
+
+    let Some(association) = &script.association else {
+        return Err(WinbangError::Unresolved(format!(
+            "no interpreter is configured for {}",
+            script.file_path.display()
+        )));
+    };
+    let runtime = &association.exec_runtime;
+    check_launch(config, runtime, &script.file_path, &NtfsStreams)
+        .map_err(|blocked| WinbangError::blocked(&script.file_path, blocked))?;
+    let (mut command, batch) = match resolve_executable(runtime) {
+        Some(path) if is_batch_file(&path.to_string_lossy()) => {
+            log_debug!("Runtime is a batch file: {:?}", path);
+            (Command::new(path), true)
+        }
+        _ => (Command::new(runtime), false),
+    };
 
     // If exec_argv_override was found, use it.
-    if let Some(arg_string) =
-        &script.association.as_ref().unwrap().exec_argv_override
-    {
+    if let Some(arg_string) = &association.exec_argv_override {
         let mut vars = HashMap::new();
-        let file_path = script.file_path.to_str().unwrap();
+        let Some(file_path) = script.file_path.to_str() else {
+            return Err(WinbangError::Unresolved(format!(
+                "{} is not a valid Unicode path",
+                script.file_path.display()
+            )));
+        };
 
-        vars.insert("script", file_path.replace("\\", "\\\\"));
+        vars.insert("script", file_path.to_string());
         vars.insert("script_unix", file_path.replace("\\", "/"));
 
         expand_and_push_args(
             &mut command,
+            batch,
             arg_string,
             &vars,
             extra_args.as_ref(),
-        );
+        )?;
     } else {
         // No override found, use the default behavior and optional argument
         log_debug!("No exec argv override found, using default behavior");
@@ -62,17 +98,17 @@ pub(crate) fn build_command(
             for part in
                 shell_words::split(arg).unwrap_or_else(|_| vec![arg.clone()])
             {
-                command.arg(part);
+                push_arg(&mut command, batch, part)?;
             }
         }
 
         // Append the script file path
-        command.arg(&script.file_path);
+        push_arg(&mut command, batch, &script.file_path)?;
 
         // Append extra arguments if provided
         if let Some(extra_args) = extra_args {
             for arg in extra_args {
-                command.arg(arg);
+                push_arg(&mut command, batch, arg)?;
             }
         }
     }
@@ -83,11 +119,14 @@ pub(crate) fn build_command(
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit());
 
-    command
+    audit::record_command(&command);
+    Ok(command)
 }
 
 /// Handle interactive dispatch for script execution.
-/// This function is called when the parent process is a GUI shell.
+/// This function is called when the parent process is a GUI shell. Scripts
+/// the user ran from the prompt before run again without it while they are
+/// unchanged (see `trust`).
 ///
 /// # Arguments
 ///
@@ -95,59 +134,137 @@ pub(crate) fn build_command(
 /// * `command`: Command object to execute the script.
 /// * `config`: Configuration object.
 ///
-/// returns: Result<(), Error>
+/// returns: Result<i32, WinbangError> — the script's exit code, or 0 when
+/// it was opened for editing or not run.
 ///
 /// # Examples
 ///
 /// ```
 /// let script_path = Path::new("example_script.sh");
 /// let mut command = Command::new("bash");
-/// handle_interactive_dispatch(script_path, &mut command, &config)?;
+/// let code = handle_interactive_dispatch(script_path, &mut command, &config)?;
 /// ```
 pub(crate) fn handle_interactive_dispatch(
     script: &ScriptMetadata,
     command: &mut Command,
     config: &Config,
-) -> io::Result<()> {
+) -> Result<i32, WinbangError> {
     log_debug!("Interactive dispatch for script: {:?}", script);
     let editor = resolve_view_runtime(script, config);
-    let operation = resolve_operation(script, config);
+    let zone = zone_of(&NtfsStreams, &script.file_path);
+    let signed = SignedScript::load(&script.file_path);
+    let (operation, origin) =
+        resolve_operation(script, config, zone.as_ref(), &signed);
 
     log_debug!("Editor resolved: {:?}", editor);
-    log_debug!("Operation resolved: {:?}", operation);
+    log_debug!("Operation resolved: {:?} ({})", operation, origin);
 
     match operation {
         DefaultOperation::Prompt => {
-            match interactive_prompt(script, &editor)? {
+            let unsigned = unsigned_execute(config, &signed);
+            let mut store = trust_store_for(&origin);
+            let trust = store.as_ref().map_or(Trust::Unknown, |store| {
+                store.check(&script.file_path).unwrap_or(Trust::Unknown)
+            });
+            if trust == Trust::Trusted && unsigned.is_none() {
+                audit::record_operation(Operation::Execute);
+                let code = run_to_exit(command)?;
+                log_info!("Trusted script executed: {:?}", script.file_path);
+                return Ok(code);
+            }
+
+            let notice = match (&origin, &zone, &trust) {
+                (Origin::MarkOfTheWeb(_), Some(zone), _) => {
+                    Some(Notice::Downloaded(zone))
+                }
+                (Origin::Unsigned(_), _, _) => {
+                    unsigned.as_ref().map(|(sig, _)| Notice::Unsigned(sig))
+                }
+                (_, _, Trust::Changed(summary)) => {
+                    Some(Notice::Changed(summary))
+                }
+                _ => None,
+            };
+            match interactive_prompt(script, &editor, notice)? {
                 UserChoice::Run => {
-                    let mut child = command.spawn()?;
-                    child.wait()?;
-                    log_debug!(&format!("Script executed: {:?}", script));
+                    audit::record_operation(Operation::Run);
+                    if let Some(store) = &mut store
+                        && let Err(e) = store
+                            .add(&script.file_path)
+                            .and_then(|_| store.save())
+                    {
+                        log_warn!("Could not trust script: {}", e);
+                    }
+                    let code = run_to_exit(command)?;
+                    log_info!("Script executed: {:?}", script.file_path);
+                    Ok(code)
+                }
+                UserChoice::Edit => {
+                    audit::record_operation(Operation::Edit);
+                    Ok(0)
+                }
+                UserChoice::Exit => {
+                    audit::record_operation(Operation::Cancel);
+                    Ok(0)
                 }
-                UserChoice::Edit => { /* already handled */ }
-                UserChoice::Exit => { /* do nothing */ }
             }
         }
         DefaultOperation::Execute => {
-            let mut child = command.spawn()?;
-            child.wait()?;
-            log_debug!(&format!("Script auto-executed: {:?}", script));
+            audit::record_operation(Operation::Execute);
+            let code = run_to_exit(command)?;
+            log_info!("Script auto-executed: {:?}", script.file_path);
+            Ok(code)
         }
         DefaultOperation::Open => {
+            audit::record_operation(Operation::Open);
             let editor_path = which::which(&editor)
                 .unwrap_or_else(|_| PathBuf::from("notepad"));
-            Command::new(editor_path)
+            Command::new(&editor_path)
                 .arg::<&PathBuf>(&script.file_path)
-                .spawn()?
+                .spawn()
+                .map_err(|e| WinbangError::spawn(&editor_path, e))?
                 .wait()?;
-            log_debug!(&format!(
+            log_info!(
                 "Script opened in editor: {:?} -> {:?}",
-                editor, script
-            ));
+                editor,
+                script.file_path
+            );
+            Ok(0)
         }
     }
+}
 
-    Ok(())
+/// Run `command` and wait for it, recording its exit code in the audit log.
+/// Returns the exit code, or 1 for a process ended without one.
+pub(crate) fn run_to_exit(command: &mut Command) -> Result<i32, WinbangError> {
+    let status = command
+        .spawn()
+        .map_err(|e| WinbangError::spawn(command.get_program(), e))?
+        .wait()?;
+    audit::record_exit(status.code());
+    Ok(status.code().unwrap_or(1))
+}
+
+/// The trust store, unless the prompt was forced by Group Policy,
+/// `untrusted_paths`, the Mark of the Web or a missing signature, which
+/// trusting a script must not get around.
+fn trust_store_for(origin: &Origin) -> Option<TrustStore> {
+    if matches!(
+        origin,
+        Origin::Policy(_)
+            | Origin::UntrustedPath(_)
+            | Origin::MarkOfTheWeb(_)
+            | Origin::Unsigned(_)
+    ) {
+        return None;
+    }
+    match store_path().and_then(|path| TrustStore::load(&path)) {
+        Ok(store) => Some(store),
+        Err(e) => {
+            log_warn!("Trust store unusable: {}", e);
+            None
+        }
+    }
 }
 
 /// Handle dispatch when no interpreter is found.
@@ -157,18 +274,18 @@ pub(crate) fn handle_interactive_dispatch(
 /// * `script`: Path to the script.
 /// * `config`: Configuration object.
 ///
-/// returns: Result<(), Error>
+/// returns: Result<i32, WinbangError> — the fallback handler's exit code.
 ///
 /// # Examples
 ///
 /// ```
 /// let script_path = Path::new("example_script.sh");
-/// handle_fallback_dispatch(script_path, &config)?;
+/// let code = handle_fallback_dispatch(script_path, &config)?;
 /// ```
 pub(crate) fn handle_fallback_dispatch(
     script: &ScriptMetadata,
     config: &Config,
-) -> io::Result<()> {
+) -> Result<i32, WinbangError> {
     let metadata = fs::metadata(&script.file_path)?;
     let size_mb = metadata.len() / 1_048_576;
 
@@ -220,10 +337,8 @@ pub(crate) fn handle_fallback_dispatch(
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit());
 
-    let mut child = fallback_cmd.spawn()?;
-    child.wait()?;
-
-    Ok(())
+    audit::record_command(&fallback_cmd);
+    run_to_exit(&mut fallback_cmd)
 }
 
 /// Resolve the view runtime for the script.
@@ -240,7 +355,10 @@ pub(crate) fn handle_fallback_dispatch(
 /// ```
 /// let runtime = resolve_view_runtime(&script, &config);
 /// ```
-fn resolve_view_runtime(script: &ScriptMetadata, config: &Config) -> String {
+pub(crate) fn resolve_view_runtime(
+    script: &ScriptMetadata,
+    config: &Config,
+) -> String {
     // Priority order: shebang interpreter > file extension > default
     if let Some(runtime) = script
         .association
@@ -252,17 +370,17 @@ fn resolve_view_runtime(script: &ScriptMetadata, config: &Config) -> String {
 
     if let Some(default_large) = &config.default_large {
         if script.file_size / 1_048_576 >= default_large.size_mb_threshold {
-            log_debug!(&format!(
+            log_debug!(
                 "File size exceeds threshold: {} MB",
                 script.file_size / 1_048_576
-            ));
+            );
 
             return default_large.view_runtime.clone();
         } else {
-            log_debug!(&format!(
+            log_debug!(
                 "File size is within threshold: {} MB",
                 script.file_size / 1_048_576
-            ));
+            );
         }
     }
 
@@ -277,37 +395,104 @@ fn resolve_view_runtime(script: &ScriptMetadata, config: &Config) -> String {
         .unwrap_or_else(|| "notepad".to_string())
 }
 
-/// Resolve the default operation for the script.
+/// Resolve the default operation for the script, and where it came from.
+/// Scripts downloaded from the internet always prompt unless policy says to
+/// ignore the Mark of the Web. Group Policy comes next: scripts in a pinned
+/// trusted directory run directly, and a forced `DefaultOperation` applies
+/// to everything else.
+/// Next, scripts under `untrusted_paths` prompt and those under
+/// `trusted_paths` run directly, before the association and config
+/// defaults apply. A script that would run directly prompts instead when a
+/// `[policy]` with `require_signature_to_execute` can't verify it.
 ///
 /// # Arguments
 ///
 /// * `script`:
 /// * `config`:
+/// * `zone`: The script's Mark of the Web, if it has one.
+/// * `signed`: The script's signature.
 ///
-/// returns: DefaultOperation
+/// returns: (DefaultOperation, Origin)
 ///
 /// # Examples
 ///
 /// ```
-/// let operation = resolve_operation(&script, &config);
+/// let zone = zone_of(&NtfsStreams, &script.file_path);
+/// let signed = SignedScript::load(&script.file_path);
+/// let (operation, origin) =
+///     resolve_operation(&script, &config, zone.as_ref(), &signed);
 /// ```
-fn resolve_operation(
+pub(crate) fn resolve_operation(
     script: &ScriptMetadata,
     config: &Config,
-) -> DefaultOperation {
+    zone: Option<&ZoneInfo>,
+    signed: &SignedScript,
+) -> (DefaultOperation, Origin) {
+    match configured_operation(script, config, zone) {
+        (DefaultOperation::Execute, origin) => {
+            match unsigned_execute(config, signed) {
+                Some((_, source)) => {
+                    (DefaultOperation::Prompt, Origin::Unsigned(source))
+                }
+                None => (DefaultOperation::Execute, origin),
+            }
+        }
+        resolved => resolved,
+    }
+}
+
+fn configured_operation(
+    script: &ScriptMetadata,
+    config: &Config,
+    zone: Option<&ZoneInfo>,
+) -> (DefaultOperation, Origin) {
+    if let Some(zone) = zone.filter(|zone| zone.is_internet())
+        && mark_of_the_web(config).0 != MarkOfTheWeb::Ignore
+    {
+        return (
+            DefaultOperation::Prompt,
+            Origin::MarkOfTheWeb(zone.zone_name()),
+        );
+    }
+
+    let policy = &config.group_policy;
+    if policy.trusted_directory(&script.file_path).is_some() {
+        return (
+            DefaultOperation::Execute,
+            Origin::Policy("TrustedDirectories"),
+        );
+    }
+
+    if let Some(op) = policy.default_operation {
+        return (op, Origin::Policy("DefaultOperation"));
+    }
+
+    if let Some(rule) = untrusted_rule(config, &script.file_path) {
+        return (
+            DefaultOperation::Prompt,
+            Origin::UntrustedPath(rule.to_string()),
+        );
+    }
+    if let Some(rule) = trusted_rule(config, &script.file_path) {
+        return (
+            DefaultOperation::Execute,
+            Origin::TrustedPath(rule.to_string()),
+        );
+    }
+
     if let Some(op) = script
         .association
         .as_ref()
-        .and_then(|a| a.default_operation.clone())
+        .and_then(|a| a.default_operation)
     {
-        return op;
+        return (op, Origin::Association);
     }
 
     if let Some(op) = config.default_operation {
-        return op;
+        return (op, Origin::Config);
     }
 
-    DefaultOperation::Prompt
+    (DefaultOperation::Prompt, Origin::Default)
 }
 
 /// Expand variable strings inside command arguments and push them to the command.
@@ -316,10 +501,11 @@ fn resolve_operation(
 /// # Arguments
 ///
 /// * `command`: Command object to modify.
+/// * `batch`: Whether the runtime is a batch file (see `push_arg`).
 /// * `arg_string`: String containing arguments with placeholders.
 /// * `vars`: HashMap of variables to expand.
 ///
-/// returns: ()
+/// returns: Result<(), Error>
 ///
 /// # Examples
 ///
@@ -328,70 +514,106 @@ fn resolve_operation(
 /// let arg_string = "arg1 @{{script}} arg2";
 /// let vars = HashMap::new();
 /// vars.insert("script", "example.py".to_string());
-/// expand_and_push_args(&mut command, arg_string, &vars);
+/// expand_and_push_args(&mut command, false, arg_string, &vars, None)?;
 /// ```
 fn expand_and_push_args(
     command: &mut Command,
+    batch: bool,
     arg_str: &str,
     vars: &HashMap<&str, String>,
     passed_args: Option<&Vec<String>>,
-) {
-    log_debug!(&format!("Expanding arguments with vars: {:?}", vars));
+) -> io::Result<()> {
+    log_trace!("Expanding arguments with vars: {:?}", vars);
+    let ctx = FilterContext::current();
 
     // Split the argument string into parts and expand each part
     for part in shell_words::split(arg_str).unwrap_or_default() {
-        log_debug!(&format!("Expanding part: '{}'", part));
+        log_trace!("Expanding part: '{}'", part);
 
         // Special handling for @{passed_args} - expand to multiple separate args
         if part == "@{passed_args}" {
             if let Some(args) = passed_args {
                 for arg in args {
-                    log_debug!(&format!("Adding passed argument: '{}'", arg));
-                    command.arg(arg);
+                    log_trace!("Adding passed argument: '{}'", arg);
+                    push_arg(command, batch, arg)?;
                 }
             }
             // If no passed_args, don't add anything (no empty args)
             continue;
         }
 
-        let expanded = expand_placeholders(&part, vars);
+        let expanded = expand_placeholders(&part, vars, &ctx);
 
         // Skip empty arguments after expansion to avoid passing spurious empty args
         if expanded.is_empty() {
-            log_debug!("Skipping empty expanded argument");
+            log_trace!("Skipping empty expanded argument");
             continue;
         }
 
         // Push the expanded argument directly without re-splitting.
         // The initial shell_words::split already handled quoting,
         // so re-splitting would break paths with spaces.
-        log_debug!(&format!("Expanded argument: '{}'", expanded));
-        command.arg(expanded);
+        log_trace!("Expanded argument: '{}'", expanded);
+        push_arg(command, batch, expanded)?;
     }
+
+    Ok(())
 }
 
-/// Expand placeholders in a string using a HashMap.
+/// Push one argument onto the command.
+///
+/// Batch files run through cmd.exe, which re-parses the command line and
+/// would otherwise expand `%VAR%` and act on `&`, `|` and friends inside
+/// file names and arguments. For those, the argument is escaped with
+/// `quote_batch_arg` and pushed raw.
 ///
 /// # Arguments
 ///
-/// * `s`: String containing placeholders.
-/// * `vars`: HashMap of variables to expand.
+/// * `command`: Command object to modify.
+/// * `batch`: Whether the runtime is a batch file.
+/// * `arg`: The argument.
 ///
-/// returns: String
+/// returns: Result<(), Error>
 ///
 /// # Examples
 ///
 /// ```
-/// let s = "Hello @{{name}}!";
-/// let mut vars = HashMap::new();
-/// vars.insert("name", "World".to_string());
-/// let result = expand_placeholders(s, &vars);
+/// let mut command = Command::new("C:\\tools\\run.cmd");
+/// push_arg(&mut command, true, "a & b")?;
 /// ```
-fn expand_placeholders(s: &str, vars: &HashMap<&str, String>) -> String {
-    let mut result = s.to_owned();
-    for (key, val) in vars {
-        let placeholder = format!("@{{{}}}", key);
-        result = result.replace(&placeholder, val);
+fn push_arg(
+    command: &mut Command,
+    batch: bool,
+    arg: impl AsRef<OsStr>,
+) -> io::Result<()> {
+    let arg = arg.as_ref();
+    if !batch {
+        command.arg(arg);
+        return Ok(());
+    }
+
+    match quote_batch_arg(&arg.to_string_lossy()) {
+        Some(quoted) => {
+            push_raw(command, quoted);
+            Ok(())
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("argument cannot be passed to a batch file: {:?}", arg),
+        )),
     }
-    result
+}
+
+/// Append an argument to the command line verbatim, with no further quoting.
+#[cfg(target_os = "windows")]
+fn push_raw(command: &mut Command, arg: String) {
+    use std::os::windows::process::CommandExt;
+    command.raw_arg(arg);
+}
+
+/// Only Windows has a single command line to write into; elsewhere the
+/// pre-quoted argument is passed as one argv entry.
+#[cfg(not(target_os = "windows"))]
+fn push_raw(command: &mut Command, arg: String) {
+    command.arg(arg);
 }
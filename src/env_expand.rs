@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::env;
+
+/// Snapshot of the process environment, for `expand_env_vars`.
+pub(crate) fn current_env() -> HashMap<String, String> {
+    env::vars().collect()
+}
+
+/// Expand `%VAR%` references the way `ExpandEnvironmentStringsW` does.
+///
+/// Names are matched case-insensitively. A reference to an unknown variable
+/// (or an empty `%%`) is left as-is, and its closing `%` may open the next
+/// reference, so `50% of %PATH%` still expands `%PATH%`.
+///
+/// # Arguments
+///
+/// * `s`: String containing `%VAR%` references.
+/// * `env`: Variables to substitute, in any case.
+///
+/// returns: String
+///
+/// # Examples
+///
+/// ```
+/// let cmd = "%SystemRoot%\\System32\\notepad.exe %1";
+/// let expanded = expand_env_vars(cmd, &current_env());
+/// ```
+pub(crate) fn expand_env_vars(
+    s: &str,
+    env: &HashMap<String, String>,
+) -> String {
+    let by_upper: HashMap<String, &str> = env
+        .iter()
+        .map(|(k, v)| (k.to_uppercase(), v.as_str()))
+        .collect();
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find('%') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(len) = after.find('%') else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let name = &after[..len];
+        match by_upper.get(&name.to_uppercase()) {
+            Some(value) if !name.is_empty() => {
+                out.push_str(value);
+                rest = &after[len + 1..];
+            }
+            _ => {
+                // Keep the opening `%` and rescan from the closing one.
+                out.push('%');
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> HashMap<String, String> {
+        let mut env = HashMap::new();
+        env.insert("SystemRoot".to_string(), r"C:\Windows".to_string());
+        env.insert("EMPTY".to_string(), String::new());
+        env.insert("Path".to_string(), r"C:\bin".to_string());
+        env
+    }
+
+    #[test]
+    fn expands_known_variables() {
+        assert_eq!(
+            expand_env_vars(r"%SystemRoot%\System32\notepad.exe %1", &env()),
+            r"C:\Windows\System32\notepad.exe %1"
+        );
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        assert_eq!(
+            expand_env_vars("%SYSTEMROOT%|%path%", &env()),
+            r"C:\Windows|C:\bin"
+        );
+    }
+
+    #[test]
+    fn unknown_variables_are_left_as_is() {
+        assert_eq!(expand_env_vars("%NOPE%\\x", &env()), "%NOPE%\\x");
+        assert_eq!(expand_env_vars("100%% done", &env()), "100%% done");
+        assert_eq!(expand_env_vars("trailing %", &env()), "trailing %");
+        assert_eq!(expand_env_vars("%1 %*", &env()), "%1 %*");
+    }
+
+    #[test]
+    fn closing_percent_can_open_the_next_reference() {
+        assert_eq!(expand_env_vars("50% of %PATH%", &env()), r"50% of C:\bin");
+        assert_eq!(expand_env_vars("%NOPE%PATH%", &env()), r"%NOPEC:\bin");
+    }
+
+    #[test]
+    fn empty_values_expand_to_nothing() {
+        assert_eq!(expand_env_vars("a%EMPTY%b", &env()), "ab");
+    }
+}
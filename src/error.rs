@@ -0,0 +1,234 @@
+//! `WinbangError`: the ways running a script or verb can fail, each with
+//! the exit code Winbang returns and the message the user sees.
+//!
+//! Exit codes follow the shell's conventions for programs that can't be
+//! found or run, Windows' own code for missing elevation, and `sysexits.h`
+//! for the rest, so callers such as an AHK `ShellExecuteEx` fallback chain
+//! can tell failures apart. A script that
+//! runs exits with its own code.
+
+use std::path::{Path, PathBuf};
+use std::{error, fmt, io};
+
+use crate::gui::show_error;
+use crate::platform::is_interactive_parent;
+use crate::policy::Blocked;
+
+/// Options that don't go together (`EX_USAGE`).
+const EXIT_USAGE: i32 = 64;
+/// An I/O or registry operation failed (`EX_IOERR`).
+const EXIT_IO_ERROR: i32 = 74;
+/// Policy refused the launch (`EX_NOPERM`).
+const EXIT_NO_PERMISSION: i32 = 77;
+/// A config file is invalid (`EX_CONFIG`).
+const EXIT_CONFIG: i32 = 78;
+/// The command needs an elevated prompt (`ERROR_ELEVATION_REQUIRED`).
+const EXIT_ELEVATION_REQUIRED: i32 = 740;
+/// A program was found but couldn't be started.
+const EXIT_CANNOT_EXECUTE: i32 = 126;
+/// There was nothing to run: no script, interpreter or handler.
+const EXIT_NOT_FOUND: i32 = 127;
+
+#[derive(Debug)]
+pub(crate) enum WinbangError {
+    /// A config file exists but can't be read or parsed. Exits 78.
+    Config { path: PathBuf, reason: String },
+    /// The script, its interpreter or a verb's handler couldn't be found or
+    /// resolved: why. Exits 127.
+    Unresolved(String),
+    /// Group Policy or a `[policy]` refused to launch `script`. Exits 77.
+    Blocked { script: PathBuf, blocked: Blocked },
+    /// The command needs an elevated prompt: why. Exits 740.
+    Elevation(String),
+    /// Options or arguments that can't be used as given: why. Exits 64.
+    Usage(String),
+    /// `program` couldn't be started. Exits 127 when it doesn't exist and
+    /// 126 otherwise.
+    Spawn { program: String, source: io::Error },
+    /// A registry change failed: what. Exits 74.
+    Registry(String),
+    /// Reading or writing `path`, such as the trust store or a `.reg` file,
+    /// failed. Exits 74.
+    File { path: PathBuf, source: io::Error },
+    /// Any other I/O failure. Exits 74.
+    Io(io::Error),
+}
+
+impl WinbangError {
+    /// `blocked`, refusing to launch `script`.
+    pub(crate) fn blocked(script: &Path, blocked: Blocked) -> WinbangError {
+        WinbangError::Blocked {
+            script: script.to_path_buf(),
+            blocked,
+        }
+    }
+
+    /// `source`, failing to start `program`.
+    pub(crate) fn spawn(
+        program: impl AsRef<std::ffi::OsStr>,
+        source: io::Error,
+    ) -> WinbangError {
+        WinbangError::Spawn {
+            program: program.as_ref().to_string_lossy().to_string(),
+            source,
+        }
+    }
+
+    /// The process exit code for this error.
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            WinbangError::Config { .. } => EXIT_CONFIG,
+            WinbangError::Unresolved(_) => EXIT_NOT_FOUND,
+            WinbangError::Blocked { .. } => EXIT_NO_PERMISSION,
+            WinbangError::Elevation(_) => EXIT_ELEVATION_REQUIRED,
+            WinbangError::Usage(_) => EXIT_USAGE,
+            WinbangError::Spawn { source, .. }
+                if source.kind() == io::ErrorKind::NotFound =>
+            {
+                EXIT_NOT_FOUND
+            }
+            WinbangError::Spawn { .. } => EXIT_CANNOT_EXECUTE,
+            WinbangError::Registry(_)
+            | WinbangError::File { .. }
+            | WinbangError::Io(_) => EXIT_IO_ERROR,
+        }
+    }
+
+    /// Tell the user: on stderr, and in a dialog when started from a GUI
+    /// shell, where there is no console to read.
+    ///
+    /// # Arguments
+    ///
+    /// * `gui_shells`: Process names that count as GUI shells.
+    ///
+    /// returns: ()
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// if let Err(e) = run_script(&argv, &config) {
+    ///     e.report(&config.gui_shells.clone().unwrap_or_default());
+    ///     process::exit(e.exit_code());
+    /// }
+    /// ```
+    pub(crate) fn report(&self, gui_shells: &[String]) {
+        eprintln!("winbang: {}", self);
+        if !is_interactive_parent(gui_shells) {
+            return;
+        }
+        match self {
+            WinbangError::Blocked { script, blocked } => show_error(
+                &format!("\"{}\" was blocked", file_name(script)),
+                &format!(
+                    "Your organization's policy doesn't allow this script \
+                     to run.\n\n{}.",
+                    blocked
+                ),
+            ),
+            _ => show_error("Winbang", &format!("{}.", self)),
+        }
+    }
+}
+
+impl fmt::Display for WinbangError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WinbangError::Config { path, reason } => {
+                write!(f, "invalid config {}: {}", path.display(), reason)
+            }
+            WinbangError::Unresolved(why) => f.write_str(why),
+            WinbangError::Blocked { script, blocked } => {
+                write!(f, "{} was blocked: {}", file_name(script), blocked)
+            }
+            WinbangError::Spawn { program, source } => {
+                write!(f, "cannot start {}: {}", program, source)
+            }
+            WinbangError::Elevation(why) | WinbangError::Usage(why) => {
+                f.write_str(why)
+            }
+            WinbangError::Registry(what) => f.write_str(what),
+            WinbangError::File { path, source } => {
+                write!(f, "{}: {}", path.display(), source)
+            }
+            WinbangError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for WinbangError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            WinbangError::Blocked { blocked, .. } => Some(blocked),
+            WinbangError::Spawn { source, .. }
+            | WinbangError::File { source, .. } => Some(source),
+            WinbangError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WinbangError {
+    fn from(e: io::Error) -> Self {
+        WinbangError::Io(e)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_and_messages() {
+        let blocked = WinbangError::blocked(
+            Path::new("C:/x/build.sh"),
+            Blocked {
+                reason: "extension .sh matches deny rule \"sh\"".to_string(),
+                source: "[policy]".to_string(),
+            },
+        );
+        assert_eq!(blocked.exit_code(), 77);
+        assert_eq!(
+            blocked.to_string(),
+            "build.sh was blocked: extension .sh matches deny rule \"sh\" \
+             ([policy])"
+        );
+
+        let missing = WinbangError::spawn(
+            "pyhton",
+            io::Error::from(io::ErrorKind::NotFound),
+        );
+        assert_eq!(missing.exit_code(), 127);
+        assert!(missing.to_string().starts_with("cannot start pyhton: "));
+        let denied = WinbangError::spawn(
+            "python",
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        );
+        assert_eq!(denied.exit_code(), 126);
+
+        let config = WinbangError::Config {
+            path: PathBuf::from("config.toml"),
+            reason: "expected `=`".to_string(),
+        };
+        assert_eq!(config.exit_code(), 78);
+        assert_eq!(
+            config.to_string(),
+            "invalid config config.toml: expected `=`"
+        );
+        assert_eq!(WinbangError::Unresolved(String::new()).exit_code(), 127);
+        assert_eq!(WinbangError::Usage(String::new()).exit_code(), 64);
+        assert_eq!(WinbangError::Elevation(String::new()).exit_code(), 740);
+        let file = WinbangError::File {
+            path: PathBuf::from("trust.toml"),
+            source: io::Error::other("locked"),
+        };
+        assert_eq!(file.exit_code(), 74);
+        assert_eq!(file.to_string(), "trust.toml: locked");
+        assert_eq!(WinbangError::from(io::Error::other("x")).exit_code(), 74);
+    }
+}
@@ -0,0 +1,355 @@
+//! `winbang explain`: the settings in effect and where each one came from,
+//! so admins and users can see when Group Policy overrides their config.
+
+use std::path::Path;
+
+use crate::config::{Config, DefaultOperation, Origin};
+use crate::dispatch::resolve_operation;
+use crate::location::canonical_path;
+use crate::policy::{check_launch, mark_of_the_web, signatures};
+use crate::script::get_script_metadata;
+use crate::signature::SignedScript;
+use crate::trust::{Trust, TrustStore, store_path};
+use crate::zone::{FileStreams, zone_of};
+
+/// The effective settings as `(label, value)` lines, followed by how
+/// `script` would be handled when one is given.
+///
+/// # Arguments
+///
+/// * `config`: Active configuration, including Group Policy.
+/// * `config_path`: The config file that was loaded.
+/// * `script`: Optional script to explain.
+/// * `streams`: Where to read the script's Mark of the Web.
+///
+/// returns: Vec<(&str, String)>
+///
+/// # Examples
+///
+/// ```
+/// let lines = explain(&config, &config_path, Some("build.sh"), &NtfsStreams);
+/// for (label, value) in lines {
+///     println!("{:<20} {}", label, value);
+/// }
+/// ```
+pub(crate) fn explain(
+    config: &Config,
+    config_path: &Path,
+    script: Option<&str>,
+    streams: &dyn FileStreams,
+) -> Vec<(&'static str, String)> {
+    let policy = &config.group_policy;
+    let mut lines = Vec::new();
+
+    let found = if config_path.exists() {
+        ""
+    } else {
+        " (not found; built-in defaults)"
+    };
+    lines.push(("config file", format!("{}{}", config_path.display(), found)));
+    lines.push((
+        "project config",
+        allowed(policy.disable_project_config, "DisableProjectConfig"),
+    ));
+    lines.push((
+        "user config",
+        allowed(policy.disable_user_config, "DisableUserConfig"),
+    ));
+
+    let (operation, origin) = default_operation(config);
+    lines.push(("default_operation", format!("{} ({})", operation, origin)));
+    lines.push((
+        "denied interpreters",
+        listed(&policy.deny_interpreters, "DenyInterpreters"),
+    ));
+    let trusted: Vec<String> = policy
+        .trusted_directories
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect();
+    lines.push((
+        "trusted directories",
+        listed(&trusted, "TrustedDirectories"),
+    ));
+    let (action, source) = mark_of_the_web(config);
+    lines.push(("mark of the web", format!("{} ({})", action, source)));
+    lines.push(("trusted paths", rules(&config.trusted_paths)));
+    lines.push(("untrusted paths", rules(&config.untrusted_paths)));
+
+    let Some(script) = script else {
+        return lines;
+    };
+    let metadata = get_script_metadata(
+        &script.to_string(),
+        config.file_associations.as_deref().unwrap_or(&[]),
+    );
+    lines.push(("script", metadata.file_path.display().to_string()));
+    let absolute = std::path::absolute(&metadata.file_path).ok();
+    match canonical_path(&metadata.file_path) {
+        Some(real) if Some(&real) != absolute.as_ref() => {
+            lines.push(("resolves to", real.display().to_string()));
+        }
+        Some(_) => {}
+        None => lines.push(("resolves to", "not found".to_string())),
+    }
+    let zone = zone_of(streams, &metadata.file_path);
+    let downloaded = match &zone {
+        Some(zone) => format!(
+            "{} zone, from {}",
+            zone.zone_name(),
+            zone.host_url.as_deref().unwrap_or("an unknown source")
+        ),
+        None => "none".to_string(),
+    };
+    lines.push(("zone", downloaded));
+    let signed = SignedScript::load(&metadata.file_path);
+    for (signature, source) in signatures(config, &signed) {
+        lines.push(("signature", format!("{} ({})", signature, source)));
+    }
+    let interpreter = match &metadata.association {
+        Some(assoc) if policy.denies_interpreter(&assoc.exec_runtime) => {
+            format!(
+                "{} (blocked: {})",
+                assoc.exec_runtime,
+                Origin::Policy("DenyInterpreters")
+            )
+        }
+        Some(assoc) => assoc.exec_runtime.clone(),
+        None => "none; opens in the default viewer".to_string(),
+    };
+    lines.push(("interpreter", interpreter));
+    if let Some(assoc) = &metadata.association {
+        let (operation, origin) =
+            resolve_operation(&metadata, config, zone.as_ref(), &signed);
+        lines.push(("operation", format!("{} ({})", operation, origin)));
+        let trust = store_path()
+            .and_then(|path| TrustStore::load(&path))
+            .and_then(|store| store.check(&metadata.file_path));
+        let trust = match trust {
+            Ok(Trust::Trusted) => "trusted".to_string(),
+            Ok(Trust::Changed(summary)) => {
+                format!("changed since trusted: {}", summary)
+            }
+            Ok(Trust::Unknown) => "not trusted".to_string(),
+            Err(e) => format!("unreadable: {}", e),
+        };
+        lines.push(("trust store", trust));
+        let verdict = match check_launch(
+            config,
+            &assoc.exec_runtime,
+            &metadata.file_path,
+            streams,
+        ) {
+            Ok(()) => "allowed".to_string(),
+            Err(blocked) => format!("blocked: {}", blocked),
+        };
+        lines.push(("policy", verdict));
+    }
+    lines
+}
+
+/// The operation for scripts without their own, as `resolve_operation`
+/// picks it.
+fn default_operation(config: &Config) -> (DefaultOperation, Origin) {
+    if let Some(op) = config.group_policy.default_operation {
+        (op, Origin::Policy("DefaultOperation"))
+    } else if let Some(op) = config.default_operation {
+        (op, Origin::Config)
+    } else {
+        (DefaultOperation::Prompt, Origin::Default)
+    }
+}
+
+fn allowed(disabled: bool, policy: &'static str) -> String {
+    if disabled {
+        format!("disabled ({})", Origin::Policy(policy))
+    } else {
+        "allowed".to_string()
+    }
+}
+
+fn rules(rules: &[String]) -> String {
+    if rules.is_empty() {
+        "none".to_string()
+    } else {
+        format!("{} (config file)", rules.join(", "))
+    }
+}
+
+fn listed(items: &[String], policy: &'static str) -> String {
+    if items.is_empty() {
+        "none".to_string()
+    } else {
+        format!("{} ({})", items.join(", "), Origin::Policy(policy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{ExecPolicy, MarkOfTheWeb};
+    use crate::zone::MemoryStreams;
+
+    fn value(lines: &[(&str, String)], label: &str) -> String {
+        lines
+            .iter()
+            .find(|(l, _)| *l == label)
+            .map(|(_, v)| v.clone())
+            .unwrap()
+    }
+
+    #[test]
+    fn policy_overrides_and_is_reported() {
+        let mut config: Config = toml::from_str(
+            r#"
+            default_operation = "open"
+
+            [[file_associations]]
+            exec_runtime = "ruby"
+            extension = "rb"
+            default_operation = "execute"
+            "#,
+        )
+        .unwrap();
+        let path = Path::new("test_files/none.toml");
+        let script = Some("test_files/test.rb");
+        let streams = MemoryStreams::default();
+
+        let lines = explain(&config, path, script, &streams);
+        assert_eq!(value(&lines, "default_operation"), "open (config file)");
+        assert_eq!(value(&lines, "operation"), "execute (file association)");
+        assert_eq!(value(&lines, "policy"), "allowed");
+
+        let dir = std::path::absolute("test_files").unwrap();
+        let rule = dir.to_string_lossy().to_string();
+        config.trusted_paths = vec![rule.clone()];
+        let lines = explain(&config, path, script, &streams);
+        let trusted = format!("execute (trusted_paths rule \"{}\")", rule);
+        assert_eq!(value(&lines, "operation"), trusted);
+        config.untrusted_paths = vec![format!("{}/*.rb", rule)];
+        let lines = explain(&config, path, script, &streams);
+        assert!(value(&lines, "operation").starts_with("prompt (untrusted"));
+
+        let streams = MemoryStreams::default().with_zone(
+            Path::new("test_files/test.rb"),
+            include_str!("../test_files/zone/internet.txt"),
+        );
+        let lines = explain(&config, path, script, &streams);
+        assert_eq!(
+            value(&lines, "zone"),
+            "Internet zone, from https://example.com/tools/build.sh"
+        );
+        assert_eq!(
+            value(&lines, "operation"),
+            "prompt (downloaded from the Internet zone)"
+        );
+        config.policy.mark_of_the_web = Some(MarkOfTheWeb::Block);
+        let lines = explain(&config, path, script, &streams);
+        assert!(value(&lines, "policy").contains("Internet zone"));
+        config.policy.mark_of_the_web = Some(MarkOfTheWeb::Ignore);
+        let lines = explain(&config, path, script, &streams);
+        assert_eq!(
+            value(&lines, "operation"),
+            "prompt (downloaded from the Internet zone)"
+        );
+        let machine: ExecPolicy =
+            toml::from_str("mark_of_the_web = \"ignore\"").unwrap();
+        config.machine_policy = Some(("C:/ProgramData".into(), machine));
+        let lines = explain(&config, path, script, &streams);
+        assert!(value(&lines, "operation").starts_with("prompt (untrusted"));
+
+        let streams = MemoryStreams::default();
+        config.group_policy.default_operation = Some(DefaultOperation::Prompt);
+        config.group_policy.disable_user_config = true;
+        config.group_policy.deny_interpreters = vec!["ruby.exe".to_string()];
+        let lines = explain(&config, path, script, &streams);
+        let forced = "prompt (policy HKLM\\Software\\Policies\\Winbang\\\
+                      DefaultOperation)";
+        assert_eq!(value(&lines, "default_operation"), forced);
+        assert_eq!(value(&lines, "operation"), forced);
+        assert!(value(&lines, "user config").starts_with("disabled (policy"));
+        assert_eq!(value(&lines, "project config"), "allowed");
+        assert!(value(&lines, "interpreter").starts_with("ruby (blocked:"));
+        assert!(value(&lines, "policy").starts_with("blocked: interpreter"));
+    }
+
+    #[test]
+    fn signatures_gate_execute_and_paths() {
+        let public = include_str!("../test_files/signed/test.pub");
+        let mut config: Config = toml::from_str(&format!(
+            r#"
+            default_operation = "execute"
+
+            [[file_associations]]
+            exec_runtime = "python"
+            extension = "py"
+
+            [policy]
+            trusted_keys = ["{}"]
+            require_signature_to_execute = true
+            "#,
+            public.lines().nth(1).unwrap()
+        ))
+        .unwrap();
+        let path = Path::new("test_files/none.toml");
+        let streams = MemoryStreams::default();
+
+        let lines = explain(
+            &config,
+            path,
+            Some("test_files/signed/inline.py"),
+            &streams,
+        );
+        assert!(value(&lines, "signature").starts_with("valid, key RW"));
+        assert_eq!(value(&lines, "operation"), "execute (config file)");
+
+        let tampered = Some("test_files/signed/tampered.py");
+        let lines = explain(&config, path, tampered, &streams);
+        assert_eq!(
+            value(&lines, "signature"),
+            "invalid: comment block doesn't match the script ([policy])"
+        );
+        assert_eq!(
+            value(&lines, "operation"),
+            "prompt (no valid signature, required by [policy])"
+        );
+        assert_eq!(value(&lines, "policy"), "allowed");
+
+        let dir = std::path::absolute("test_files/signed").unwrap();
+        let rule = format!("{}/*.py", dir.to_string_lossy());
+        config.policy.require_signature_paths = vec![rule];
+        let lines = explain(&config, path, tampered, &streams);
+        assert!(
+            value(&lines, "policy").starts_with(
+                "blocked: path matches require_signature_paths rule"
+            )
+        );
+        let lines = explain(
+            &config,
+            path,
+            Some("test_files/signed/inline.py"),
+            &streams,
+        );
+        assert_eq!(value(&lines, "policy"), "allowed");
+
+        // Each section checks against its own keys, not the union.
+        let machine: ExecPolicy =
+            toml::from_str("require_signature_to_execute = true").unwrap();
+        config.machine_policy = Some(("C:/ProgramData".into(), machine));
+        let inline = Some("test_files/signed/inline.py");
+        let lines = explain(&config, path, inline, &streams);
+        let signatures: Vec<&String> = lines
+            .iter()
+            .filter(|(label, _)| *label == "signature")
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(signatures.len(), 2);
+        assert_eq!(
+            signatures[0],
+            "invalid: no trusted keys are configured ([policy] in \
+             C:/ProgramData)"
+        );
+        assert!(signatures[1].starts_with("valid, key RW"));
+        assert!(value(&lines, "operation").starts_with("prompt (no valid"));
+    }
+}
@@ -0,0 +1,238 @@
+//! Group Policy settings under `HKLM\Software\Policies\Winbang`, which admins
+//! deploy through ADMX templates or Group Policy Preferences. Policy
+//! overrides every config file.
+//!
+//! * `DefaultOperation` (REG_SZ): `prompt`, `open` or `execute`, forced for
+//!   every script.
+//! * `DisableUserConfig`, `DisableProjectConfig` (REG_DWORD): ignore the
+//!   APPDATA and current-directory `config.toml`.
+//! * `MarkOfTheWeb` (REG_SZ): `ignore`, `prompt` or `block` scripts
+//!   downloaded from the internet.
+//! * `DenyInterpreters`, `TrustedDirectories`: lists, either as a
+//!   REG_MULTI_SZ value or as a subkey of that name whose values hold the
+//!   entries (the layout ADMX `list` elements write).
+
+use std::path::{Path, PathBuf};
+
+use crate::config::DefaultOperation;
+use crate::location::canonical_path;
+use crate::policy::{MarkOfTheWeb, program_name};
+use crate::registry::{RegValue, RegistryBackend, Root};
+
+pub(crate) const POLICY_KEY: &str = "Software\\Policies\\Winbang";
+
+/// The Group Policy settings in effect. Everything is unset by default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct GroupPolicy {
+    /// Operation forced for every script.
+    pub(crate) default_operation: Option<DefaultOperation>,
+    /// Ignore `%APPDATA%\Winbang\config.toml`.
+    pub(crate) disable_user_config: bool,
+    /// Ignore `config.toml` in the current directory.
+    pub(crate) disable_project_config: bool,
+    /// Interpreters Winbang must never launch, by name (`mshta`) or path.
+    pub(crate) deny_interpreters: Vec<String>,
+    /// Directories whose scripts run without prompting.
+    pub(crate) trusted_directories: Vec<PathBuf>,
+    /// Handling of scripts downloaded from the internet.
+    pub(crate) mark_of_the_web: Option<MarkOfTheWeb>,
+}
+
+impl GroupPolicy {
+    /// Read the policy from `HKLM\Software\Policies\Winbang`. Missing or
+    /// malformed values are left unset.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry`: Registry to read.
+    ///
+    /// returns: GroupPolicy
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let policy = GroupPolicy::load(&registry::system());
+    /// ```
+    pub(crate) fn load(registry: &dyn RegistryBackend) -> GroupPolicy {
+        let default_operation = registry
+            .read_string(Root::LocalMachine, POLICY_KEY, "DefaultOperation")
+            .and_then(|name| parse_operation(&name));
+        GroupPolicy {
+            default_operation,
+            disable_user_config: read_flag(registry, "DisableUserConfig"),
+            disable_project_config: read_flag(registry, "DisableProjectConfig"),
+            deny_interpreters: read_list(registry, "DenyInterpreters"),
+            trusted_directories: read_list(registry, "TrustedDirectories")
+                .into_iter()
+                .map(PathBuf::from)
+                .collect(),
+            mark_of_the_web: registry
+                .read_string(Root::LocalMachine, POLICY_KEY, "MarkOfTheWeb")
+                .and_then(|name| parse_mark_of_the_web(&name)),
+        }
+    }
+
+    /// Whether `interpreter`, a name or path, is denied. Names match the
+    /// file name with or without `.exe`, case-insensitively.
+    pub(crate) fn denies_interpreter(&self, interpreter: &str) -> bool {
+        let name = program_name(interpreter);
+        self.deny_interpreters
+            .iter()
+            .any(|denied| program_name(denied) == name)
+    }
+
+    /// The pinned trusted directory `path` is in, if any. Both are compared
+    /// with links resolved, and a path that can't be resolved is in none.
+    pub(crate) fn trusted_directory(&self, path: &Path) -> Option<&Path> {
+        let path = canonical_path(path)?;
+        self.trusted_directories
+            .iter()
+            .map(PathBuf::as_path)
+            .find(|dir| {
+                let dir =
+                    canonical_path(dir).unwrap_or_else(|| dir.to_path_buf());
+                is_within(&path, &dir)
+            })
+    }
+}
+
+/// The full registry path of a policy value, for messages.
+pub(crate) fn value_path(name: &str) -> String {
+    format!(
+        "{}\\{}\\{}",
+        Root::LocalMachine.short_name(),
+        POLICY_KEY,
+        name
+    )
+}
+
+fn parse_operation(name: &str) -> Option<DefaultOperation> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "prompt" => Some(DefaultOperation::Prompt),
+        "open" => Some(DefaultOperation::Open),
+        "execute" => Some(DefaultOperation::Execute),
+        _ => None,
+    }
+}
+
+fn parse_mark_of_the_web(name: &str) -> Option<MarkOfTheWeb> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "ignore" => Some(MarkOfTheWeb::Ignore),
+        "prompt" => Some(MarkOfTheWeb::Prompt),
+        "block" => Some(MarkOfTheWeb::Block),
+        _ => None,
+    }
+}
+
+fn read_flag(registry: &dyn RegistryBackend, name: &str) -> bool {
+    matches!(
+        registry.read_value(Root::LocalMachine, POLICY_KEY, name),
+        Some(RegValue::Dword(n)) if n != 0
+    )
+}
+
+fn read_list(registry: &dyn RegistryBackend, name: &str) -> Vec<String> {
+    let mut items =
+        match registry.read_value(Root::LocalMachine, POLICY_KEY, name) {
+            Some(RegValue::MultiSz(items)) => items,
+            Some(value) => {
+                value.as_str().map(str::to_string).into_iter().collect()
+            }
+            None => Vec::new(),
+        };
+    let key = format!("{}\\{}", POLICY_KEY, name);
+    for value in registry.value_names(Root::LocalMachine, &key) {
+        items.extend(registry.read_string(Root::LocalMachine, &key, &value));
+    }
+    items.retain(|item| !item.trim().is_empty());
+    items
+}
+
+/// Whether `path` is `dir` or below it, comparing components
+/// case-insensitively as Windows does.
+fn is_within(path: &Path, dir: &Path) -> bool {
+    let lower = |p: &Path| -> Vec<String> {
+        p.components()
+            .map(|c| c.as_os_str().to_string_lossy().to_lowercase())
+            .collect()
+    };
+    let (path, dir) = (lower(path), lower(dir));
+    !dir.is_empty() && path.starts_with(&dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::MemoryRegistry;
+    use std::{env, fs};
+
+    #[test]
+    fn reads_every_setting() {
+        let reg = MemoryRegistry::new();
+        let root = env::temp_dir()
+            .join(format!("winbang-group-policy-{}", std::process::id()));
+        let repos = root.join("Repos");
+        let write = |name: &str, value: RegValue| {
+            reg.write_value(Root::LocalMachine, POLICY_KEY, name, &value);
+        };
+        write("DefaultOperation", RegValue::Sz("Execute".to_string()));
+        write("DisableUserConfig", RegValue::Dword(1));
+        write("DisableProjectConfig", RegValue::Dword(0));
+        write("MarkOfTheWeb", RegValue::Sz("Block".to_string()));
+        write(
+            "DenyInterpreters",
+            RegValue::MultiSz(vec!["mshta".to_string(), String::new()]),
+        );
+        reg.write_string(
+            Root::LocalMachine,
+            &format!("{}\\DenyInterpreters", POLICY_KEY),
+            "1",
+            "C:\\Windows\\System32\\wscript.exe",
+        );
+        reg.write_string(
+            Root::LocalMachine,
+            &format!("{}\\TrustedDirectories", POLICY_KEY),
+            "1",
+            &repos.to_string_lossy(),
+        );
+
+        let policy = GroupPolicy::load(&reg);
+        assert_eq!(policy.default_operation, Some(DefaultOperation::Execute));
+        assert!(policy.disable_user_config);
+        assert!(!policy.disable_project_config);
+        assert_eq!(policy.mark_of_the_web, Some(MarkOfTheWeb::Block));
+        assert!(policy.denies_interpreter("MSHTA.EXE"));
+        assert!(policy.denies_interpreter("wscript"));
+        assert!(!policy.denies_interpreter("cscript"));
+        for dir in ["Repos", "Repos2"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join("a.sh"), "").unwrap();
+        }
+        let script = repos.join(".").join("a.sh");
+        assert_eq!(policy.trusted_directory(&script), Some(repos.as_path()));
+        let outside = root.join("Repos2").join("a.sh");
+        assert_eq!(policy.trusted_directory(&outside), None);
+        assert_eq!(policy.trusted_directory(&repos.join("missing.sh")), None);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn missing_or_malformed_values_are_unset() {
+        let reg = MemoryRegistry::new();
+        assert_eq!(GroupPolicy::load(&reg), GroupPolicy::default());
+
+        reg.write_string(
+            Root::LocalMachine,
+            POLICY_KEY,
+            "DefaultOperation",
+            "x",
+        );
+        reg.write_string(
+            Root::LocalMachine,
+            POLICY_KEY,
+            "DisableUserConfig",
+            "1",
+        );
+        assert_eq!(GroupPolicy::load(&reg), GroupPolicy::default());
+    }
+}
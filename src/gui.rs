@@ -1,15 +1,36 @@
-use crate::log_debug;
 use crate::script::ScriptMetadata;
+use crate::signature::Signature;
+use crate::zone::ZoneInfo;
+use crate::{log_error, log_info};
 use std::io;
 use std::path::PathBuf;
 use std::process::Command;
+#[cfg(target_os = "windows")]
 use windows::Win32::Foundation::{HINSTANCE, HWND};
+#[cfg(target_os = "windows")]
 use windows::Win32::UI::Controls::{
-    TASKDIALOG_BUTTON, TASKDIALOGCONFIG, TDF_ALLOW_DIALOG_CANCELLATION,
-    TaskDialogIndirect,
+    TASKDIALOG_BUTTON, TASKDIALOGCONFIG, TASKDIALOGCONFIG_0, TD_WARNING_ICON,
+    TDF_ALLOW_DIALOG_CANCELLATION, TaskDialogIndirect,
 };
+#[cfg(target_os = "windows")]
+use windows::Win32::UI::WindowsAndMessaging::{
+    MB_ICONERROR, MB_OK, MessageBoxW,
+};
+#[cfg(target_os = "windows")]
 use windows::core::PCWSTR;
 
+/// Something the prompt must point out before the user decides.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub(crate) enum Notice<'a> {
+    /// The script changed since the user last ran it: a summary.
+    Changed(&'a str),
+    /// The script was downloaded from the internet.
+    Downloaded(&'a ZoneInfo),
+    /// Policy wants a valid signature to run the script without asking.
+    Unsigned(&'a Signature),
+}
+
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
 pub(crate) enum UserChoice {
     Run,
     Edit,
@@ -22,6 +43,7 @@ pub(crate) enum UserChoice {
 ///
 /// * `script`: Path to the script.
 /// * `editor`: Path to the editor.
+/// * `notice`: What to warn about, if anything.
 ///
 /// returns: Result<UserChoice, Error>
 ///
@@ -30,11 +52,13 @@ pub(crate) enum UserChoice {
 /// ```
 /// let script_path = Path::new("example_script.sh");
 /// let editor = "notepad";
-/// let user_choice = interactive_prompt(script_path, editor)?;
+/// let user_choice = interactive_prompt(script_path, editor, None)?;
 /// ```
+#[cfg(target_os = "windows")]
 pub(crate) fn interactive_prompt(
     script: &ScriptMetadata,
     editor: &str,
+    notice: Option<Notice>,
 ) -> io::Result<UserChoice> {
     const ID_RUN: i32 = 1001;
     const ID_EDIT: i32 = 1002;
@@ -58,10 +82,39 @@ pub(crate) fn interactive_prompt(
     .encode_utf16()
     .collect();
 
-    let content: Vec<u16> =
-        format!("\"{}\" is an executable text file.\0", script_name)
+    let content: Vec<u16> = match &notice {
+        Some(Notice::Changed(summary)) => format!(
+            "\"{}\" changed since you last ran it: {}.\0",
+            script_name, summary
+        ),
+        Some(Notice::Downloaded(zone)) => format!(
+            "{}\n\nOnly run scripts from sources you trust.\0",
+            zone.describe_source()
+        ),
+        Some(Notice::Unsigned(signature)) => format!(
+            "\"{}\" needs a valid signature to run without asking. Its \
+             signature is {}.\0",
+            script_name, signature
+        ),
+        None => format!("\"{}\" is an executable text file.\0", script_name),
+    }
+    .encode_utf16()
+    .collect();
+
+    // Downloaded scripts get a warning icon and headline.
+    let (instruction, icon): (Vec<u16>, PCWSTR) = match &notice {
+        Some(Notice::Downloaded(zone)) => (
+            format!(
+                "Warning: \"{}\" came from the {} zone\0",
+                script_name,
+                zone.zone_name()
+            )
             .encode_utf16()
-            .collect();
+            .collect(),
+            TD_WARNING_ICON,
+        ),
+        _ => (vec![0], PCWSTR::null()),
+    };
 
     let buttons = [
         TASKDIALOG_BUTTON {
@@ -85,6 +138,8 @@ pub(crate) fn interactive_prompt(
         hwndParent: HWND(std::ptr::null_mut()),
         hInstance: HINSTANCE(std::ptr::null_mut()),
         pszWindowTitle: PCWSTR(title.as_ptr()),
+        pszMainInstruction: PCWSTR(instruction.as_ptr()),
+        Anonymous1: TASKDIALOGCONFIG_0 { pszMainIcon: icon },
         pszContent: PCWSTR(content.as_ptr()),
         cButtons: buttons.len() as u32,
         pButtons: buttons.as_ptr(),
@@ -96,38 +151,91 @@ pub(crate) fn interactive_prompt(
     unsafe {
         // ComCtl32 v6 is required and is enabled via app.manifest
         TaskDialogIndirect(&config, Some(&mut selected_button), None, None)
-            .map_err(|e| {
-                io::Error::new(io::ErrorKind::Other, format!("{e}"))
-            })?;
+            .map_err(|e| io::Error::other(format!("{e}")))?;
     }
 
     match selected_button {
         ID_RUN => Ok(UserChoice::Run),
         ID_EDIT => {
-            let editor_path = which::which(editor)
-                .unwrap_or_else(|_| PathBuf::from("notepad")); // fallback
-
-            log_debug!(&format!(
-                "User chose to edit the script: {:?} with editor: {:?}",
-                script, editor_path
-            ));
-
-            match Command::new(editor_path)
-                .arg::<&PathBuf>(&script.file_path)
-                .spawn()
-            {
-                Ok(mut child) => {
-                    if let Err(_e) = child.wait() {
-                        log_debug!(&format!("Editor wait() failed: {}", _e));
-                    }
-                }
-                Err(_e) => {
-                    log_debug!(&format!("Editor spawn() failed: {}", _e));
-                }
-            }
-
+            open_in_editor(script, editor);
             Ok(UserChoice::Edit)
         }
         _ => Ok(UserChoice::Exit),
     }
 }
+
+/// Task Dialogs are Windows-only; elsewhere the prompt is unavailable and
+/// callers fall back to their non-interactive behavior.
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn interactive_prompt(
+    _script: &ScriptMetadata,
+    _editor: &str,
+    _notice: Option<Notice>,
+) -> io::Result<UserChoice> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "interactive prompt requires Windows",
+    ))
+}
+
+/// Show an error in a message box, for launches from a GUI shell where there
+/// is no console to print to.
+///
+/// # Arguments
+///
+/// * `title`: Window title.
+/// * `text`: Message.
+///
+/// returns: ()
+///
+/// # Examples
+///
+/// ```
+/// show_error("\"build.sh\" was blocked", "Scripts in Downloads may not run.");
+/// ```
+#[cfg(target_os = "windows")]
+pub(crate) fn show_error(title: &str, text: &str) {
+    let wide = |s: &str| -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    };
+    let (title, text) = (wide(title), wide(text));
+    unsafe {
+        MessageBoxW(
+            None,
+            PCWSTR(text.as_ptr()),
+            PCWSTR(title.as_ptr()),
+            MB_OK | MB_ICONERROR,
+        );
+    }
+}
+
+/// Message boxes are Windows-only; elsewhere errors go to stderr alone.
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn show_error(_title: &str, _text: &str) {}
+
+/// Open the script in the editor and wait for it to exit.
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn open_in_editor(script: &ScriptMetadata, editor: &str) {
+    let editor_path =
+        which::which(editor).unwrap_or_else(|_| PathBuf::from("notepad")); // fallback
+
+    log_info!(
+        "User chose to edit the script: {:?} with editor: {:?}",
+        script,
+        editor_path
+    );
+
+    match Command::new(editor_path)
+        .arg::<&PathBuf>(&script.file_path)
+        .spawn()
+    {
+        Ok(mut child) => {
+            if let Err(e) = child.wait() {
+                log_error!("Editor wait() failed: {}", e);
+            }
+        }
+        Err(e) => {
+            log_error!("Editor spawn() failed: {}", e);
+        }
+    }
+}
@@ -1,27 +1,52 @@
-#![cfg(target_os = "windows")]
-
 use std::env;
-use std::path::PathBuf;
+use std::path::Path;
+
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+
+use crate::cmdline::{launches, quote_arg, split_command_line};
+use crate::config::{Config, VerbPosition};
+use crate::manifest;
+use crate::platform::is_elevated;
+use crate::registry::{RegistryBackend, Root};
+use crate::{log_debug, log_info, log_warn};
+
+/// Where Winbang's verbs and associations are registered.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Scope {
+    /// The current user (`HKCU\Software\Classes`); no elevation needed.
+    #[default]
+    User,
+    /// Every user (`HKLM\Software\Classes`); needs an elevated prompt.
+    Machine,
+}
 
-use crate::log_debug;
-use crate::registry::{Root, value_exists, write_string};
+impl Scope {
+    pub(crate) fn root(self) -> Root {
+        match self {
+            Scope::User => Root::CurrentUser,
+            Scope::Machine => Root::LocalMachine,
+        }
+    }
+}
 
 /// File-name component of current exe.
 pub fn self_exe_basename() -> Option<String> {
-    env::current_exe()
-        .ok()
-        .as_ref()
-        .and_then(|p: &PathBuf| p.file_name())
-        .map(|s| s.to_string_lossy().to_string())
+    env::current_exe().ok().as_deref().and_then(exe_basename)
 }
 
-/// `Applications\<exe-name>` ProgID. Used by verb dispatcher.
-pub fn self_progid() -> Option<String> {
-    self_exe_basename().map(|name| format!("Applications\\{}", name))
+fn exe_basename(exe: &Path) -> Option<String> {
+    exe.file_name().map(|s| s.to_string_lossy().to_string())
 }
 
-fn shell_key_root() -> Option<String> {
-    self_progid().map(|p| format!("Software\\Classes\\{}\\shell", p))
+/// `Applications\<exe-name>` ProgID for the given exe file name.
+pub fn progid_for(exe_name: &str) -> String {
+    format!("Applications\\{}", exe_name)
+}
+
+fn shell_key_root(exe: &Path) -> Option<String> {
+    exe_basename(exe)
+        .map(|name| format!("Software\\Classes\\{}\\shell", progid_for(&name)))
 }
 
 struct VerbSpec {
@@ -68,67 +93,777 @@ const VERBS: &[VerbSpec] = &[
     },
 ];
 
+/// Value under the shell key recording the Winbang version, verb set and
+/// exe path the verbs were last written for.
+const STAMP_VALUE: &str = "WinbangStamp";
+
+/// Run on every launch: if the stamp under the shell key doesn't match this
+/// exe and config, do what `install_verbs` does. A matching stamp costs two
+/// registry reads: whether HKLM holds a stamp, then the stamp itself.
+///
+/// A machine-wide install takes over: per-user verbs would shadow it in
+/// HKCR, so none are written once HKLM holds a stamp, and the machine's
+/// verbs are only repaired from an elevated process.
+pub fn ensure_verbs_registered(
+    registry: &dyn RegistryBackend,
+    config: &Config,
+) {
+    with_current_exe(|exe| self_heal(registry, exe, config, is_elevated()));
+}
+
+fn self_heal(
+    registry: &dyn RegistryBackend,
+    exe: &Path,
+    config: &Config,
+    elevated: bool,
+) {
+    if !installed_at(registry, exe, Root::LocalMachine) {
+        ensure_at(registry, exe, config, Root::CurrentUser);
+    } else if elevated {
+        ensure_at(registry, exe, config, Root::LocalMachine);
+    } else {
+        log_debug!(
+            "ensure_verbs_registered: machine-wide install present; \
+             leaving the verbs alone"
+        );
+    }
+}
+
+/// Whether Winbang's verbs were installed under `root`.
+pub(crate) fn installed_at(
+    registry: &dyn RegistryBackend,
+    exe: &Path,
+    root: Root,
+) -> bool {
+    shell_key_root(exe)
+        .is_some_and(|shell| registry.value_exists(root, &shell, STAMP_VALUE))
+}
+
+fn ensure_at(
+    registry: &dyn RegistryBackend,
+    exe: &Path,
+    config: &Config,
+    root: Root,
+) {
+    let Some(shell_root) = shell_key_root(exe) else {
+        return;
+    };
+    let current = registry.read_string(root, &shell_root, STAMP_VALUE);
+    let wanted = stamp(exe, &planned_verbs(config));
+    if current.as_deref() == Some(wanted.as_str()) {
+        return;
+    }
+    log_debug!(
+        "ensure_verbs_registered: stamp {:?} != {:?}; syncing verbs",
+        current,
+        wanted
+    );
+    write_verbs(registry, exe, config, root, false);
+}
+
 /// Idempotently fill in any missing verb subkeys under
-/// `HKCU\Software\Classes\Applications\<exe-name>\shell`.
-pub fn ensure_verbs_registered() {
-    write_verbs(false);
+/// `Software\Classes\Applications\<exe-name>\shell` in `scope`'s hive, bring
+/// config-defined verbs in line with `[context_menu]`, repair commands that
+/// launch a Winbang exe at another path, and remove verbs Winbang registered
+/// earlier that are no longer configured.
+pub fn install_verbs(
+    registry: &dyn RegistryBackend,
+    config: &Config,
+    scope: Scope,
+) {
+    with_current_exe(|exe| {
+        write_verbs(registry, exe, config, scope.root(), false)
+    });
 }
 
 /// Unconditionally overwrite every verb subkey under
-/// `HKCU\Software\Classes\Applications\<exe-name>\shell`.
-pub fn reinstall_verbs() {
-    write_verbs(true);
+/// `Software\Classes\Applications\<exe-name>\shell` in `scope`'s hive.
+pub fn reinstall_verbs(
+    registry: &dyn RegistryBackend,
+    config: &Config,
+    scope: Scope,
+) {
+    with_current_exe(|exe| {
+        write_verbs(registry, exe, config, scope.root(), true)
+    });
 }
 
-fn write_verbs(force: bool) {
-    let exe = match env::current_exe() {
-        Ok(p) => p,
+fn with_current_exe(f: impl FnOnce(&Path)) {
+    match env::current_exe() {
+        Ok(exe) => f(&exe),
         Err(e) => {
-            log_debug!(&format!("write_verbs: current_exe() failed: {}", e));
-            return;
+            log_warn!("write_verbs: current_exe() failed: {}", e);
+        }
+    }
+}
+
+/// A verb as it should appear under Winbang's shell key.
+struct PlannedVerb {
+    name: String,
+    label: String,
+    args: String,
+    icon: Option<String>,
+    position: Option<VerbPosition>,
+    extended: bool,
+    /// Defined (or customized) in config, so kept in sync with it rather
+    /// than only filled in when missing.
+    from_config: bool,
+}
+
+/// The built-in verbs plus every `[context_menu]` entry. An entry named like
+/// a built-in customizes its display options.
+fn planned_verbs(config: &Config) -> Vec<PlannedVerb> {
+    let mut planned: Vec<PlannedVerb> = VERBS
+        .iter()
+        .map(|spec| PlannedVerb {
+            name: spec.name.to_string(),
+            label: spec.label.to_string(),
+            args: spec.args_template.to_string(),
+            icon: None,
+            position: None,
+            extended: false,
+            from_config: false,
+        })
+        .collect();
+
+    for (name, entry) in config.context_menu.iter().flatten() {
+        let existing = planned
+            .iter_mut()
+            .find(|verb| verb.name.eq_ignore_ascii_case(name));
+        let verb = match existing {
+            Some(verb) => verb,
+            None => {
+                planned.push(PlannedVerb {
+                    name: name.clone(),
+                    label: name.clone(),
+                    args: format!(
+                        "dispatch-verb --verb {} --file \"%1\" %*",
+                        quote_arg(name)
+                    ),
+                    icon: None,
+                    position: None,
+                    extended: false,
+                    from_config: true,
+                });
+                planned.last_mut().expect("just pushed")
+            }
+        };
+        if let Some(label) = &entry.label {
+            verb.label = label.clone();
+        }
+        verb.icon = entry.icon.clone();
+        verb.position = entry.position;
+        verb.extended = entry.extended;
+        verb.from_config = true;
+    }
+    planned
+}
+
+/// `<version>|<verb set hash>|<exe path>`: anything that changes what
+/// `write_verbs` would write changes the stamp. The hash is the start of a
+/// SHA-256, so it only changes with the verbs, not with the toolchain.
+fn stamp(exe: &Path, planned: &[PlannedVerb]) -> String {
+    let mut hasher = Sha256::new();
+    // Length-prefix every field so no two verb sets hash the same bytes.
+    let mut field = |value: Option<&str>| match value {
+        Some(value) => {
+            hasher.update((value.len() as u64 + 1).to_le_bytes());
+            hasher.update(value.as_bytes());
         }
+        None => hasher.update(0u64.to_le_bytes()),
     };
+    for verb in planned {
+        field(Some(&verb.name));
+        field(Some(&verb.label));
+        field(Some(&verb.args));
+        field(verb.icon.as_deref());
+        field(verb.position.map(VerbPosition::as_registry_str));
+        field(Some(if verb.extended { "1" } else { "0" }));
+        field(Some(if verb.from_config { "1" } else { "0" }));
+    }
+    let digest = hasher.finalize();
+    let hash: String =
+        digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}|{}|{}", env!("CARGO_PKG_VERSION"), hash, exe.display())
+}
+
+fn write_verbs(
+    registry: &dyn RegistryBackend,
+    exe: &Path,
+    config: &Config,
+    root: Root,
+    force: bool,
+) {
     let exe_str = exe.to_string_lossy().to_string();
-    let shell_root = match shell_key_root() {
-        Some(s) => s,
-        None => {
-            log_debug!(
-                "write_verbs: could not determine self exe basename; skipping"
+    let (Some(exe_name), Some(shell_root)) =
+        (exe_basename(exe), shell_key_root(exe))
+    else {
+        log_warn!(
+            "write_verbs: could not determine self exe basename; skipping"
+        );
+        return;
+    };
+
+    let planned = planned_verbs(config);
+    for verb in &planned {
+        let verb_key = format!("{}\\{}", shell_root, verb.name);
+        let command_key = format!("{}\\command", verb_key);
+        let overwrite = force || verb.from_config;
+        let command = format!("\"{}\" {}", exe_str, verb.args);
+        let position = verb.position.map(VerbPosition::as_registry_str);
+
+        let values = [
+            (verb_key.as_str(), "", Some(verb.label.as_str())),
+            (verb_key.as_str(), "Icon", verb.icon.as_deref()),
+            (verb_key.as_str(), "Position", position),
+            (verb_key.as_str(), "Extended", verb.extended.then_some("")),
+            (command_key.as_str(), "", Some(command.as_str())),
+        ];
+        for (key, name, data) in values {
+            sync_value(registry, root, key, name, data, overwrite);
+        }
+        if !overwrite {
+            repair_command(
+                registry,
+                root,
+                &command_key,
+                &command,
+                &exe_str,
+                &exe_name,
             );
-            return;
         }
+        let current = registry.read_string(root, &command_key, "");
+        if current.is_some_and(|c| dispatches_to(&c, &exe_name)) {
+            manifest::record_key(registry, root, &verb_key);
+        }
+    }
+
+    remove_stale_verbs(registry, root, &shell_root, &exe_name, &planned);
+    let stamp = stamp(exe, &planned);
+    sync_value(registry, root, &shell_root, STAMP_VALUE, Some(&stamp), true);
+}
+
+/// Rewrite a verb command that launches a Winbang exe at another path, e.g.
+/// after the exe moved. Commands that run anything else are the user's and
+/// are left alone.
+fn repair_command(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    command_key: &str,
+    command: &str,
+    exe: &str,
+    exe_name: &str,
+) {
+    let Some(current) = registry.read_string(root, command_key, "") else {
+        return;
     };
+    let program = split_command_line(&current).into_iter().next();
+    let elsewhere =
+        program.is_some_and(|program| !program.eq_ignore_ascii_case(exe));
+    if launches(&current, exe_name) && elsewhere {
+        log_info!(
+            "write_verbs: repairing stale {}: {:?} -> {:?}",
+            command_key,
+            current,
+            command
+        );
+        manifest::track_value(registry, root, command_key, "");
+        registry.write_string(root, command_key, "", command);
+    }
+}
 
-    for spec in VERBS {
-        let verb_key = format!("{}\\{}", shell_root, spec.name);
-        let command_key = format!("{}\\command", verb_key);
+/// Bring one value in line with `data`. Without `overwrite`, a value that is
+/// already present is left alone and an unwanted one is not removed.
+fn sync_value(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    key: &str,
+    name: &str,
+    data: Option<&str>,
+    overwrite: bool,
+) {
+    let current = registry.read_string(root, key, name);
+    let exists = registry.value_exists(root, key, name);
+    match data {
+        Some(data)
+            if !exists || (overwrite && current.as_deref() != Some(data)) =>
+        {
+            manifest::track_value(registry, root, key, name);
+            let ok = registry.write_string(root, key, name, data);
+            log_debug!(
+                "write_verbs: wrote {}\\[{}] = {:?} -> {}",
+                key,
+                name,
+                data,
+                ok
+            );
+        }
+        None if exists && overwrite => {
+            let ok = registry.delete_value(root, key, name);
+            log_debug!("write_verbs: removed {}\\[{}] -> {}", key, name, ok);
+        }
+        _ => {
+            log_debug!(
+                "write_verbs: {}\\[{}] up to date, leaving alone",
+                key,
+                name
+            );
+        }
+    }
+}
 
-        if force || !value_exists(Root::CurrentUser, &verb_key, "") {
-            let ok = write_string(Root::CurrentUser, &verb_key, "", spec.label);
-            log_debug!(&format!(
-                "write_verbs: wrote label for {} -> {}",
-                spec.name, ok
-            ));
-        } else {
-            log_debug!(&format!(
-                "write_verbs: label for {} already present, leaving alone",
-                spec.name
-            ));
+/// Delete verbs that dispatch back into Winbang but are no longer planned,
+/// e.g. after a `[context_menu]` entry is removed. Verbs with any other
+/// command were not created by Winbang and are kept.
+fn remove_stale_verbs(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    shell_root: &str,
+    exe_name: &str,
+    planned: &[PlannedVerb],
+) {
+    for name in registry.subkeys(root, shell_root) {
+        if planned.iter().any(|v| v.name.eq_ignore_ascii_case(&name)) {
+            continue;
         }
+        let verb_key = format!("{}\\{}", shell_root, name);
+        let command = registry
+            .read_string(root, &format!("{}\\command", verb_key), "")
+            .unwrap_or_default();
+        if dispatches_to(&command, exe_name) {
+            let ok = registry.delete_tree(root, &verb_key);
+            manifest::forget_key(registry, root, &verb_key);
+            log_info!("write_verbs: removed stale verb {} -> {}", name, ok);
+        }
+    }
+}
 
-        if force || !value_exists(Root::CurrentUser, &command_key, "") {
-            let command = format!("\"{}\" {}", exe_str, spec.args_template);
-            let ok =
-                write_string(Root::CurrentUser, &command_key, "", &command);
-            log_debug!(&format!(
-                "write_verbs: wrote command for {} -> {} ({})",
-                spec.name, ok, command
-            ));
-        } else {
-            log_debug!(&format!(
-                "write_verbs: command for {} already present, leaving alone",
-                spec.name
-            ));
+/// Remove every registry key and value the manifest says Winbang created,
+/// then Winbang's own key, in `scope`'s hive. With `dry_run`, nothing is
+/// removed.
+///
+/// # Arguments
+///
+/// * `registry`: Registry to clean up.
+/// * `scope`: Per-user or machine-wide install to remove.
+/// * `dry_run`: Only report what would be removed.
+///
+/// returns: Vec<Entry> — the entries removed (or that would be).
+///
+/// # Examples
+///
+/// ```
+/// for entry in uninstall(&registry, Scope::User, true) {
+///     println!("would remove {}", entry);
+/// }
+/// ```
+pub fn uninstall(
+    registry: &dyn RegistryBackend,
+    scope: Scope,
+    dry_run: bool,
+) -> Vec<manifest::Entry> {
+    let removals = manifest::removals(registry, scope.root());
+    if !dry_run {
+        for entry in &removals {
+            let ok = manifest::remove(registry, entry);
+            log_info!("uninstall: removed {} -> {}", entry, ok);
         }
     }
+    removals
+}
+
+/// Whether a verb command dispatches back into Winbang, i.e. it launches
+/// `exe` with a `dispatch-verb` argument. Only Winbang writes such commands.
+fn dispatches_to(command: &str, exe: &str) -> bool {
+    launches(command, exe)
+        && split_command_line(command)
+            .iter()
+            .any(|arg| arg == "dispatch-verb")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{MemoryRegistry, PlanRegistry, RegValue, regfile};
+
+    const SHELL: &str = "Software\\Classes\\Applications\\winbang.exe\\shell";
+
+    /// Forward slashes so the file name parses on every host OS.
+    fn exe() -> &'static Path {
+        Path::new("C:/Tools/winbang.exe")
+    }
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn read(reg: &MemoryRegistry, key: &str, name: &str) -> Option<String> {
+        reg.read_string(Root::CurrentUser, &format!("{SHELL}\\{key}"), name)
+    }
+
+    #[test]
+    fn registers_every_verb() {
+        let reg = MemoryRegistry::new();
+        write_verbs(&reg, exe(), &config(""), Root::CurrentUser, false);
+
+        let names = reg.subkeys(Root::CurrentUser, SHELL);
+        assert_eq!(names.len(), VERBS.len());
+        assert_eq!(
+            reg.read_string(
+                Root::CurrentUser,
+                &format!("{SHELL}\\open\\command"),
+                ""
+            ),
+            Some("\"C:/Tools/winbang.exe\" \"%1\" %*".to_string())
+        );
+        assert_eq!(
+            reg.read_string(Root::CurrentUser, &format!("{SHELL}\\runas"), ""),
+            Some("Run as administrator".to_string())
+        );
+        // Visible through the merged HKCR view the dispatcher reads.
+        assert!(reg.key_exists(
+            Root::ClassesRoot,
+            "Applications\\winbang.exe\\shell\\edit"
+        ));
+    }
+
+    #[test]
+    fn ensure_leaves_existing_values_alone() {
+        let reg = MemoryRegistry::new();
+        let edit = format!("{SHELL}\\edit\\command");
+        reg.write_string(Root::CurrentUser, &edit, "", "custom");
+
+        write_verbs(&reg, exe(), &config(""), Root::CurrentUser, false);
+        assert_eq!(
+            reg.read_string(Root::CurrentUser, &edit, ""),
+            Some("custom".to_string())
+        );
+
+        write_verbs(&reg, exe(), &config(""), Root::CurrentUser, true);
+        assert_eq!(
+            reg.read_string(Root::CurrentUser, &edit, ""),
+            Some(
+                "\"C:/Tools/winbang.exe\" dispatch-verb --verb edit --file \"%1\" %*"
+                    .to_string()
+            )
+        );
+    }
+
+    const MENU: &str = r#"
+        [context_menu."Run with arguments"]
+        action = "run-with-args"
+        icon = "C:\\Tools\\winbang.exe,0"
+        position = "top"
+
+        [context_menu.lint]
+        template = "ruff.exe check \"%1\""
+        label = "Lint"
+        extended = true
+
+        [context_menu.edit]
+        label = "Edit script"
+        "#;
+
+    #[test]
+    fn registers_context_menu_entries() {
+        let reg = MemoryRegistry::new();
+        write_verbs(&reg, exe(), &config(MENU), Root::CurrentUser, false);
+
+        assert_eq!(
+            reg.subkeys(Root::CurrentUser, SHELL).len(),
+            VERBS.len() + 2
+        );
+        assert_eq!(
+            read(&reg, "Run with arguments\\command", "").as_deref(),
+            Some(
+                "\"C:/Tools/winbang.exe\" dispatch-verb --verb \
+                 \"Run with arguments\" --file \"%1\" %*"
+            )
+        );
+        assert_eq!(
+            read(&reg, "Run with arguments", "Icon").as_deref(),
+            Some("C:\\Tools\\winbang.exe,0")
+        );
+        assert_eq!(
+            read(&reg, "Run with arguments", "Position").as_deref(),
+            Some("Top")
+        );
+        assert_eq!(read(&reg, "lint", "").as_deref(), Some("Lint"));
+        assert_eq!(read(&reg, "lint", "Extended").as_deref(), Some(""));
+        // Customizing a built-in keeps its command.
+        assert_eq!(read(&reg, "edit", "").as_deref(), Some("Edit script"));
+        assert!(
+            read(&reg, "edit\\command", "")
+                .unwrap()
+                .contains("--verb edit")
+        );
+    }
+
+    #[test]
+    fn config_changes_update_and_remove_verbs() {
+        let reg = MemoryRegistry::new();
+        write_verbs(&reg, exe(), &config(MENU), Root::CurrentUser, false);
+
+        // A user-made verb that doesn't dispatch into Winbang is never removed.
+        reg.write_string(
+            Root::CurrentUser,
+            &format!("{SHELL}\\mine\\command"),
+            "",
+            "notepad.exe %1",
+        );
+
+        let changed = r#"
+            [context_menu.lint]
+            template = "ruff.exe check \"%1\""
+            label = "Lint with ruff"
+            "#;
+        write_verbs(&reg, exe(), &config(changed), Root::CurrentUser, false);
+
+        assert_eq!(read(&reg, "lint", "").as_deref(), Some("Lint with ruff"));
+        assert_eq!(read(&reg, "lint", "Extended"), None);
+        assert!(!reg.key_exists(
+            Root::CurrentUser,
+            &format!("{SHELL}\\Run with arguments")
+        ));
+        assert!(reg.key_exists(Root::CurrentUser, &format!("{SHELL}\\mine")));
+        // Built-ins stay, but a label customized earlier is left alone
+        // unless forced.
+        assert_eq!(read(&reg, "edit", "").as_deref(), Some("Edit script"));
+        write_verbs(&reg, exe(), &config(changed), Root::CurrentUser, true);
+        assert_eq!(read(&reg, "edit", "").as_deref(), Some("Edit"));
+    }
+
+    #[test]
+    fn uninstall_removes_only_what_winbang_created() {
+        let reg = MemoryRegistry::new();
+        let progid = "Software\\Classes\\Applications\\winbang.exe";
+        // Explorer's "Open with" made the ProgID and its open verb.
+        reg.write_string(
+            Root::CurrentUser,
+            &format!("{SHELL}\\open\\command"),
+            "",
+            "\"C:/Tools/winbang.exe\" \"%1\"",
+        );
+        write_verbs(&reg, exe(), &config(MENU), Root::CurrentUser, false);
+        reg.write_string(
+            Root::CurrentUser,
+            &format!("{SHELL}\\mine\\command"),
+            "",
+            "notepad.exe %1",
+        );
+
+        let planned = uninstall(&reg, Scope::User, true);
+        assert!(planned.len() > VERBS.len());
+        assert!(reg.key_exists(Root::CurrentUser, &format!("{SHELL}\\edit")));
+
+        assert_eq!(uninstall(&reg, Scope::User, false), planned);
+        assert_eq!(reg.subkeys(Root::CurrentUser, SHELL), ["mine", "open"]);
+        assert_eq!(read(&reg, "open", ""), None);
+        assert!(reg.key_exists(Root::CurrentUser, progid));
+        assert!(!reg.key_exists(Root::CurrentUser, manifest::WINBANG_KEY));
+        assert!(uninstall(&reg, Scope::User, true).is_empty());
+    }
+
+    #[test]
+    fn uninstall_adopts_verbs_from_before_the_manifest() {
+        let reg = MemoryRegistry::new();
+        write_verbs(&reg, exe(), &config(""), Root::CurrentUser, false);
+        reg.delete_tree(Root::CurrentUser, manifest::WINBANG_KEY);
+
+        write_verbs(&reg, exe(), &config(""), Root::CurrentUser, false);
+        uninstall(&reg, Scope::User, false);
+        // `open` has no `dispatch-verb` argument, so it can't be told apart
+        // from one Explorer wrote and is kept.
+        assert_eq!(reg.subkeys(Root::CurrentUser, SHELL), ["open"]);
+    }
+
+    /// Every value under `key`, recursively, as `(path, name, value)`.
+    fn dump(
+        reg: &MemoryRegistry,
+        key: &str,
+    ) -> Vec<(String, String, Option<RegValue>)> {
+        let mut out = Vec::new();
+        for name in reg.value_names(Root::CurrentUser, key) {
+            let value = reg.read_value(Root::CurrentUser, key, &name);
+            out.push((key.to_string(), name, value));
+        }
+        for sub in reg.subkeys(Root::CurrentUser, key) {
+            out.extend(dump(reg, &format!("{key}\\{sub}")));
+        }
+        out
+    }
+
+    #[test]
+    fn emitted_reg_matches_a_direct_install() {
+        // Quotes and backslashes wherever values get escaped; forward
+        // slashes in the exe so its file name parses on every host OS.
+        let exe = Path::new(r#"C:/Program Files/"Win" bang/winbang.exe"#);
+        let menu = r#"
+            [context_menu.'say "hi"']
+            label = 'Say "hi" \ now'
+            icon = 'C:\icons\a.ico,0'
+            "#;
+        let base = MemoryRegistry::new();
+        base.write_string(
+            Root::CurrentUser,
+            &format!("{SHELL}\\stale\\command"),
+            "",
+            "winbang.exe dispatch-verb --verb stale",
+        );
+
+        let direct = MemoryRegistry::new();
+        direct.write_string(
+            Root::CurrentUser,
+            &format!("{SHELL}\\stale\\command"),
+            "",
+            "winbang.exe dispatch-verb --verb stale",
+        );
+        write_verbs(&direct, exe, &config(menu), Root::CurrentUser, true);
+
+        let plan = PlanRegistry::new(&base);
+        write_verbs(&plan, exe, &config(menu), Root::CurrentUser, true);
+        let text = plan.to_reg();
+        assert!(text.starts_with("Windows Registry Editor Version 5.00\r\n"));
+        // Planning leaves the registry alone.
+        assert!(!base.key_exists(Root::CurrentUser, &format!("{SHELL}\\edit")));
+
+        let replay = MemoryRegistry::new();
+        replay.write_string(
+            Root::CurrentUser,
+            &format!("{SHELL}\\stale\\command"),
+            "",
+            "winbang.exe dispatch-verb --verb stale",
+        );
+        regfile::load(&replay, &text).unwrap();
+        assert_eq!(dump(&replay, "Software"), dump(&direct, "Software"));
+        assert_eq!(
+            read(&replay, "say \"hi\"", "").as_deref(),
+            Some("Say \"hi\" \\ now")
+        );
+        assert!(
+            !replay.key_exists(Root::CurrentUser, &format!("{SHELL}\\stale"))
+        );
+    }
+
+    #[test]
+    fn moved_exe_repairs_only_its_own_commands() {
+        let reg = MemoryRegistry::new();
+        write_verbs(
+            &reg,
+            Path::new("C:/Old/winbang.exe"),
+            &config(""),
+            Root::CurrentUser,
+            false,
+        );
+        reg.write_string(
+            Root::CurrentUser,
+            &format!("{SHELL}\\edit\\command"),
+            "",
+            "notepad.exe \"%1\"",
+        );
+
+        ensure_at(&reg, exe(), &config(""), Root::CurrentUser);
+        assert_eq!(
+            read(&reg, "open\\command", "").as_deref(),
+            Some("\"C:/Tools/winbang.exe\" \"%1\" %*")
+        );
+        assert!(
+            read(&reg, "print\\command", "")
+                .unwrap()
+                .starts_with("\"C:/Tools/winbang.exe\" dispatch-verb")
+        );
+        assert_eq!(
+            read(&reg, "edit\\command", "").as_deref(),
+            Some("notepad.exe \"%1\"")
+        );
+    }
+
+    #[test]
+    fn stamp_hash_is_stable() {
+        let exe = Path::new("C:/Tools/winbang.exe");
+        assert_eq!(
+            stamp(exe, &[]),
+            format!(
+                "{}|e3b0c44298fc1c14|C:/Tools/winbang.exe",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        let config: Config = toml::from_str("").unwrap();
+        let mut planned = planned_verbs(&config);
+        let before = stamp(exe, &planned);
+        assert_eq!(before, stamp(exe, &planned_verbs(&config)));
+        planned[0].extended = !planned[0].extended;
+        assert_ne!(before, stamp(exe, &planned));
+    }
+
+    #[test]
+    fn matching_stamp_skips_the_sync() {
+        let reg = MemoryRegistry::new();
+        ensure_at(&reg, exe(), &config(""), Root::CurrentUser);
+        let stamp = reg.read_string(Root::CurrentUser, SHELL, STAMP_VALUE);
+        assert!(stamp.as_deref().unwrap().ends_with("|C:/Tools/winbang.exe"));
+
+        // A deleted verb stays deleted while the stamp matches...
+        reg.delete_tree(Root::CurrentUser, &format!("{SHELL}\\print"));
+        ensure_at(&reg, exe(), &config(""), Root::CurrentUser);
+        assert_eq!(read(&reg, "print", ""), None);
+
+        // ...but a config change (or a new version or path) resyncs.
+        ensure_at(&reg, exe(), &config(MENU), Root::CurrentUser);
+        assert_eq!(read(&reg, "print", "").as_deref(), Some("Print"));
+        assert_ne!(
+            reg.read_string(Root::CurrentUser, SHELL, STAMP_VALUE),
+            stamp
+        );
+    }
+
+    #[test]
+    fn machine_install_is_recorded_and_removed_per_hive() {
+        let reg = MemoryRegistry::new();
+        write_verbs(&reg, exe(), &config(""), Root::LocalMachine, false);
+        write_verbs(&reg, exe(), &config(""), Root::CurrentUser, false);
+
+        assert!(installed_at(&reg, exe(), Root::LocalMachine));
+        let removed = uninstall(&reg, Scope::Machine, false);
+        assert!(removed.iter().all(|e| e.to_string().contains("HKLM\\")));
+        assert!(!reg.key_exists(Root::LocalMachine, &format!("{SHELL}\\edit")));
+        assert!(reg.key_exists(Root::CurrentUser, &format!("{SHELL}\\edit")));
+    }
+
+    #[test]
+    fn self_heal_defers_to_a_machine_install() {
+        let reg = MemoryRegistry::new();
+        write_verbs(
+            &reg,
+            Path::new("C:/Old/winbang.exe"),
+            &config(""),
+            Root::LocalMachine,
+            false,
+        );
+
+        self_heal(&reg, exe(), &config(""), false);
+        assert!(!reg.key_exists(Root::CurrentUser, SHELL));
+        assert!(
+            reg.read_string(
+                Root::LocalMachine,
+                &format!("{SHELL}\\open\\command"),
+                ""
+            )
+            .unwrap()
+            .contains("C:/Old/")
+        );
+
+        self_heal(&reg, exe(), &config(""), true);
+        assert!(!reg.key_exists(Root::CurrentUser, SHELL));
+        assert!(installed_at(&reg, exe(), Root::LocalMachine));
+        assert_eq!(
+            reg.read_string(
+                Root::ClassesRoot,
+                "Applications\\winbang.exe\\shell\\open\\command",
+                ""
+            )
+            .as_deref(),
+            Some("\"C:/Tools/winbang.exe\" \"%1\" %*")
+        );
+    }
 }
@@ -0,0 +1,193 @@
+//! Script locations: `trusted_paths` and `untrusted_paths`, which adjust
+//! whether a script prompts, and the path rules of `[policy]`.
+//!
+//! Rules match the script's canonical path, with symlinks and junctions
+//! resolved, so a link can neither pull an outside script into a trusted
+//! directory nor hide a script from an untrusted one. The literal directory
+//! part of each rule is resolved the same way.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{self, Component, Path, PathBuf};
+
+use crate::config::Config;
+use crate::env_expand::{current_env, expand_env_vars};
+use crate::policy::{glob_match, normalize_path};
+
+/// The path with every symlink and junction resolved, without the `\\?\`
+/// prefix Windows adds. `None` when the path doesn't exist.
+///
+/// # Arguments
+///
+/// * `path`: Path to resolve, relative to the current directory if not
+///   absolute.
+///
+/// returns: Option<PathBuf>
+///
+/// # Examples
+///
+/// ```
+/// let real = canonical_path(Path::new("scripts\\build.sh"));
+/// ```
+pub(crate) fn canonical_path(path: &Path) -> Option<PathBuf> {
+    fs::canonicalize(path).ok().map(|p| strip_verbatim(&p))
+}
+
+/// The first `untrusted_paths` rule `script` falls under. A script that
+/// can't be resolved is matched by its absolute path instead.
+pub(crate) fn untrusted_rule<'a>(
+    config: &'a Config,
+    script: &Path,
+) -> Option<&'a str> {
+    let path = canonical_path(script).unwrap_or_else(|| {
+        path::absolute(script).unwrap_or_else(|_| script.to_path_buf())
+    });
+    matching_rule(&config.untrusted_paths, &path, &current_env())
+}
+
+/// The first `trusted_paths` rule `script` falls under. A script that
+/// can't be resolved is never trusted.
+pub(crate) fn trusted_rule<'a>(
+    config: &'a Config,
+    script: &Path,
+) -> Option<&'a str> {
+    let path = canonical_path(script)?;
+    matching_rule(&config.trusted_paths, &path, &current_env())
+}
+
+/// The first of `rules` that matches `path`, which should be canonical.
+///
+/// # Arguments
+///
+/// * `rules`: Globs as `path_matches` takes them.
+/// * `path`: Script path.
+/// * `env`: Variables for `%VAR%` expansion.
+///
+/// returns: Option<&str>
+///
+/// # Examples
+///
+/// ```
+/// let rule = matching_rule(&config.trusted_paths, &path, &current_env());
+/// ```
+pub(crate) fn matching_rule<'a>(
+    rules: &'a [String],
+    path: &Path,
+    env: &HashMap<String, String>,
+) -> Option<&'a str> {
+    rules
+        .iter()
+        .find(|rule| path_matches(rule, path, env))
+        .map(String::as_str)
+}
+
+/// Whether `path` matches the glob `rule`, case-insensitively. `*` and `?`
+/// stay within one directory and `**` spans any number; a rule without
+/// wildcards matches that path and everything below it.
+pub(crate) fn path_matches(
+    rule: &str,
+    path: &Path,
+    env: &HashMap<String, String>,
+) -> bool {
+    let path = normalize_path(&path.to_string_lossy());
+    glob_match(&canonical_glob(rule, env), &path)
+}
+
+/// Expand, normalize and resolve the literal directory part of a glob,
+/// which is everything before the component holding the first wildcard.
+fn canonical_glob(rule: &str, env: &HashMap<String, String>) -> String {
+    let mut glob = expand_env_vars(rule, env).replace('\\', "/");
+    if !glob.contains(['*', '?']) {
+        glob = format!("{}/**", glob.trim_end_matches('/'));
+    }
+    let wildcard = glob.find(['*', '?']).unwrap_or(glob.len());
+    let Some(split) = glob[..wildcard].rfind('/') else {
+        return normalize_path(&glob);
+    };
+    let (dir, rest) = glob.split_at(split + 1);
+
+    let dir_path = Path::new(dir);
+    let has_name = dir_path
+        .components()
+        .any(|c| matches!(c, Component::Normal(_)));
+    match canonical_path(dir_path) {
+        Some(real) if dir_path.is_absolute() && has_name => {
+            let real = normalize_path(&real.to_string_lossy());
+            format!("{}/{}", real.trim_end_matches('/'), normalize_path(rest))
+        }
+        _ => normalize_path(&glob),
+    }
+}
+
+fn strip_verbatim(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy();
+    if let Some(unc) = text.strip_prefix("\\\\?\\UNC\\") {
+        PathBuf::from(format!("\\\\{}", unc))
+    } else if let Some(rest) = text.strip_prefix("\\\\?\\") {
+        PathBuf::from(rest)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_without_wildcards_cover_the_directory() {
+        let env =
+            HashMap::from([("REPOS".to_string(), "C:\\Repos".to_string())]);
+        let script = Path::new("C:\\Repos\\tool\\build.sh");
+        assert!(path_matches("%REPOS%", script, &env));
+        assert!(path_matches("c:\\repos\\", script, &env));
+        assert!(path_matches("C:\\Repos\\*\\*.sh", script, &env));
+        assert!(!path_matches("C:\\Repos\\*.sh", script, &env));
+        assert!(!path_matches("C:\\Repo", script, &env));
+        assert_eq!(
+            strip_verbatim(Path::new("\\\\?\\UNC\\server\\share\\a.py")),
+            PathBuf::from("\\\\server\\share\\a.py")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_cannot_escape_the_rules() {
+        use std::env;
+        use std::os::unix::fs::symlink;
+
+        let root = env::temp_dir()
+            .join(format!("winbang-location-{}", std::process::id()));
+        let (repos, outside) = (root.join("repos"), root.join("outside"));
+        fs::create_dir_all(&repos).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(repos.join("ok.sh"), "").unwrap();
+        fs::write(outside.join("evil.sh"), "").unwrap();
+        symlink(&outside, repos.join("escape")).unwrap();
+        symlink(&repos, root.join("alias")).unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            "trusted_paths = [{:?}]\nuntrusted_paths = [{:?}]",
+            repos.to_string_lossy(),
+            outside.join("**").to_string_lossy(),
+        ))
+        .unwrap();
+        let trusted = |p: PathBuf| trusted_rule(&config, &p).is_some();
+        let untrusted = |p: PathBuf| untrusted_rule(&config, &p).is_some();
+
+        assert!(trusted(repos.join("ok.sh")));
+        assert!(trusted(root.join("alias").join("ok.sh")));
+        assert!(!trusted(repos.join("escape").join("evil.sh")));
+        assert!(untrusted(repos.join("escape").join("evil.sh")));
+        assert!(!trusted(repos.join("missing.sh")));
+        let alias = root.join("alias").join("*.sh");
+        let real = canonical_path(&repos.join("ok.sh")).unwrap();
+        assert!(path_matches(
+            &alias.to_string_lossy(),
+            &real,
+            &current_env()
+        ));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
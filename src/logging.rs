@@ -1,37 +1,346 @@
-#[macro_export]
-macro_rules! log_debug {
-    ($msg:expr) => {
-        #[cfg(debug_assertions)]
-        {
-            use std::fs::OpenOptions;
-            use std::io::Write;
-            use std::path::Path;
+//! Diagnostic logging, in every build. Messages at or above the level set
+//! by `--log-level` or `WINBANG_LOG` (default `warn`) go to stderr, or to
+//! the file named by `--log-file` or `WINBANG_LOG_FILE`. A log file is
+//! opened once per process and rotated by size.
+//!
+//! Each line carries a UTC timestamp, the PID, the level and the module:
+//!
+//! ```text
+//! 2025-10-18T09:30:00.123Z 4120 DEBUG dispatch: Operation resolved: Prompt (default)
+//! ```
 
-            let msg = $msg;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fmt, process};
 
-            let appdata = std::env::var("APPDATA").unwrap_or_else(|_| String::from("."));
-            let debug_log_dir = Path::new(&appdata).join("Winbang");
-            let debug_log_path = debug_log_dir.join("debug.log");
+use clap::ValueEnum;
 
-            if !debug_log_dir.exists() {
-                std::fs::create_dir_all(&debug_log_dir).unwrap_or_else(|_| {
-                    eprintln!("Failed to create debug log directory: {:?}", debug_log_dir);
-                });
-            }
+/// Size at which a log file is rotated.
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Rotated log files kept, as `<file>.1` (newest) and up.
+const KEEP_FILES: usize = 3;
+
+/// How much is logged, from nothing to everything.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub(crate) enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn parse(name: &str) -> Option<Level> {
+        Level::from_str(name.trim(), true).ok()
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        })
+    }
+}
+
+struct Logger {
+    level: Level,
+    sink: Mutex<Sink>,
+}
+
+enum Sink {
+    Stderr,
+    File(FileSink),
+}
+
+/// The log file, held open for the life of the process.
+struct FileSink {
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+}
+
+impl FileSink {
+    fn open(path: PathBuf) -> io::Result<FileSink> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if size >= MAX_FILE_BYTES {
+            rotate(&path, KEEP_FILES)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Ok(FileSink {
+            path,
+            file: Some(file),
+            size,
+        })
+    }
 
-            if let Ok(mut file) = OpenOptions::new()
+    fn write(&mut self, line: &str) {
+        if self.size + line.len() as u64 > MAX_FILE_BYTES && self.size > 0 {
+            // The handle must be closed before Windows lets the file move.
+            self.file = None;
+            let _ = rotate(&self.path, KEEP_FILES);
+            self.file = OpenOptions::new()
                 .create(true)
                 .append(true)
-                .open(debug_log_path)
-            {
-                let _ = writeln!(file, "[DEBUG] {}", msg);
-                let _ = file.flush();
+                .open(&self.path)
+                .ok();
+            self.size = 0;
+        }
+        if let Some(file) = &mut self.file
+            && file.write_all(line.as_bytes()).is_ok()
+        {
+            self.size += line.len() as u64;
+        }
+    }
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Start logging. The command-line options win over `WINBANG_LOG` and
+/// `WINBANG_LOG_FILE`. A log file that can't be opened falls back to
+/// stderr. Only the first call has any effect; until then nothing is
+/// logged.
+///
+/// # Arguments
+///
+/// * `level`: `--log-level`, if given.
+/// * `file`: `--log-file`, if given.
+///
+/// returns: ()
+///
+/// # Examples
+///
+/// ```
+/// logging::init(cli.log_level, cli.log_file.clone());
+/// ```
+pub(crate) fn init(level: Option<Level>, file: Option<PathBuf>) {
+    let env_level = env::var("WINBANG_LOG").ok();
+    let level = resolve_level(level, env_level.as_deref());
+    let file = file.or_else(|| {
+        env::var_os("WINBANG_LOG_FILE")
+            .filter(|f| !f.is_empty())
+            .map(PathBuf::from)
+    });
+
+    let sink = match file {
+        Some(path) if level > Level::Off => match FileSink::open(path) {
+            Ok(sink) => Sink::File(sink),
+            Err(e) => {
+                eprintln!("winbang: cannot open the log file: {}", e);
+                Sink::Stderr
             }
+        },
+        _ => Sink::Stderr,
+    };
+    let _ = LOGGER.set(Logger {
+        level,
+        sink: Mutex::new(sink),
+    });
+}
 
-            println!("[DEBUG] {}", msg);
-        }
+/// The level from `--log-level`, else from `WINBANG_LOG`, else `warn`.
+fn resolve_level(cli: Option<Level>, env: Option<&str>) -> Level {
+    if let Some(level) = cli {
+        return level;
+    }
+    match env.filter(|name| !name.trim().is_empty()) {
+        Some(name) => Level::parse(name).unwrap_or_else(|| {
+            eprintln!("winbang: ignoring unknown WINBANG_LOG level {:?}", name);
+            Level::Warn
+        }),
+        None => Level::Warn,
+    }
+}
+
+/// Whether messages at `level` are logged.
+pub(crate) fn enabled(level: Level) -> bool {
+    LOGGER
+        .get()
+        .is_some_and(|logger| level != Level::Off && level <= logger.level)
+}
+
+/// Write one message. Use the `log_*!` macros instead, which skip
+/// formatting for levels that are off.
+pub(crate) fn write(level: Level, module: &str, args: fmt::Arguments) {
+    let Some(logger) = LOGGER.get() else {
+        return;
     };
-    ($fmt:literal, $($arg:tt)*) => {
-        log_debug!(format!($fmt, $($arg)*));
+    let module = module.strip_prefix("winbang::").unwrap_or(module);
+    let line = format!(
+        "{} {} {:<5} {}: {}\n",
+        rfc3339(SystemTime::now()),
+        process::id(),
+        level,
+        module,
+        args
+    );
+    let Ok(mut sink) = logger.sink.lock() else {
+        return;
     };
+    match &mut *sink {
+        Sink::Stderr => {
+            let _ = io::stderr().write_all(line.as_bytes());
+        }
+        Sink::File(file) => file.write(&line),
+    }
+}
+
+/// Shift `<path>.N` to `.N+1`, dropping the oldest of `keep`, and move
+/// `path` to `<path>.1`. With `keep = 0`, `path` is removed.
+///
+/// # Arguments
+///
+/// * `path`: File to rotate.
+/// * `keep`: Rotated files to keep.
+///
+/// returns: Result<(), Error>
+///
+/// # Examples
+///
+/// ```
+/// rotate(Path::new("audit.jsonl"), 5)?;
+/// ```
+pub(crate) fn rotate(path: &Path, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return fs::remove_file(path);
+    }
+    let _ = fs::remove_file(rotated(path, keep));
+    for n in (1..keep).rev() {
+        let from = rotated(path, n);
+        if from.exists() {
+            fs::rename(from, rotated(path, n + 1))?;
+        }
+    }
+    fs::rename(path, rotated(path, 1))
+}
+
+/// `<path>.n`, the `n`th most recent rotation of `path`.
+pub(crate) fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_os_string();
+    path.push(format!(".{}", n));
+    PathBuf::from(path)
+}
+
+/// `time` as RFC 3339 UTC with milliseconds, e.g.
+/// `2025-10-18T09:30:00.123Z`.
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60,
+        since.subsec_millis()
+    )
+}
+
+/// Log at `level` with `format!` arguments.
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($level) {
+            $crate::logging::write($level, module_path!(), format_args!($($arg)+));
+        }
+    };
+}
+
+/// Something failed and Winbang couldn't do what was asked.
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logging::Level::Error, $($arg)+) };
+}
+
+/// Something went wrong, but Winbang carried on.
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logging::Level::Warn, $($arg)+) };
+}
+
+/// A decision worth knowing about: what ran, what was blocked.
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logging::Level::Info, $($arg)+) };
+}
+
+/// How a decision was reached.
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logging::Level::Debug, $($arg)+) };
+}
+
+/// Step-by-step detail, such as each argument expanded.
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)+) => { $crate::log_at!($crate::logging::Level::Trace, $($arg)+) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn levels_come_from_the_cli_then_the_environment() {
+        assert_eq!(resolve_level(None, None), Level::Warn);
+        assert_eq!(resolve_level(None, Some(" Debug ")), Level::Debug);
+        assert_eq!(resolve_level(None, Some("loud")), Level::Warn);
+        assert_eq!(resolve_level(Some(Level::Off), Some("trace")), Level::Off);
+        assert!(Level::Error < Level::Trace);
+    }
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_251_199_250);
+        assert_eq!(rfc3339(time), "2024-02-29T23:59:59.250Z");
+    }
+
+    #[test]
+    fn file_sink_rotates_past_its_limit() {
+        let root =
+            env::temp_dir().join(format!("winbang-log-{}", std::process::id()));
+        let path = root.join("winbang.log");
+        let mut sink = FileSink::open(path.clone()).unwrap();
+        let line = format!("{}\n", "x".repeat(300 * 1024));
+        for _ in 0..10 {
+            sink.write(&line);
+        }
+        assert!(sink.size <= MAX_FILE_BYTES);
+        assert!(rotated(&path, KEEP_FILES).exists());
+        assert!(!rotated(&path, KEEP_FILES + 1).exists());
+        drop(sink);
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
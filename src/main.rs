@@ -1,63 +1,441 @@
+mod actions;
+mod assoc;
+mod audit;
 mod cli;
+mod cmdline;
 mod config;
 mod dispatch;
+mod env_expand;
+mod error;
+mod explain;
+mod group_policy;
 mod gui;
 mod install;
+mod location;
 mod logging;
+mod manifest;
 mod platform;
+mod policy;
+mod progid;
 mod registry;
 mod script;
+mod setup;
+mod signature;
+mod template;
+mod trust;
 mod verb;
+mod zone;
 
-use crate::config::{Config, find_config_path, load_config};
+use crate::audit::{Context, Operation};
+use crate::config::{
+    Config, find_config_path, load_config, load_machine_policy,
+};
 use crate::dispatch::{
     build_command, handle_fallback_dispatch, handle_interactive_dispatch,
+    run_to_exit,
 };
-use crate::platform::is_interactive_parent;
-use crate::script::get_script_metadata;
+use crate::error::WinbangError;
+use crate::group_policy::GroupPolicy;
+use crate::install::Scope;
+use crate::platform::{is_elevated, is_interactive_parent};
+use crate::script::{ScriptMetadata, get_script_metadata};
 use clap::Parser;
 use std::path::PathBuf;
-use std::{env, io, process};
+use std::{env, fs, process};
 
-fn main() -> io::Result<()> {
-    #[cfg(debug_assertions)]
+fn main() {
+    let cli = cli::Cli::parse();
+    logging::init(cli.log_level, cli.log_file.clone());
     if let Ok(cwd) = env::current_dir() {
-        log_debug!(&format!("Current working directory: {:?}", cwd));
+        log_debug!("Current working directory: {:?}", cwd);
     }
 
-    let cli = cli::Cli::parse();
+    let registry = registry::system();
+    let (config_path, config) = match load_active_config(&registry) {
+        Ok(loaded) => loaded,
+        // Without a config, only the default GUI shell is known.
+        Err(e) => process::exit(exit_code(Err(e), &["explorer.exe".into()])),
+    };
+    let gui_shells = config.gui_shells.clone().unwrap_or_default();
 
-    install::ensure_verbs_registered();
+    if cli.writes_machine_scope() && !is_elevated() {
+        let error = WinbangError::Elevation(
+            "--scope machine writes HKEY_LOCAL_MACHINE; run it from an \
+             elevated prompt (Run as administrator), or use --emit-reg to \
+             write a .reg file instead"
+                .to_string(),
+        );
+        process::exit(exit_code(Err(error), &gui_shells));
+    }
 
-    if cli.reinstall_verbs {
-        install::reinstall_verbs();
-        return Ok(());
+    if let Some(path) = &cli.emit_reg {
+        let result = if cli.manages_registry() {
+            let plan = registry::PlanRegistry::new(&registry);
+            manage(
+                &plan,
+                cli.scope,
+                cli.reinstall_verbs,
+                cli.command,
+                &config,
+                true,
+            )
+            .and_then(|code| {
+                fs::write(path, plan.to_reg_bytes()).map_err(|source| {
+                    WinbangError::File {
+                        path: path.clone(),
+                        source,
+                    }
+                })?;
+                println!("Wrote {}", path.display());
+                Ok(code)
+            })
+        } else {
+            Err(WinbangError::Usage(
+                "--emit-reg applies to install, --reinstall-verbs, assoc, \
+                 setup and uninstall"
+                    .to_string(),
+            ))
+        };
+        process::exit(exit_code(result, &gui_shells));
+    }
+
+    if !cli.skips_verb_sync() {
+        install::ensure_verbs_registered(&registry, &config);
+    }
+    if cli.manages_registry() {
+        let result = manage(
+            &registry,
+            cli.scope,
+            cli.reinstall_verbs,
+            cli.command,
+            &config,
+            false,
+        );
+        process::exit(exit_code(result, &gui_shells));
     }
 
     match cli.command {
         Some(cli::Command::DispatchVerb { verb, file, extras }) => {
-            let config = load_active_config();
-            let outcome =
-                verb::dispatch_verb(&verb, &file, &extras, &config);
-            process::exit(outcome.exit_code());
+            audit::begin(&config, Context::Verb, Some(&verb), &file);
+            let result =
+                verb::dispatch_verb(&registry, &verb, &file, &extras, &config);
+            process::exit(finish(result, &gui_shells));
+        }
+        Some(cli::Command::KeepOpen { file, extras }) => {
+            audit::begin(&config, Context::Verb, None, &file);
+            audit::record_operation(Operation::Execute);
+            let result = actions::keep_open(&file, &extras, &config);
+            let code = finish(result, &gui_shells);
+            actions::pause(code);
+            process::exit(code);
+        }
+        Some(cli::Command::Explain { script }) => {
+            let lines = explain::explain(
+                &config,
+                &config_path,
+                script.as_deref(),
+                &zone::NtfsStreams,
+            );
+            for (label, value) in lines {
+                println!("{:<20} {}", label, value);
+            }
+        }
+        Some(cli::Command::Trust { action }) => {
+            process::exit(exit_code(run_trust(action), &gui_shells))
+        }
+        Some(cli::Command::Script(argv)) => {
+            let result = run_script(&argv, &config);
+            process::exit(finish(result, &gui_shells));
+        }
+        _ => {
+            let error = WinbangError::Usage(
+                "usage: winbang <script> [args...]".to_string(),
+            );
+            process::exit(exit_code(Err(error), &gui_shells));
+        }
+    }
+}
+
+/// Turn a command's result into the exit code: its own, or the error's
+/// after telling the user about it.
+fn exit_code(result: Result<i32, WinbangError>, gui_shells: &[String]) -> i32 {
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            log_info!("{}", e);
+            e.report(gui_shells);
+            e.exit_code()
+        }
+    }
+}
+
+/// Close the audit record of a launch and return its exit code, as
+/// `exit_code` does.
+fn finish(result: Result<i32, WinbangError>, gui_shells: &[String]) -> i32 {
+    if let Err(e) = &result {
+        audit::record_operation(match e {
+            WinbangError::Blocked { .. } => Operation::Blocked,
+            _ => Operation::Failed,
+        });
+    }
+    let code = exit_code(result, gui_shells);
+    audit::finish();
+    code
+}
+
+/// Run one of the commands that change Winbang's registration, against
+/// `registry` in `scope`'s hive. With `emit_reg`, `registry` only plans the
+/// changes for a `.reg` file, and the messages say what would happen.
+/// Returns the exit code.
+fn manage(
+    registry: &dyn registry::RegistryBackend,
+    scope: Scope,
+    reinstall_verbs: bool,
+    command: Option<cli::Command>,
+    config: &Config,
+    emit_reg: bool,
+) -> Result<i32, WinbangError> {
+    if reinstall_verbs {
+        install::reinstall_verbs(registry, config, scope);
+        warn_if_shadowed(registry, scope);
+        return Ok(0);
+    }
+    match command {
+        Some(cli::Command::Install) => {
+            install::install_verbs(registry, config, scope);
+            warn_if_shadowed(registry, scope);
+            Ok(0)
+        }
+        Some(cli::Command::Assoc { action }) => {
+            run_assoc(registry, scope, action, emit_reg)
         }
-        Some(cli::Command::Script(argv)) => run_script(&argv),
-        None => {
-            eprintln!("Usage: winbang <script> [args...]");
-            Ok(())
+        Some(cli::Command::Setup { target }) => {
+            run_setup(registry, scope, target, emit_reg)
         }
+        Some(cli::Command::Uninstall { dry_run }) => {
+            uninstall(registry, scope, dry_run, emit_reg);
+            Ok(0)
+        }
+        _ => Err(WinbangError::Usage("not a registry command".to_string())),
     }
 }
 
-fn load_active_config() -> Config {
-    let config_path =
-        find_config_path().unwrap_or_else(|| PathBuf::from("config.toml"));
-    load_config(&config_path)
+/// Per-user verbs take precedence over machine-wide ones in HKCR, so a
+/// leftover per-user install hides the machine-wide one from this user.
+fn warn_if_shadowed(registry: &dyn registry::RegistryBackend, scope: Scope) {
+    if scope != Scope::Machine {
+        return;
+    }
+    let Ok(exe) = env::current_exe() else {
+        return;
+    };
+    if install::installed_at(registry, &exe, registry::Root::CurrentUser) {
+        println!(
+            "Note: your per-user Winbang verbs override the machine-wide \
+             ones; run `winbang uninstall` to remove them."
+        );
+    }
 }
 
-fn run_script(argv: &[String]) -> io::Result<()> {
+fn uninstall(
+    registry: &dyn registry::RegistryBackend,
+    scope: Scope,
+    dry_run: bool,
+    emit_reg: bool,
+) {
+    let removed = install::uninstall(registry, scope, dry_run);
+    let verb = if dry_run || emit_reg {
+        "Would remove"
+    } else {
+        "Removed"
+    };
+    if removed.is_empty() {
+        println!("Nothing to remove.");
+    }
+    for entry in &removed {
+        println!("{} {}", verb, entry);
+    }
+}
+
+fn run_assoc(
+    registry: &dyn registry::RegistryBackend,
+    scope: Scope,
+    action: cli::AssocAction,
+    emit_reg: bool,
+) -> Result<i32, WinbangError> {
+    let Some(exe_name) = install::self_exe_basename() else {
+        return Err(WinbangError::Unresolved(
+            "could not determine the executable name".to_string(),
+        ));
+    };
+
+    let (extensions, adding) = match action {
+        cli::AssocAction::Add { extensions } => (extensions, true),
+        cli::AssocAction::Remove { extensions } => (extensions, false),
+        cli::AssocAction::List => {
+            for status in assoc::list(registry, scope, &exe_name) {
+                let note = if status.user_choice {
+                    "default"
+                } else {
+                    "open with"
+                };
+                println!("{:<10} {}", status.ext, note);
+            }
+            return Ok(0);
+        }
+    };
+
+    let (added, removed) = if emit_reg {
+        ("Would add", "Would remove")
+    } else {
+        ("Added", "Removed")
+    };
+    let mut needs_prompt = Vec::new();
+    for ext in &extensions {
+        let result = if adding {
+            assoc::add(registry, scope, &exe_name, ext)
+        } else {
+            assoc::remove(registry, scope, &exe_name, ext)
+        };
+        match result.map_err(WinbangError::Usage)? {
+            status if adding => {
+                println!("{} {}", added, status.ext);
+                if !status.user_choice {
+                    needs_prompt.push(status.ext);
+                }
+            }
+            status => {
+                println!("{} {}", removed, status.ext);
+                if status.user_choice {
+                    println!(
+                        "  Explorer still opens {} with Winbang; pick another \
+                         app with \"Open with -> Always\".",
+                        status.ext
+                    );
+                }
+            }
+        }
+    }
+    if !needs_prompt.is_empty() {
+        println!(
+            "To make Winbang the default for {}, open a file of each type \
+             in Explorer and choose \"Open with -> Always\"; Windows only \
+             lets Explorer set the default.",
+            needs_prompt.join(", ")
+        );
+    }
+    Ok(0)
+}
+
+fn run_setup(
+    registry: &dyn registry::RegistryBackend,
+    scope: Scope,
+    target: cli::SetupTarget,
+    emit_reg: bool,
+) -> Result<i32, WinbangError> {
+    let cli::SetupTarget::Extensionless { icon } = target;
+    let exe = env::current_exe()?;
+
+    if !setup::setup_extensionless(registry, &exe, scope, icon) {
+        return Err(WinbangError::Registry(format!(
+            "some registry writes failed{}",
+            if scope == Scope::Machine {
+                "; machine scope needs an elevated prompt"
+            } else {
+                ""
+            }
+        )));
+    }
+    let exe_name = install::self_exe_basename().unwrap_or_default();
+    let problems = setup::verify_extensionless(registry, &exe_name);
+    if !problems.is_empty() {
+        return Err(WinbangError::Registry(format!(
+            "the setup was written, but:\n  {}",
+            problems.join("\n  ")
+        )));
+    }
+    if emit_reg {
+        println!("Would open files without an extension with Winbang.");
+    } else {
+        println!("Files without an extension now open with Winbang.");
+    }
+    Ok(0)
+}
+
+/// Run a `trust` command. Stops at the first script that fails, keeping
+/// the changes made before it.
+fn run_trust(action: cli::TrustAction) -> Result<i32, WinbangError> {
+    let path = trust::store_path()?;
+    let store_error = |source| WinbangError::File {
+        path: path.clone(),
+        source,
+    };
+    let mut store = trust::TrustStore::load(&path).map_err(store_error)?;
+
+    let (scripts, adding) = match action {
+        cli::TrustAction::Add { scripts } => (scripts, true),
+        cli::TrustAction::Revoke { scripts } => (scripts, false),
+        cli::TrustAction::List => {
+            if store.scripts.is_empty() {
+                println!("No trusted scripts.");
+            }
+            for entry in &store.scripts {
+                let note = match store.check(&entry.path) {
+                    Ok(trust::Trust::Trusted) => "",
+                    Ok(_) => " (changed)",
+                    Err(_) => " (missing)",
+                };
+                println!("{}  {}{}", entry.sha256, entry.path.display(), note);
+            }
+            return Ok(0);
+        }
+    };
+
+    let mut result = Ok(0);
+    for script in &scripts {
+        if adding {
+            match store.add(script) {
+                Ok(entry) => println!("Trusted {}", entry.path.display()),
+                Err(source) => {
+                    result = Err(WinbangError::File {
+                        path: script.clone(),
+                        source,
+                    });
+                    break;
+                }
+            }
+        } else if let Some(entry) = store.revoke(script) {
+            println!("Revoked {}", entry.path.display());
+        } else {
+            result = Err(WinbangError::Unresolved(format!(
+                "{} is not trusted",
+                script.display()
+            )));
+            break;
+        }
+    }
+    store.save().map_err(store_error)?;
+    result
+}
+
+/// Load the config file Group Policy allows, with the policy applied on
+/// top. Returns the file's path along with the config.
+fn load_active_config(
+    registry: &dyn registry::RegistryBackend,
+) -> Result<(PathBuf, Config), WinbangError> {
+    let policy = GroupPolicy::load(registry);
+    let config_path = find_config_path(&policy)
+        .unwrap_or_else(|| PathBuf::from("config.toml"));
+    let mut config = load_config(&config_path)?;
+    config.machine_policy = load_machine_policy(&config_path)?;
+    config.group_policy = policy;
+    Ok((config_path, config))
+}
+
+/// Run a script the way its association says. Returns the exit code of
+/// whatever ran: the script, its editor or the fallback handler.
+fn run_script(argv: &[String], config: &Config) -> Result<i32, WinbangError> {
     let script_arg = &argv[0];
-    let config = load_active_config();
 
     let script = get_script_metadata(
         script_arg,
@@ -70,28 +448,51 @@ fn run_script(argv: &[String]) -> io::Result<()> {
         None
     };
 
-    log_debug!(&format!("Extra args passed to runtime: {:?}", extra_args));
-
-    if script.association.is_some() {
-        let mut command = build_command(&script, extra_args, &config);
-        log_debug!("command = {:?}", command);
+    log_debug!("Extra args passed to runtime: {:?}", extra_args);
 
-        if is_interactive_parent(&config.gui_shells.clone().unwrap_or_default())
-        {
-            log_debug!(&format!("Script executed (interactive): {:?}", script));
-            handle_interactive_dispatch(&script, &mut command, &config)?;
-        } else {
-            log_debug!(&format!("Script executed: {:?}", script));
-            command.spawn()?.wait()?;
-        }
+    let interactive =
+        is_interactive_parent(&config.gui_shells.clone().unwrap_or_default());
+    let context = if interactive {
+        Context::Gui
     } else {
-        log_debug!(&format!(
+        Context::Console
+    };
+    audit::begin(config, context, None, &script.file_path);
+    if !script.file_path.is_file() {
+        return Err(WinbangError::Unresolved(format!(
+            "no such script: {}",
+            script_arg
+        )));
+    }
+    launch_script(&script, extra_args, interactive, config)
+}
+
+fn launch_script(
+    script: &ScriptMetadata,
+    extra_args: Option<Vec<String>>,
+    interactive: bool,
+    config: &Config,
+) -> Result<i32, WinbangError> {
+    let Some(assoc) = &script.association else {
+        log_info!(
             "No interpreter found for script: {:?}, using fallback handler",
-            script
-        ));
+            script.file_path
+        );
 
-        handle_fallback_dispatch(&script, &config)?;
-    }
+        audit::record_operation(Operation::Fallback);
+        return handle_fallback_dispatch(script, config);
+    };
+
+    audit::record_association(assoc);
+    let mut command = build_command(script, extra_args, config)?;
+    log_debug!("command = {:?}", command);
 
-    Ok(())
+    if interactive {
+        log_info!("Script executed (interactive): {:?}", script.file_path);
+        handle_interactive_dispatch(script, &mut command, config)
+    } else {
+        log_info!("Script executed: {:?}", script.file_path);
+        audit::record_operation(Operation::Execute);
+        run_to_exit(&mut command)
+    }
 }
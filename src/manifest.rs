@@ -0,0 +1,451 @@
+//! Record of the registry keys and values Winbang created, so `uninstall`
+//! can remove exactly those and never touch anything the user owns.
+//!
+//! The record lives under `Software\Winbang\Manifest` in the hive the
+//! entries are in: HKLM for machine-wide ones, HKCU for everything else:
+//!
+//! * `Keys`: one value per key Winbang created, named by its full path
+//!   (e.g. `HKCU\Software\Classes\Applications\winbang.exe`). Uninstall
+//!   deletes the whole key.
+//! * `Values`: one REG_MULTI_SZ per pre-existing key Winbang wrote values
+//!   into, named by the key's full path and listing the value names.
+//!   Uninstall deletes only those values.
+//! * `Replaced`: one value per shared key whose default value Winbang
+//!   overwrote, named by the key's full path and holding the old data.
+//!   Uninstall writes it back.
+
+use std::fmt;
+
+use crate::registry::{RegValue, RegistryBackend, Root};
+
+/// Winbang's own key. Uninstall removes it last.
+pub(crate) const WINBANG_KEY: &str = "Software\\Winbang";
+const KEYS: &str = "Software\\Winbang\\Manifest\\Keys";
+const VALUES: &str = "Software\\Winbang\\Manifest\\Values";
+const REPLACED: &str = "Software\\Winbang\\Manifest\\Replaced";
+
+/// Containers shared with the system and other software; never recorded as
+/// Winbang's, even when a registry (such as an empty test one) lacks them.
+const SHARED_KEYS: &[&str] = &[
+    "Software",
+    "Software\\Classes",
+    "Software\\Classes\\Applications",
+];
+
+/// One thing Winbang created in the registry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Entry {
+    /// A key Winbang created, owned with everything below it.
+    Key { root: Root, path: String },
+    /// A value Winbang wrote into a key it did not create.
+    Value {
+        root: Root,
+        path: String,
+        name: String,
+    },
+    /// The default value of a key Winbang did not create, which Winbang
+    /// overwrote: what it held before.
+    Replaced {
+        root: Root,
+        path: String,
+        previous: RegValue,
+    },
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entry::Key { root, path } => {
+                write!(f, "key   {}\\{}", root.short_name(), path)
+            }
+            Entry::Value { root, path, name } => {
+                let name = if name.is_empty() { "(Default)" } else { name };
+                write!(f, "value {}\\{} [{}]", root.short_name(), path, name)
+            }
+            Entry::Replaced {
+                root,
+                path,
+                previous,
+            } => {
+                write!(f, "value {}\\{} [(Default)]", root.short_name(), path)?;
+                match previous.as_str() {
+                    Some(data) => write!(f, ", back to {:?}", data),
+                    None => f.write_str(", back to its old data"),
+                }
+            }
+        }
+    }
+}
+
+/// The hive whose manifest records changes under `root`.
+fn home(root: Root) -> Root {
+    match root {
+        Root::LocalMachine => Root::LocalMachine,
+        Root::ClassesRoot | Root::CurrentUser => Root::CurrentUser,
+    }
+}
+
+fn full_path(root: Root, path: &str) -> String {
+    format!("{}\\{}", root.short_name(), path)
+}
+
+/// Record that Winbang is about to create `path`, if it doesn't exist yet.
+/// The outermost missing key along the path is recorded, so uninstall
+/// removes every key the write creates and none of the ones above it.
+/// Call this before the write.
+///
+/// # Arguments
+///
+/// * `registry`: Registry the key is written to.
+/// * `root`: Root of `path`.
+/// * `path`: Key about to be written.
+///
+/// returns: ()
+///
+/// # Examples
+///
+/// ```
+/// track_key(&registry, Root::CurrentUser, "Software\\Classes\\.py\\OpenWithProgids");
+/// registry.write_string(Root::CurrentUser, "Software\\Classes\\.py\\OpenWithProgids", "Winbang", "");
+/// ```
+pub(crate) fn track_key(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    path: &str,
+) {
+    if registry.key_exists(root, path) {
+        return;
+    }
+    let mut prefix = String::new();
+    for part in path.split('\\').filter(|p| !p.is_empty()) {
+        if !prefix.is_empty() {
+            prefix.push('\\');
+        }
+        prefix.push_str(part);
+        let shared =
+            SHARED_KEYS.iter().any(|k| k.eq_ignore_ascii_case(&prefix));
+        if !shared && !registry.key_exists(root, &prefix) {
+            break;
+        }
+    }
+    record_key(registry, root, &prefix);
+}
+
+/// Record an existing key as Winbang's, e.g. one an earlier version created
+/// before the manifest existed.
+pub(crate) fn record_key(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    path: &str,
+) {
+    if !is_recorded_key(registry, root, path) {
+        registry.write_string(home(root), KEYS, &full_path(root, path), "");
+    }
+}
+
+/// Record that Winbang is about to write value `name` under `path`. Creating
+/// the key is tracked as with `track_key`; a value in a key that is already
+/// recorded needs no entry of its own. Call this before the write.
+pub(crate) fn track_value(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    path: &str,
+    name: &str,
+) {
+    if !registry.key_exists(root, path) {
+        track_key(registry, root, path);
+        return;
+    }
+    track_shared_value(registry, root, path, name);
+}
+
+/// Record that Winbang is about to write value `name` into a key other
+/// software shares, such as `.<ext>\OpenWithProgids`. Only the value is
+/// recorded, never the key, even if this write creates it. Call this before
+/// the write.
+pub(crate) fn track_shared_value(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    path: &str,
+    name: &str,
+) {
+    if is_recorded_key(registry, root, path)
+        || registry.value_exists(root, path, name)
+    {
+        return;
+    }
+    let full = full_path(root, path);
+    let mut names = recorded_values(registry, home(root), &full);
+    if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+        names.push(name.to_string());
+        registry.write_value(
+            home(root),
+            VALUES,
+            &full,
+            &RegValue::MultiSz(names),
+        );
+    }
+}
+
+/// Record that Winbang is about to overwrite the default value of `path`, a
+/// key other software shares such as `.`. A default that isn't Winbang's
+/// already is recorded with its data, so uninstall can put it back; a
+/// missing one is recorded as with `track_shared_value`. Call this before
+/// the write.
+///
+/// # Arguments
+///
+/// * `registry`: Registry the value is written to.
+/// * `root`: Root of `path`.
+/// * `path`: Key whose default value is about to be written.
+///
+/// returns: ()
+///
+/// # Examples
+///
+/// ```
+/// track_replaced_default(&registry, Root::CurrentUser, "Software\\Classes\\.");
+/// registry.write_string(Root::CurrentUser, "Software\\Classes\\.", "", "No Extension");
+/// ```
+pub(crate) fn track_replaced_default(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    path: &str,
+) {
+    let Some(previous) = registry.read_value(root, path, "") else {
+        track_shared_value(registry, root, path, "");
+        return;
+    };
+    let full = full_path(root, path);
+    let ours = is_recorded_key(registry, root, path)
+        || recorded_values(registry, home(root), &full)
+            .iter()
+            .any(String::is_empty)
+        || registry.value_exists(home(root), REPLACED, &full);
+    if !ours {
+        registry.write_value(home(root), REPLACED, &full, &previous);
+    }
+}
+
+/// Forget a key Winbang removed itself (for example a stale verb), so a
+/// later key of the same name isn't mistaken for Winbang's.
+pub(crate) fn forget_key(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    path: &str,
+) {
+    let full = full_path(root, path);
+    for name in registry.value_names(home(root), KEYS) {
+        if name.eq_ignore_ascii_case(&full) {
+            registry.delete_value(home(root), KEYS, &name);
+        }
+    }
+}
+
+fn is_recorded_key(
+    registry: &dyn RegistryBackend,
+    root: Root,
+    path: &str,
+) -> bool {
+    let full = full_path(root, path).to_ascii_lowercase();
+    registry.value_names(home(root), KEYS).iter().any(|key| {
+        let key = key.to_ascii_lowercase();
+        full == key || full.starts_with(&format!("{}\\", key))
+    })
+}
+
+fn recorded_values(
+    registry: &dyn RegistryBackend,
+    home: Root,
+    full: &str,
+) -> Vec<String> {
+    match registry.read_value(home, VALUES, full) {
+        Some(RegValue::MultiSz(names)) => names,
+        _ => Vec::new(),
+    }
+}
+
+/// Everything the manifest in `home` records, keys first and replaced
+/// defaults last.
+pub(crate) fn entries(
+    registry: &dyn RegistryBackend,
+    home: Root,
+) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = registry
+        .value_names(home, KEYS)
+        .into_iter()
+        .filter_map(|full| {
+            let (root, path) = Root::split_path(&full)?;
+            Some(Entry::Key { root, path })
+        })
+        .collect();
+    for full in registry.value_names(home, VALUES) {
+        let Some((root, path)) = Root::split_path(&full) else {
+            continue;
+        };
+        for name in recorded_values(registry, home, &full) {
+            entries.push(Entry::Value {
+                root,
+                path: path.clone(),
+                name,
+            });
+        }
+    }
+    for full in registry.value_names(home, REPLACED) {
+        let (Some((root, path)), Some(previous)) = (
+            Root::split_path(&full),
+            registry.read_value(home, REPLACED, &full),
+        ) else {
+            continue;
+        };
+        entries.push(Entry::Replaced {
+            root,
+            path,
+            previous,
+        });
+    }
+    entries
+}
+
+/// The entries uninstall would remove: those the manifest in `home` records
+/// that are still present. Winbang's own `Software\Winbang` key in `home`
+/// is listed last.
+///
+/// # Arguments
+///
+/// * `registry`: Registry holding the manifest.
+/// * `home`: `CurrentUser` or `LocalMachine`.
+///
+/// returns: Vec<Entry>
+///
+/// # Examples
+///
+/// ```
+/// for entry in removals(&registry, Root::CurrentUser) {
+///     println!("{}", entry);
+/// }
+/// ```
+pub(crate) fn removals(
+    registry: &dyn RegistryBackend,
+    home: Root,
+) -> Vec<Entry> {
+    let mut removals: Vec<Entry> = entries(registry, home)
+        .into_iter()
+        .filter(|entry| match entry {
+            Entry::Key { root, path } => registry.key_exists(*root, path),
+            Entry::Value { root, path, name } => {
+                registry.value_exists(*root, path, name)
+            }
+            Entry::Replaced { root, path, .. } => {
+                registry.key_exists(*root, path)
+            }
+        })
+        .collect();
+    if registry.key_exists(home, WINBANG_KEY) {
+        removals.push(Entry::Key {
+            root: home,
+            path: WINBANG_KEY.to_string(),
+        });
+    }
+    removals
+}
+
+/// Remove one entry, or restore a replaced default. Returns true if it was
+/// removed or restored.
+pub(crate) fn remove(registry: &dyn RegistryBackend, entry: &Entry) -> bool {
+    match entry {
+        Entry::Key { root, path } => registry.delete_tree(*root, path),
+        Entry::Value { root, path, name } => {
+            registry.delete_value(*root, path, name)
+        }
+        Entry::Replaced {
+            root,
+            path,
+            previous,
+        } => registry.write_value(*root, path, "", previous),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::MemoryRegistry;
+
+    #[test]
+    fn tracks_outermost_created_key() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(Root::CurrentUser, "Software\\Classes\\.py", "", "P");
+
+        let key = "Software\\Classes\\.py\\OpenWithProgids\\Sub";
+        track_key(&reg, Root::CurrentUser, key);
+        reg.write_string(Root::CurrentUser, key, "", "x");
+        // Already covered by the recorded parent.
+        track_key(&reg, Root::CurrentUser, &format!("{key}\\Deeper"));
+
+        assert_eq!(
+            entries(&reg, Root::CurrentUser),
+            [Entry::Key {
+                root: Root::CurrentUser,
+                path: "Software\\Classes\\.py\\OpenWithProgids".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn tracks_values_only_in_foreign_keys() {
+        let reg = MemoryRegistry::new();
+        let key = "Software\\Classes\\.py\\OpenWithProgids";
+        reg.write_string(Root::CurrentUser, key, "Python.File", "");
+
+        track_value(&reg, Root::CurrentUser, key, "Winbang");
+        track_value(&reg, Root::CurrentUser, key, "winbang");
+        // A value the user already had is theirs.
+        track_value(&reg, Root::CurrentUser, key, "Python.File");
+
+        assert_eq!(
+            entries(&reg, Root::CurrentUser),
+            [Entry::Value {
+                root: Root::CurrentUser,
+                path: key.to_string(),
+                name: "Winbang".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn removals_skip_missing_entries() {
+        let reg = MemoryRegistry::new();
+        track_key(&reg, Root::CurrentUser, "Software\\A");
+        track_key(&reg, Root::CurrentUser, "Software\\B");
+        reg.write_string(Root::CurrentUser, "Software\\A", "", "x");
+
+        let removals = removals(&reg, Root::CurrentUser);
+        assert_eq!(
+            removals.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["key   HKCU\\Software\\A", "key   HKCU\\Software\\Winbang"]
+        );
+        for entry in &removals {
+            assert!(remove(&reg, entry));
+        }
+        assert!(!reg.key_exists(Root::CurrentUser, "Software\\A"));
+        assert!(entries(&reg, Root::CurrentUser).is_empty());
+    }
+
+    #[test]
+    fn machine_entries_are_recorded_in_hklm() {
+        let reg = MemoryRegistry::new();
+        let key = "Software\\Classes\\No Extension";
+        track_key(&reg, Root::LocalMachine, key);
+        track_key(&reg, Root::CurrentUser, "Software\\Mine");
+
+        assert_eq!(
+            entries(&reg, Root::LocalMachine),
+            [Entry::Key {
+                root: Root::LocalMachine,
+                path: key.to_string(),
+            }]
+        );
+        assert_eq!(entries(&reg, Root::CurrentUser).len(), 1);
+        assert!(reg.key_exists(Root::LocalMachine, KEYS));
+    }
+}
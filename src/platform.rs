@@ -1,15 +1,40 @@
 use crate::log_debug;
-use std::path::PathBuf;
-use windows::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "windows")]
+use windows::Win32::Foundation::{
+    CloseHandle, ERROR_SUCCESS, HANDLE, HLOCAL, INVALID_HANDLE_VALUE, LocalFree,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::Security::Authorization::{
+    GetNamedSecurityInfoW, SE_FILE_OBJECT,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::Security::{
+    GetTokenInformation, LookupAccountSidW, OWNER_SECURITY_INFORMATION,
+    PSECURITY_DESCRIPTOR, PSID, SID_NAME_USE, TOKEN_ELEVATION, TOKEN_QUERY,
+    TokenElevation,
+};
+#[cfg(target_os = "windows")]
+use windows::Win32::Storage::FileSystem::GetShortPathNameW;
+#[cfg(target_os = "windows")]
 use windows::Win32::System::Diagnostics::ToolHelp::{
     CreateToolhelp32Snapshot, PROCESSENTRY32W, Process32FirstW, Process32NextW,
     TH32CS_SNAPPROCESS,
 };
+#[cfg(target_os = "windows")]
 use windows::Win32::System::ProcessStatus::K32GetModuleBaseNameW;
+#[cfg(target_os = "windows")]
 use windows::Win32::System::Threading::{
-    GetCurrentProcessId, OpenProcess, PROCESS_QUERY_INFORMATION,
-    PROCESS_VM_READ,
+    GetCurrentProcess, GetCurrentProcessId, OpenProcess, OpenProcessToken,
+    PROCESS_QUERY_INFORMATION, PROCESS_VM_READ,
 };
+#[cfg(target_os = "windows")]
+use windows::Win32::UI::Shell::ShellExecuteW;
+#[cfg(target_os = "windows")]
+use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+#[cfg(target_os = "windows")]
+use windows::core::{PCWSTR, PWSTR};
 
 /// Check if the parent process is a GUI shell.
 ///
@@ -37,16 +62,24 @@ pub fn is_interactive_parent(gui_shells: &[String]) -> bool {
         })
         .unwrap_or(false);
 
-    log_debug!(&format!("GUI Shells: {:?}", gui_shells));
+    log_debug!("GUI Shells: {:?}", gui_shells);
 
-    log_debug!(&format!(
+    log_debug!(
         "Parent PID: {}, Parent Name: {:?}, Is GUI Shell: {}",
-        parent_pid, parent_name, is_gui_shell
-    ));
+        parent_pid,
+        parent_name,
+        is_gui_shell
+    );
 
     is_gui_shell
 }
 
+/// The parent process's PID and, when it can be read, its name.
+pub(crate) fn parent_process() -> Option<(u32, Option<String>)> {
+    let pid = get_parent_pid()?;
+    Some((pid, get_process_name(pid)))
+}
+
 /// Get the parent process ID of the current process.
 ///
 /// # Arguments
@@ -60,6 +93,7 @@ pub fn is_interactive_parent(gui_shells: &[String]) -> bool {
 /// ```
 /// let parent_pid = get_parent_pid();
 /// ```
+#[cfg(target_os = "windows")]
 fn get_parent_pid() -> Option<u32> {
     unsafe {
         let current_pid = GetCurrentProcessId();
@@ -90,6 +124,11 @@ fn get_parent_pid() -> Option<u32> {
     }
 }
 
+#[cfg(not(target_os = "windows"))]
+fn get_parent_pid() -> Option<u32> {
+    None
+}
+
 /// Get the name of a process by its PID.
 ///
 /// # Arguments
@@ -104,6 +143,7 @@ fn get_parent_pid() -> Option<u32> {
 /// let pid = 1234;
 /// let process_name = get_process_name(pid);
 /// ```
+#[cfg(target_os = "windows")]
 fn get_process_name(pid: u32) -> Option<String> {
     unsafe {
         let h_process = OpenProcess(
@@ -128,6 +168,11 @@ fn get_process_name(pid: u32) -> Option<String> {
     }
 }
 
+#[cfg(not(target_os = "windows"))]
+fn get_process_name(_pid: u32) -> Option<String> {
+    None
+}
+
 /// Resolve the executable path using the `which` command.
 ///
 /// # Arguments
@@ -145,3 +190,223 @@ fn get_process_name(pid: u32) -> Option<String> {
 pub(crate) fn resolve_executable(executable: &str) -> Option<PathBuf> {
     which::which(executable).ok()
 }
+
+/// Get the 8.3 short form of a path.
+///
+/// # Arguments
+///
+/// * `path`: Long path to an existing file or directory.
+///
+/// returns: Option<String>
+///
+/// # Examples
+///
+/// ```
+/// let short = short_path_name("C:\\Program Files\\x.sh");
+/// ```
+#[cfg(target_os = "windows")]
+pub(crate) fn short_path_name(path: &str) -> Option<String> {
+    let wide: Vec<u16> =
+        path.encode_utf16().chain(std::iter::once(0)).collect();
+    let mut buffer = vec![0u16; 1024];
+    let len =
+        unsafe { GetShortPathNameW(PCWSTR(wide.as_ptr()), Some(&mut buffer)) };
+
+    // Zero means failure (or no 8.3 support); a length beyond the buffer means
+    // it was too small, which we treat the same way.
+    if len == 0 || len as usize > buffer.len() {
+        return None;
+    }
+
+    Some(String::from_utf16_lossy(&buffer[..len as usize]))
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn short_path_name(_path: &str) -> Option<String> {
+    None
+}
+
+/// Start a program elevated through the UAC prompt without waiting for it.
+///
+/// # Arguments
+///
+/// * `program`: Program to run.
+/// * `params`: Its command line, already quoted.
+/// * `dir`: Working directory, instead of the elevated default of
+///   System32.
+///
+/// returns: Result<(), Error>
+///
+/// # Examples
+///
+/// ```
+/// run_elevated("whoami.exe", "/groups", Some(Path::new("C:\\")))?;
+/// ```
+#[cfg(target_os = "windows")]
+pub(crate) fn run_elevated(
+    program: &Path,
+    params: &str,
+    dir: Option<&Path>,
+) -> io::Result<()> {
+    let wide = |s: &str| -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    };
+    let (verb, program, params) = (
+        wide("runas"),
+        wide(&program.to_string_lossy()),
+        wide(params),
+    );
+    let dir = dir.map(|dir| wide(&dir.to_string_lossy()));
+
+    let result = unsafe {
+        ShellExecuteW(
+            None,
+            PCWSTR(verb.as_ptr()),
+            PCWSTR(program.as_ptr()),
+            PCWSTR(params.as_ptr()),
+            dir.as_ref()
+                .map_or(PCWSTR::null(), |dir| PCWSTR(dir.as_ptr())),
+            SW_SHOWNORMAL,
+        )
+    };
+
+    // Values above 32 mean success; anything else is an error code.
+    if result.0 as usize > 32 {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "ShellExecuteW failed with code {}",
+            result.0 as usize
+        )))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn run_elevated(
+    _program: &Path,
+    _params: &str,
+    _dir: Option<&Path>,
+) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "elevation requires Windows",
+    ))
+}
+
+/// Whether this process runs elevated, i.e. may write machine-wide
+/// (`HKLM`) registry keys.
+///
+/// # Arguments
+///
+/// * None
+///
+/// returns: bool
+///
+/// # Examples
+///
+/// ```
+/// if !is_elevated() {
+///     eprintln!("run this from an elevated prompt");
+/// }
+/// ```
+#[cfg(target_os = "windows")]
+pub(crate) fn is_elevated() -> bool {
+    unsafe {
+        let mut token = HANDLE::default();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token)
+            .is_err()
+        {
+            return false;
+        }
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut len = 0u32;
+        let ok = GetTokenInformation(
+            token,
+            TokenElevation,
+            Some(&mut elevation as *mut _ as *mut _),
+            std::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut len,
+        )
+        .is_ok();
+        let _ = CloseHandle(token);
+        ok && elevation.TokenIsElevated != 0
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn is_elevated() -> bool {
+    false
+}
+
+/// The owner of a file as `DOMAIN\name` (e.g. `BUILTIN\Administrators`).
+///
+/// # Arguments
+///
+/// * `path`: File to look up.
+///
+/// returns: Option<String>
+///
+/// # Examples
+///
+/// ```
+/// let owner = file_owner(Path::new("C:\\Tools\\build.sh"));
+/// ```
+#[cfg(target_os = "windows")]
+pub(crate) fn file_owner(path: &Path) -> Option<String> {
+    let wide: Vec<u16> = path
+        .to_string_lossy()
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut owner = PSID::default();
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    let status = unsafe {
+        GetNamedSecurityInfoW(
+            PCWSTR(wide.as_ptr()),
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION,
+            Some(&mut owner),
+            None,
+            None,
+            None,
+            &mut descriptor,
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+
+    let mut name = [0u16; 256];
+    let mut domain = [0u16; 256];
+    let (mut name_len, mut domain_len) = (256u32, 256u32);
+    let mut sid_use = SID_NAME_USE::default();
+    let found = unsafe {
+        LookupAccountSidW(
+            PCWSTR::null(),
+            owner,
+            Some(PWSTR(name.as_mut_ptr())),
+            &mut name_len,
+            Some(PWSTR(domain.as_mut_ptr())),
+            &mut domain_len,
+            &mut sid_use,
+        )
+    };
+    // The owner SID points into the descriptor, so free it only now.
+    unsafe {
+        LocalFree(Some(HLOCAL(descriptor.0)));
+    }
+    found.ok()?;
+
+    let name = String::from_utf16_lossy(&name[..name_len as usize]);
+    let domain = String::from_utf16_lossy(&domain[..domain_len as usize]);
+    Some(if domain.is_empty() {
+        name
+    } else {
+        format!("{}\\{}", domain, name)
+    })
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn file_owner(_path: &Path) -> Option<String> {
+    None
+}
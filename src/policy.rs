@@ -0,0 +1,552 @@
+//! `[policy]`: deny- and allow-lists checked before Winbang launches any
+//! script, on its interpreter, location, extension, owner and size.
+//!
+//! The `[policy]` of the `%PROGRAMDATA%` config always applies, even when a
+//! user config is in use, and so does Group Policy's `DenyInterpreters`. A
+//! launch must pass every one of them.
+
+use std::collections::HashMap;
+use std::path::{self, Path};
+use std::{error, fmt, fs};
+
+use serde::Deserialize;
+
+use crate::config::{Config, Origin};
+use crate::env_expand::current_env;
+use crate::location::{canonical_path, path_matches};
+use crate::log_info;
+use crate::platform::file_owner;
+use crate::signature::{Signature, SignedScript};
+use crate::zone::{FileStreams, zone_of};
+
+/// One `[policy]` section. Empty lists impose nothing; a non-empty
+/// `allow_*` list blocks everything it doesn't match.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ExecPolicy {
+    /// Interpreters by name (`mshta`, `powershell`) or path.
+    pub(crate) deny_interpreters: Vec<String>,
+    pub(crate) allow_interpreters: Vec<String>,
+    /// Globs on the script's canonical path: `*` and `?` stay within one
+    /// directory, `**` spans any number, and a glob without wildcards
+    /// covers everything below it. `%VAR%` is expanded, and `\\**` matches
+    /// every UNC path.
+    pub(crate) deny_paths: Vec<String>,
+    pub(crate) allow_paths: Vec<String>,
+    /// Extensions, with or without the dot.
+    pub(crate) deny_extensions: Vec<String>,
+    pub(crate) allow_extensions: Vec<String>,
+    /// File owners as `DOMAIN\name`, or just `name` for any domain.
+    pub(crate) deny_owners: Vec<String>,
+    pub(crate) allow_owners: Vec<String>,
+    /// Largest script allowed, in KiB.
+    pub(crate) max_size_kb: Option<u64>,
+    /// What to do with scripts downloaded from the internet.
+    pub(crate) mark_of_the_web: Option<MarkOfTheWeb>,
+    /// Minisign public keys (`RW...`) that may sign scripts.
+    pub(crate) trusted_keys: Vec<String>,
+    /// Scripts only run without a prompt when signed by `trusted_keys`.
+    pub(crate) require_signature_to_execute: bool,
+    /// Path globs, as in `deny_paths`, whose scripts are blocked unless
+    /// signed by `trusted_keys`.
+    pub(crate) require_signature_paths: Vec<String>,
+}
+
+/// Handling of scripts whose Mark of the Web puts them in the Internet or
+/// Restricted sites zone. Ordered from least to most strict.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum MarkOfTheWeb {
+    /// Treat them like local scripts.
+    Ignore,
+    /// Always prompt, with a warning naming where they came from.
+    Prompt,
+    /// Never run them.
+    Block,
+}
+
+impl fmt::Display for MarkOfTheWeb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MarkOfTheWeb::Ignore => "ignore",
+            MarkOfTheWeb::Prompt => "prompt",
+            MarkOfTheWeb::Block => "block",
+        })
+    }
+}
+
+/// The script launch being checked.
+pub(crate) struct Launch<'a> {
+    /// Interpreter about to run, as configured.
+    pub(crate) interpreter: &'a str,
+    /// Canonical path to the script.
+    pub(crate) path: &'a Path,
+    /// Owner as `DOMAIN\name`, when it could be read.
+    pub(crate) owner: Option<String>,
+    /// Size in bytes.
+    pub(crate) size: u64,
+}
+
+/// A launch refused by policy.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Blocked {
+    /// What was refused and the rule that refused it.
+    pub(crate) reason: String,
+    /// Where the rule is set.
+    pub(crate) source: String,
+}
+
+impl fmt::Display for Blocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.reason, self.source)
+    }
+}
+
+impl error::Error for Blocked {}
+
+impl ExecPolicy {
+    /// Check a launch against this section's rules. Returns the first
+    /// violation as `Err(reason)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `launch`: The launch to check.
+    /// * `env`: Variables for `%VAR%` in path globs.
+    ///
+    /// returns: Result<(), String>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// policy.check(&launch, &current_env())?;
+    /// ```
+    pub(crate) fn check(
+        &self,
+        launch: &Launch,
+        env: &HashMap<String, String>,
+    ) -> Result<(), String> {
+        let interpreter = program_name(launch.interpreter);
+        let matches_program = |p: &String| program_name(p) == interpreter;
+        check_lists(
+            "interpreter",
+            launch.interpreter,
+            &self.deny_interpreters,
+            &self.allow_interpreters,
+            matches_program,
+        )?;
+
+        let matches_glob = |p: &String| path_matches(p, launch.path, env);
+        check_lists(
+            "path",
+            &launch.path.display().to_string(),
+            &self.deny_paths,
+            &self.allow_paths,
+            matches_glob,
+        )?;
+
+        let ext = launch
+            .path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let matches_ext = |e: &String| {
+            e.trim_start_matches('.').to_lowercase() == ext && !ext.is_empty()
+        };
+        check_lists(
+            "extension",
+            &format!(".{}", ext),
+            &self.deny_extensions,
+            &self.allow_extensions,
+            matches_ext,
+        )?;
+
+        match &launch.owner {
+            Some(owner) => check_lists(
+                "owner",
+                owner,
+                &self.deny_owners,
+                &self.allow_owners,
+                |o: &String| owner_matches(o, owner),
+            )?,
+            None if !self.allow_owners.is_empty() => {
+                return Err("owner could not be read, and allow_owners is set"
+                    .to_string());
+            }
+            None => {}
+        }
+
+        if let Some(max) = self.max_size_kb
+            && launch.size > max.saturating_mul(1024)
+        {
+            return Err(format!(
+                "size {} KiB is over max_size_kb = {}",
+                launch.size.div_ceil(1024),
+                max
+            ));
+        }
+        Ok(())
+    }
+
+    /// The `require_signature_paths` rule covering `path`, if any.
+    pub(crate) fn signature_rule(
+        &self,
+        path: &Path,
+        env: &HashMap<String, String>,
+    ) -> Option<&str> {
+        self.require_signature_paths
+            .iter()
+            .find(|rule| path_matches(rule, path, env))
+            .map(String::as_str)
+    }
+}
+
+fn check_lists(
+    what: &str,
+    value: &str,
+    deny: &[String],
+    allow: &[String],
+    matches: impl Fn(&String) -> bool,
+) -> Result<(), String> {
+    if let Some(rule) = deny.iter().find(|rule| matches(rule)) {
+        return Err(format!("{} {} matches deny rule {:?}", what, value, rule));
+    }
+    if !allow.is_empty() && !allow.iter().any(matches) {
+        return Err(format!("{} {} is not on the allow list", what, value));
+    }
+    Ok(())
+}
+
+/// Check launching `script` with `interpreter` against Group Policy and every
+/// `[policy]` in effect, including what they say about downloaded scripts.
+///
+/// # Arguments
+///
+/// * `config`: Active configuration.
+/// * `interpreter`: Interpreter about to run.
+/// * `script`: Script it runs.
+/// * `streams`: Where to read the script's Mark of the Web.
+///
+/// returns: Result<(), Blocked>
+///
+/// # Examples
+///
+/// ```
+/// check_launch(&config, "python", Path::new("build.py"), &NtfsStreams)?;
+/// ```
+pub(crate) fn check_launch(
+    config: &Config,
+    interpreter: &str,
+    script: &Path,
+    streams: &dyn FileStreams,
+) -> Result<(), Blocked> {
+    if config.group_policy.denies_interpreter(interpreter) {
+        return Err(Blocked {
+            reason: format!("interpreter {} is denied", interpreter),
+            source: Origin::Policy("DenyInterpreters").to_string(),
+        });
+    }
+
+    let path = canonical_path(script).unwrap_or_else(|| {
+        path::absolute(script).unwrap_or_else(|_| script.to_path_buf())
+    });
+    if let Some(zone) = zone_of(streams, script)
+        && zone.is_internet()
+        && let (MarkOfTheWeb::Block, source) = mark_of_the_web(config)
+    {
+        return Err(Blocked {
+            reason: format!(
+                "it was downloaded from the {} zone ({})",
+                zone.zone_name(),
+                zone.host_url.as_deref().unwrap_or("source unknown")
+            ),
+            source,
+        });
+    }
+
+    let launch = Launch {
+        interpreter,
+        owner: file_owner(&path),
+        size: fs::metadata(&path).map(|m| m.len()).unwrap_or_default(),
+        path: &path,
+    };
+    let env = current_env();
+    let mut signed = None;
+    for (policy, source) in config.exec_policies() {
+        if let Err(reason) = policy.check(&launch, &env) {
+            log_info!("check_launch: blocked: {}", reason);
+            return Err(Blocked { reason, source });
+        }
+        if let Some(rule) = policy.signature_rule(&path, &env) {
+            let signed =
+                signed.get_or_insert_with(|| SignedScript::load(&path));
+            let signature = signed.verify(&policy.trusted_keys);
+            if !matches!(signature, Signature::Valid { .. }) {
+                return Err(Blocked {
+                    reason: format!(
+                        "path matches require_signature_paths rule {:?}, and \
+                         the signature is {}",
+                        rule, signature
+                    ),
+                    source,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Why `script` may not run without a prompt: the signature and the
+/// `[policy]` whose `require_signature_to_execute` it fails. `None` when
+/// every such section's `trusted_keys` verify it.
+///
+/// # Arguments
+///
+/// * `config`: Active configuration.
+/// * `signed`: The script and its signature.
+///
+/// returns: Option<(Signature, String)>
+///
+/// # Examples
+///
+/// ```
+/// let signed = SignedScript::load(&script.file_path);
+/// if let Some((signature, source)) = unsigned_execute(&config, &signed) {
+///     println!("must prompt: {} ({})", signature, source);
+/// }
+/// ```
+pub(crate) fn unsigned_execute(
+    config: &Config,
+    signed: &SignedScript,
+) -> Option<(Signature, String)> {
+    config
+        .exec_policies()
+        .into_iter()
+        .filter(|(policy, _)| policy.require_signature_to_execute)
+        .map(|(policy, source)| (signed.verify(&policy.trusted_keys), source))
+        .find(|(signature, _)| !matches!(signature, Signature::Valid { .. }))
+}
+
+/// The script's signature as each `[policy]` in effect sees it: checked
+/// against that section's own `trusted_keys`, as `unsigned_execute` and
+/// `check_launch` check it.
+pub(crate) fn signatures(
+    config: &Config,
+    signed: &SignedScript,
+) -> Vec<(Signature, String)> {
+    config
+        .exec_policies()
+        .into_iter()
+        .map(|(policy, source)| (signed.verify(&policy.trusted_keys), source))
+        .collect()
+}
+
+/// How downloaded scripts are handled, and where that is set. Group Policy
+/// wins; otherwise the strictest `[policy]` setting applies, and scripts
+/// prompt when none is set. Only the `%PROGRAMDATA%` `[policy]` may lower
+/// this below prompting: the user's config, or a project's config in the
+/// script's own folder, can only make it stricter.
+///
+/// # Arguments
+///
+/// * `config`: Active configuration.
+///
+/// returns: (MarkOfTheWeb, String)
+///
+/// # Examples
+///
+/// ```
+/// let (action, source) = mark_of_the_web(&config);
+/// ```
+pub(crate) fn mark_of_the_web(config: &Config) -> (MarkOfTheWeb, String) {
+    if let Some(action) = config.group_policy.mark_of_the_web {
+        return (action, Origin::Policy("MarkOfTheWeb").to_string());
+    }
+    // The machine-wide `[policy]`, when there is one, comes first.
+    let mut policies = config.exec_policies().into_iter();
+    let floor = match config.machine_policy {
+        Some(_) => policies.next().and_then(|(policy, source)| {
+            Some((policy.mark_of_the_web?, source))
+        }),
+        None => None,
+    };
+    let floor =
+        floor.unwrap_or((MarkOfTheWeb::Prompt, Origin::Default.to_string()));
+    policies
+        .filter_map(|(policy, source)| Some((policy.mark_of_the_web?, source)))
+        .filter(|(action, _)| *action > floor.0)
+        .max_by_key(|(action, _)| *action)
+        .unwrap_or(floor)
+}
+
+/// Lowercased file name of a program without its `.exe`.
+pub(crate) fn program_name(program: &str) -> String {
+    let name = program
+        .trim()
+        .rsplit(['\\', '/'])
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    name.strip_suffix(".exe")
+        .map(str::to_string)
+        .unwrap_or(name)
+}
+
+/// Lowercase with `/` separators and no `\\?\` prefix, for glob matching.
+pub(crate) fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/").to_lowercase();
+    match path.strip_prefix("//?/") {
+        Some(rest) => rest.to_string(),
+        None => path,
+    }
+}
+
+/// Match a normalized path against a normalized glob.
+pub(crate) fn glob_match(pattern: &str, path: &str) -> bool {
+    let (pattern, path): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), path.chars().collect());
+    glob_match_at(&pattern, &path)
+}
+
+fn glob_match_at(pattern: &[char], path: &[char]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` may also match no directories at all.
+            let rest_no_slash = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=path.len()).any(|i| glob_match_at(rest, &path[i..]))
+                || glob_match_at(rest_no_slash, path)
+        }
+        ['*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != '/')
+            .any(|i| glob_match_at(rest, &path[i..])),
+        ['?', rest @ ..] => {
+            matches!(path, [c, tail @ ..] if *c != '/' && glob_match_at(rest, tail))
+        }
+        [p, rest @ ..] => {
+            matches!(path, [c, tail @ ..] if c == p && glob_match_at(rest, tail))
+        }
+    }
+}
+
+/// `DOMAIN\name` matches exactly; a bare `name` matches it in any domain.
+fn owner_matches(rule: &str, owner: &str) -> bool {
+    if rule.contains('\\') {
+        rule.eq_ignore_ascii_case(owner)
+    } else {
+        owner
+            .rsplit('\\')
+            .next()
+            .is_some_and(|name| name.eq_ignore_ascii_case(rule))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(toml: &str) -> ExecPolicy {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn launch(
+        interpreter: &'static str,
+        path: &'static str,
+    ) -> Launch<'static> {
+        Launch {
+            interpreter,
+            path: Path::new(path),
+            owner: Some("CORP\\alice".to_string()),
+            size: 2048,
+        }
+    }
+
+    fn env() -> HashMap<String, String> {
+        HashMap::from([("TEMP".to_string(), "C:\\Users\\a\\Temp".to_string())])
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("c:/temp/**", "c:/temp/a/b.sh"));
+        assert!(glob_match("c:/temp/**/*.sh", "c:/temp/b.sh"));
+        assert!(glob_match("//**", "//server/share/x.py"));
+        assert!(glob_match("c:/a?c/*.py", "c:/abc/x.py"));
+        assert!(!glob_match("c:/temp/*", "c:/temp/a/b.sh"));
+        assert!(!glob_match("//**", "c:/x.py"));
+        assert_eq!(normalize_path("\\\\?\\C:\\X"), "c:/x");
+    }
+
+    #[test]
+    fn deny_rules_block() {
+        let p = policy(
+            r#"
+            deny_interpreters = ["MSHTA.exe"]
+            deny_paths = ["%TEMP%\\**", "\\\\**"]
+            deny_extensions = [".hta"]
+            deny_owners = ["mallory"]
+            max_size_kb = 1
+            "#,
+        );
+        let env = env();
+        let blocked = |l: Launch| p.check(&l, &env).unwrap_err();
+
+        assert!(blocked(launch("mshta", "C:/x/a.sh")).contains("interpreter"));
+        assert!(
+            blocked(launch("bash", "C:\\Users\\A\\temp\\a.sh"))
+                .contains("deny rule \"%TEMP%\\\\**\"")
+        );
+        assert!(
+            blocked(launch("bash", "\\\\srv\\share\\a.sh")).contains("path")
+        );
+        assert!(blocked(launch("bash", "C:/x/a.HTA")).contains("extension"));
+        let mut by_mallory = launch("bash", "C:/x/a.sh");
+        by_mallory.owner = Some("EVIL\\Mallory".to_string());
+        assert!(blocked(by_mallory).contains("owner"));
+        assert!(blocked(launch("bash", "C:/x/a.sh")).contains("max_size_kb"));
+    }
+
+    #[test]
+    fn allow_rules_admit_only_matches() {
+        let p = policy(
+            r#"
+            allow_interpreters = ["python", "bash"]
+            allow_paths = ["C:/Repos/**"]
+            allow_owners = ["CORP\\alice"]
+            "#,
+        );
+        let env = env();
+        assert_eq!(p.check(&launch("python", "C:/repos/t/a.py"), &env), Ok(()));
+        assert!(p.check(&launch("ruby", "C:/repos/a.rb"), &env).is_err());
+        assert!(p.check(&launch("bash", "C:/other/a.sh"), &env).is_err());
+
+        let mut unknown = launch("bash", "C:/repos/a.sh");
+        unknown.owner = None;
+        assert!(p.check(&unknown, &env).is_err());
+        assert_eq!(ExecPolicy::default().check(&unknown, &env), Ok(()));
+    }
+
+    #[test]
+    fn strictest_mark_of_the_web_setting_wins() {
+        let mut config: Config = toml::from_str("").unwrap();
+        assert_eq!(mark_of_the_web(&config).0, MarkOfTheWeb::Prompt);
+
+        config.policy.mark_of_the_web = Some(MarkOfTheWeb::Ignore);
+        assert_eq!(mark_of_the_web(&config).0, MarkOfTheWeb::Prompt);
+        let machine = policy("mark_of_the_web = \"ignore\"");
+        config.machine_policy = Some(("C:/ProgramData".into(), machine));
+        assert_eq!(mark_of_the_web(&config).0, MarkOfTheWeb::Ignore);
+        config.policy.mark_of_the_web = Some(MarkOfTheWeb::Block);
+        assert_eq!(mark_of_the_web(&config).0, MarkOfTheWeb::Block);
+
+        config.policy.mark_of_the_web = Some(MarkOfTheWeb::Ignore);
+        let machine = policy("mark_of_the_web = \"block\"");
+        config.machine_policy = Some(("C:/ProgramData".into(), machine));
+        let (action, source) = mark_of_the_web(&config);
+        assert_eq!(action, MarkOfTheWeb::Block);
+        assert!(source.contains("C:/ProgramData"));
+
+        config.group_policy.mark_of_the_web = Some(MarkOfTheWeb::Ignore);
+        let (action, source) = mark_of_the_web(&config);
+        assert_eq!(action, MarkOfTheWeb::Ignore);
+        assert!(source.ends_with("MarkOfTheWeb"));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::registry::{RegistryBackend, Root};
+
+const FILE_EXTS: &str =
+    "Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\FileExts";
+
+/// ProgID used for files without an extension by the README's
+/// `assoc .="No Extension"` setup.
+pub(crate) const NO_EXTENSION_PROGID: &str = "No Extension";
+
+/// `CurVer` chains longer than this are treated as loops.
+const MAX_CURVER_DEPTH: usize = 8;
+
+/// List the HKCR keys that can supply shell verbs for files with the given
+/// extension, in the order Explorer consults them:
+///
+/// 1. the per-user `FileExts\.<ext>\UserChoice` ProgID;
+/// 2. the `.<ext>` default ProgID;
+/// 3. per-user `FileExts\.<ext>\OpenWithProgids`, then
+///    `.<ext>\OpenWithProgids`, in enumeration order;
+/// 4. `SystemFileAssociations\.<ext>`, then
+///    `SystemFileAssociations\<PerceivedType>`;
+/// 5. `*` and `AllFilesystemObjects`.
+///
+/// An empty `ext` means files without an extension, whose key is `.`; when
+/// `.` has no default ProgID, `No Extension` stands in for it. ProgIDs are
+/// followed through `CurVer`, and ProgIDs with no key of their own are
+/// dropped. `self_progid` (Winbang's own ProgID) is skipped wherever
+/// it appears, so the result never points back at Winbang by name.
+///
+/// # Arguments
+///
+/// * `registry`: Registry to read.
+/// * `ext`: Extension without the leading dot, or `""` for none.
+/// * `self_progid`: Winbang's own ProgID, if known.
+///
+/// returns: Vec<String>
+///
+/// # Examples
+///
+/// ```
+/// let keys = handler_keys(&registry, "py", Some("Applications\\winbang.exe"));
+/// // e.g. ["Python.File", "SystemFileAssociations\\text", "*"]
+/// ```
+pub(crate) fn handler_keys(
+    registry: &dyn RegistryBackend,
+    ext: &str,
+    self_progid: Option<&str>,
+) -> Vec<String> {
+    let dot_ext = format!(".{}", ext);
+    let user_ext = format!("{}\\{}", FILE_EXTS, dot_ext);
+
+    let mut progids: Vec<String> = Vec::new();
+    progids.extend(registry.read_string(
+        Root::CurrentUser,
+        &format!("{}\\UserChoice", user_ext),
+        "ProgId",
+    ));
+    match registry.read_string(Root::ClassesRoot, &dot_ext, "") {
+        Some(progid) if !progid.is_empty() => progids.push(progid),
+        _ if ext.is_empty() => progids.push(NO_EXTENSION_PROGID.to_string()),
+        _ => {}
+    }
+    progids.extend(registry.value_names(
+        Root::CurrentUser,
+        &format!("{}\\OpenWithProgids", user_ext),
+    ));
+    progids.extend(registry.value_names(
+        Root::ClassesRoot,
+        &format!("{}\\OpenWithProgids", dot_ext),
+    ));
+
+    let is_self = |progid: &str| {
+        self_progid.is_some_and(|own| own.eq_ignore_ascii_case(progid))
+    };
+
+    let mut keys: Vec<String> = Vec::new();
+    for progid in progids {
+        if progid.is_empty() || is_self(&progid) {
+            continue;
+        }
+        let progid = follow_curver(registry, &progid);
+        if !is_self(&progid) && registry.key_exists(Root::ClassesRoot, &progid)
+        {
+            push_unique(&mut keys, progid);
+        }
+    }
+
+    let mut shared = vec![format!("SystemFileAssociations\\{}", dot_ext)];
+    if let Some(perceived) =
+        registry.read_string(Root::ClassesRoot, &dot_ext, "PerceivedType")
+        && !perceived.is_empty()
+    {
+        shared.push(format!("SystemFileAssociations\\{}", perceived));
+    }
+    shared.push("*".to_string());
+    shared.push("AllFilesystemObjects".to_string());
+
+    for key in shared {
+        if registry.key_exists(Root::ClassesRoot, &key) {
+            push_unique(&mut keys, key);
+        }
+    }
+    keys
+}
+
+/// Follow `<ProgID>\CurVer` to the current version of a ProgID, as long as
+/// each step names a ProgID that exists.
+fn follow_curver(registry: &dyn RegistryBackend, progid: &str) -> String {
+    let mut current = progid.to_string();
+    for _ in 0..MAX_CURVER_DEPTH {
+        let curver_key = format!("{}\\CurVer", current);
+        match registry.read_string(Root::ClassesRoot, &curver_key, "") {
+            Some(next)
+                if !next.is_empty()
+                    && !next.eq_ignore_ascii_case(&current)
+                    && registry.key_exists(Root::ClassesRoot, &next) =>
+            {
+                current = next;
+            }
+            _ => break,
+        }
+    }
+    current
+}
+
+fn push_unique(keys: &mut Vec<String>, key: String) {
+    if !keys.iter().any(|k| k.eq_ignore_ascii_case(&key)) {
+        keys.push(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{MemoryRegistry, regfile};
+
+    const SELF: &str = "Applications\\winbang.exe";
+
+    fn fixture(text: &str) -> MemoryRegistry {
+        let reg = MemoryRegistry::new();
+        regfile::load(&reg, text).unwrap();
+        reg
+    }
+
+    #[test]
+    fn explorer_order_for_python() {
+        let reg = fixture(include_str!("../test_files/registry/python.reg"));
+        assert_eq!(
+            handler_keys(&reg, "py", Some(SELF)),
+            [
+                "Python.File",
+                "VSCode.py",
+                "SystemFileAssociations\\.py",
+                "SystemFileAssociations\\text",
+                "*",
+                "AllFilesystemObjects",
+            ]
+        );
+    }
+
+    #[test]
+    fn user_choice_comes_first_and_follows_curver() {
+        let reg = fixture(include_str!("../test_files/registry/curver.reg"));
+        assert_eq!(
+            handler_keys(&reg, "rb", Some(SELF)),
+            ["Ruby.File.3", "Ruby.Legacy", "*"]
+        );
+    }
+
+    #[test]
+    fn self_progid_is_skipped_everywhere() {
+        let reg = fixture(include_str!("../test_files/registry/python.reg"));
+        let keys = handler_keys(&reg, "py", Some(SELF));
+        assert!(!keys.iter().any(|k| k.eq_ignore_ascii_case(SELF)));
+
+        // Without knowing its own ProgID, Winbang would pick itself first.
+        assert_eq!(handler_keys(&reg, "py", None)[0], SELF);
+    }
+
+    #[test]
+    fn curver_loops_terminate() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(Root::ClassesRoot, "A\\CurVer", "", "B");
+        reg.write_string(Root::ClassesRoot, "B\\CurVer", "", "A");
+        reg.write_string(Root::ClassesRoot, ".x", "", "A");
+        assert_eq!(handler_keys(&reg, "x", None).len(), 1);
+    }
+
+    #[test]
+    fn missing_progids_are_dropped() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(Root::ClassesRoot, ".zz", "", "Gone.File");
+        assert!(handler_keys(&reg, "zz", None).is_empty());
+    }
+
+    #[test]
+    fn extensionless_files_use_the_dot_key() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(
+            Root::ClassesRoot,
+            "No Extension\\shell\\open\\command",
+            "",
+            "winbang.exe %1",
+        );
+        reg.write_string(Root::ClassesRoot, "*\\shell\\scan\\command", "", "s");
+        assert_eq!(handler_keys(&reg, "", None), ["No Extension", "*"]);
+
+        reg.write_string(Root::ClassesRoot, ".", "", "Scripts");
+        reg.write_string(
+            Root::ClassesRoot,
+            "Scripts\\shell\\x\\command",
+            "",
+            "x",
+        );
+        assert_eq!(handler_keys(&reg, "", None), ["Scripts", "*"]);
+    }
+}
@@ -0,0 +1,340 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use super::{RegValue, RegistryBackend, Root};
+
+/// Physical hive a key lives in. `Root::ClassesRoot` is a view over the
+/// `Software\Classes` subtree of both.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Hive {
+    User,
+    Machine,
+}
+
+const CLASSES: &str = "Software\\Classes";
+
+#[derive(Debug, Default)]
+struct Key {
+    /// Key name with its original casing.
+    name: String,
+    /// Values in insertion (enumeration) order.
+    values: Vec<(String, RegValue)>,
+}
+
+/// An in-memory registry for tests and non-Windows builds.
+///
+/// Key and value names are case-insensitive but case-preserving, like the
+/// real registry. `Root::ClassesRoot` behaves like HKCR: reads prefer the
+/// per-user `HKCU\Software\Classes` entry and fall back to the machine-wide
+/// one, enumeration merges both, and writes go to the per-user key when it
+/// already exists and to the machine-wide one otherwise.
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+    keys: RefCell<BTreeMap<(Hive, String), Key>>,
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_matches('\\')
+}
+
+fn lower(path: &str) -> String {
+    normalize(path).to_lowercase()
+}
+
+fn join(base: &str, path: &str) -> String {
+    match normalize(path) {
+        "" => base.to_string(),
+        path => format!("{}\\{}", base, path),
+    }
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Physical locations backing `root\path`, in lookup order.
+    fn locations(root: Root, path: &str) -> Vec<(Hive, String)> {
+        match root {
+            Root::CurrentUser => {
+                vec![(Hive::User, normalize(path).to_string())]
+            }
+            Root::LocalMachine => {
+                vec![(Hive::Machine, normalize(path).to_string())]
+            }
+            Root::ClassesRoot => vec![
+                (Hive::User, join(CLASSES, path)),
+                (Hive::Machine, join(CLASSES, path)),
+            ],
+        }
+    }
+
+    fn has_key(&self, hive: Hive, path: &str) -> bool {
+        self.keys.borrow().contains_key(&(hive, lower(path)))
+    }
+
+    /// Physical location a write to `root\path` lands in.
+    fn write_location(&self, root: Root, path: &str) -> (Hive, String) {
+        let mut locations = Self::locations(root, path);
+        if root == Root::ClassesRoot
+            && !self.has_key(Hive::User, &locations[0].1)
+        {
+            return locations.remove(1);
+        }
+        locations.remove(0)
+    }
+
+    /// Create `path` and every missing ancestor in `hive`.
+    fn create_key(&self, hive: Hive, path: &str) {
+        let path = normalize(path);
+        let mut prefixes = vec![""];
+        prefixes.extend(path.match_indices('\\').map(|(idx, _)| &path[..idx]));
+        if !path.is_empty() {
+            prefixes.push(path);
+        }
+
+        let mut keys = self.keys.borrow_mut();
+        for prefix in prefixes {
+            keys.entry((hive, prefix.to_lowercase()))
+                .or_insert_with(|| Key {
+                    name: prefix.rsplit('\\').next().unwrap_or("").to_string(),
+                    values: Vec::new(),
+                });
+        }
+    }
+}
+
+impl RegistryBackend for MemoryRegistry {
+    fn read_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> Option<RegValue> {
+        let keys = self.keys.borrow();
+        Self::locations(root, key_path)
+            .into_iter()
+            .find_map(|(hive, path)| {
+                keys.get(&(hive, lower(&path)))?
+                    .values
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(value_name))
+                    .map(|(_, value)| value.clone())
+            })
+    }
+
+    fn write_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+        value: &RegValue,
+    ) -> bool {
+        let (hive, path) = self.write_location(root, key_path);
+        self.create_key(hive, &path);
+
+        let mut keys = self.keys.borrow_mut();
+        let key = keys
+            .get_mut(&(hive, lower(&path)))
+            .expect("key was created");
+        match key
+            .values
+            .iter_mut()
+            .find(|(name, _)| name.eq_ignore_ascii_case(value_name))
+        {
+            Some((_, existing)) => *existing = value.clone(),
+            None => key.values.push((value_name.to_string(), value.clone())),
+        }
+        true
+    }
+
+    fn value_exists(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> bool {
+        self.read_value(root, key_path, value_name).is_some()
+    }
+
+    fn key_exists(&self, root: Root, key_path: &str) -> bool {
+        Self::locations(root, key_path)
+            .iter()
+            .any(|(hive, path)| self.has_key(*hive, path))
+    }
+
+    fn subkeys(&self, root: Root, key_path: &str) -> Vec<String> {
+        let keys = self.keys.borrow();
+        let mut found: BTreeMap<String, String> = BTreeMap::new();
+
+        for (hive, path) in Self::locations(root, key_path) {
+            let parent = lower(&path);
+            for ((key_hive, key_path), key) in keys.iter() {
+                if *key_hive != hive || key_path.is_empty() {
+                    continue;
+                }
+                let child = match key_path.rsplit_once('\\') {
+                    Some((p, child)) if p == parent => child,
+                    None if parent.is_empty() => key_path.as_str(),
+                    _ => continue,
+                };
+                found
+                    .entry(child.to_string())
+                    .or_insert_with(|| key.name.clone());
+            }
+        }
+        found.into_values().collect()
+    }
+
+    fn value_names(&self, root: Root, key_path: &str) -> Vec<String> {
+        let keys = self.keys.borrow();
+        let mut names: Vec<String> = Vec::new();
+
+        for (hive, path) in Self::locations(root, key_path) {
+            let Some(key) = keys.get(&(hive, lower(&path))) else {
+                continue;
+            };
+            for (name, _) in &key.values {
+                if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names
+    }
+
+    fn delete_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> bool {
+        let (hive, path) = self.write_location(root, key_path);
+        let mut keys = self.keys.borrow_mut();
+        let Some(key) = keys.get_mut(&(hive, lower(&path))) else {
+            return false;
+        };
+        let before = key.values.len();
+        key.values
+            .retain(|(name, _)| !name.eq_ignore_ascii_case(value_name));
+        key.values.len() != before
+    }
+
+    fn delete_tree(&self, root: Root, key_path: &str) -> bool {
+        if normalize(key_path).is_empty() {
+            return false;
+        }
+        let mut keys = self.keys.borrow_mut();
+        let mut removed = false;
+
+        for (hive, path) in Self::locations(root, key_path) {
+            let target = lower(&path);
+            let prefix = format!("{}\\", target);
+            let before = keys.len();
+            keys.retain(|(key_hive, key_path), _| {
+                *key_hive != hive
+                    || (*key_path != target && !key_path.starts_with(&prefix))
+            });
+            removed |= keys.len() != before;
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_then_read_is_case_insensitive() {
+        let reg = MemoryRegistry::new();
+        assert!(reg.write_string(
+            Root::CurrentUser,
+            "Software\\Foo",
+            "Name",
+            "x"
+        ));
+        assert_eq!(
+            reg.read_string(Root::CurrentUser, "SOFTWARE\\foo", "name"),
+            Some("x".to_string())
+        );
+        assert!(reg.key_exists(Root::CurrentUser, "software"));
+        assert!(!reg.value_exists(Root::CurrentUser, "Software\\Foo", ""));
+    }
+
+    #[test]
+    fn typed_values_round_trip() {
+        let reg = MemoryRegistry::new();
+        let values = [
+            RegValue::Sz("a".to_string()),
+            RegValue::ExpandSz("%SystemRoot%".to_string()),
+            RegValue::MultiSz(vec!["a".to_string(), "b".to_string()]),
+            RegValue::Dword(7),
+        ];
+        for (idx, value) in values.iter().enumerate() {
+            let name = idx.to_string();
+            assert!(reg.write_value(Root::CurrentUser, "K", &name, value));
+            assert_eq!(
+                reg.read_value(Root::CurrentUser, "K", &name).as_ref(),
+                Some(value)
+            );
+        }
+        assert_eq!(reg.read_string(Root::CurrentUser, "K", "2"), None);
+        assert_eq!(
+            reg.value_names(Root::CurrentUser, "K"),
+            ["0", "1", "2", "3"]
+        );
+    }
+
+    #[test]
+    fn classes_root_merges_user_over_machine() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(Root::ClassesRoot, ".py", "", "Python.File");
+        assert_eq!(
+            reg.read_string(Root::CurrentUser, "Software\\Classes\\.py", ""),
+            None,
+            "HKCR writes to a key absent from HKCU land machine-wide"
+        );
+
+        reg.write_string(
+            Root::CurrentUser,
+            "Software\\Classes\\.py",
+            "",
+            "Mine",
+        );
+        reg.write_string(Root::CurrentUser, "Software\\Classes\\.ahk", "", "A");
+        assert_eq!(
+            reg.read_string(Root::ClassesRoot, ".py", ""),
+            Some("Mine".to_string())
+        );
+        assert_eq!(reg.subkeys(Root::ClassesRoot, ""), [".ahk", ".py"]);
+    }
+
+    #[test]
+    fn enumerates_direct_subkeys_only() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(Root::CurrentUser, "A\\Shell\\open\\command", "", "x");
+        reg.write_string(Root::CurrentUser, "A\\Shell\\Edit\\command", "", "y");
+        assert_eq!(
+            reg.subkeys(Root::CurrentUser, "a\\shell"),
+            ["Edit", "open"]
+        );
+        assert_eq!(reg.subkeys(Root::CurrentUser, ""), ["A"]);
+    }
+
+    #[test]
+    fn deletes_values_and_trees() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(Root::CurrentUser, "A\\B\\C", "v", "x");
+        reg.write_string(Root::CurrentUser, "A\\BB", "v", "x");
+
+        assert!(reg.delete_value(Root::CurrentUser, "A\\B\\C", "V"));
+        assert!(!reg.delete_value(Root::CurrentUser, "A\\B\\C", "V"));
+        assert!(reg.key_exists(Root::CurrentUser, "A\\B\\C"));
+
+        assert!(reg.delete_tree(Root::CurrentUser, "A\\B"));
+        assert!(!reg.key_exists(Root::CurrentUser, "A\\B\\C"));
+        assert!(reg.key_exists(Root::CurrentUser, "A\\BB"));
+        assert!(!reg.delete_tree(Root::CurrentUser, "A\\B"));
+    }
+}
@@ -0,0 +1,184 @@
+//! Registry access behind the `RegistryBackend` trait, so verb registration
+//! and ProgID resolution can run against the real registry on Windows or an
+//! in-memory one anywhere.
+
+#[cfg(any(test, not(target_os = "windows")))]
+mod memory;
+pub mod plan;
+#[cfg(test)]
+pub mod regfile;
+#[cfg(target_os = "windows")]
+mod win32;
+
+#[cfg(any(test, not(target_os = "windows")))]
+pub use memory::MemoryRegistry;
+pub use plan::PlanRegistry;
+#[cfg(target_os = "windows")]
+pub use win32::Win32Registry;
+
+// `ClassesRoot` mirrors HKEY_CLASSES_ROOT.
+#[allow(clippy::enum_variant_names)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Root {
+    /// HKEY_CLASSES_ROOT: the merged per-user and machine-wide classes view.
+    ClassesRoot,
+    CurrentUser,
+    LocalMachine,
+}
+
+impl Root {
+    /// The short hive name, as used by `reg.exe` (e.g. `HKCU`).
+    pub fn short_name(self) -> &'static str {
+        match self {
+            Root::ClassesRoot => "HKCR",
+            Root::CurrentUser => "HKCU",
+            Root::LocalMachine => "HKLM",
+        }
+    }
+
+    /// The full hive name, as used in `.reg` files.
+    pub fn long_name(self) -> &'static str {
+        match self {
+            Root::ClassesRoot => "HKEY_CLASSES_ROOT",
+            Root::CurrentUser => "HKEY_CURRENT_USER",
+            Root::LocalMachine => "HKEY_LOCAL_MACHINE",
+        }
+    }
+
+    /// Split a full key path such as `HKEY_CURRENT_USER\Software\X` or
+    /// `HKCU\Software\X` into its root and the path below it.
+    pub fn split_path(path: &str) -> Option<(Root, String)> {
+        let (root, rest) = path.split_once('\\').unwrap_or((path, ""));
+        let root = match root.to_ascii_uppercase().as_str() {
+            "HKEY_CLASSES_ROOT" | "HKCR" => Root::ClassesRoot,
+            "HKEY_CURRENT_USER" | "HKCU" => Root::CurrentUser,
+            "HKEY_LOCAL_MACHINE" | "HKLM" => Root::LocalMachine,
+            _ => return None,
+        };
+        Some((root, rest.to_string()))
+    }
+}
+
+/// A typed registry value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegValue {
+    /// REG_SZ
+    Sz(String),
+    /// REG_EXPAND_SZ: may contain unexpanded `%VAR%` references.
+    ExpandSz(String),
+    /// REG_MULTI_SZ
+    MultiSz(Vec<String>),
+    /// REG_DWORD: only read from the Windows registry and `.reg` files.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    Dword(u32),
+}
+
+impl RegValue {
+    /// The string data of a REG_SZ or REG_EXPAND_SZ value, without expanding
+    /// `%VAR%` references.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            RegValue::Sz(s) | RegValue::ExpandSz(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Operations Winbang needs from a registry. Paths are relative to `root`
+/// and use `\` separators; `value_name` may be `""` for the default value.
+///
+/// Failures are collapsed to `None`/`false`/empty, with the rationale that
+/// callers want a clean fallback path.
+pub trait RegistryBackend {
+    /// Read a value of any supported type.
+    fn read_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> Option<RegValue>;
+
+    /// Write a value, creating the key chain if needed. Returns true on
+    /// success. Does NOT check first — callers that want
+    /// fill-in-the-blanks semantics must combine with `value_exists`.
+    fn write_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+        value: &RegValue,
+    ) -> bool;
+
+    /// Check whether the given (key, value_name) exists, whatever its type.
+    fn value_exists(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> bool;
+
+    /// Check whether a key exists.
+    fn key_exists(&self, root: Root, key_path: &str) -> bool;
+
+    /// Names of the key's direct subkeys.
+    fn subkeys(&self, root: Root, key_path: &str) -> Vec<String>;
+
+    /// Names of the key's values, in enumeration order. The default value,
+    /// when set, is listed as `""`.
+    fn value_names(&self, root: Root, key_path: &str) -> Vec<String>;
+
+    /// Delete one value. Returns true if it existed and was removed.
+    fn delete_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> bool;
+
+    /// Delete a key and everything below it. Returns true if it existed and
+    /// was removed.
+    fn delete_tree(&self, root: Root, key_path: &str) -> bool;
+
+    /// Read a REG_SZ or REG_EXPAND_SZ value. Returns `None` if the key is
+    /// absent, the value is absent, or the value type is not a string.
+    fn read_string(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> Option<String> {
+        self.read_value(root, key_path, value_name)?
+            .as_str()
+            .map(str::to_string)
+    }
+
+    /// Write a REG_SZ string value. See `write_value`.
+    fn write_string(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+        data: &str,
+    ) -> bool {
+        self.write_value(
+            root,
+            key_path,
+            value_name,
+            &RegValue::Sz(data.to_string()),
+        )
+    }
+}
+
+/// The registry of the running system. Off Windows there is none, so an
+/// empty in-memory registry stands in.
+#[cfg(target_os = "windows")]
+pub fn system() -> Win32Registry {
+    Win32Registry
+}
+
+/// The registry of the running system. Off Windows there is none, so an
+/// empty in-memory registry stands in.
+#[cfg(not(target_os = "windows"))]
+pub fn system() -> MemoryRegistry {
+    MemoryRegistry::new()
+}
@@ -0,0 +1,528 @@
+//! A registry that records changes instead of applying them, for reviewing
+//! or exporting (as a `.reg` file) what a command would do.
+
+use std::cell::RefCell;
+use std::fmt::Write;
+
+use super::{RegValue, RegistryBackend, Root};
+
+const CLASSES: &str = "Software\\Classes";
+
+/// One recorded change, at a physical location (`CurrentUser` or
+/// `LocalMachine`; writes through `ClassesRoot` land in the per-user
+/// classes key).
+#[derive(Clone, Debug, PartialEq)]
+enum Change {
+    SetValue {
+        root: Root,
+        path: String,
+        name: String,
+        value: RegValue,
+    },
+    DeleteValue {
+        root: Root,
+        path: String,
+        name: String,
+    },
+    DeleteKey {
+        root: Root,
+        path: String,
+    },
+}
+
+impl Change {
+    fn location(&self) -> (Root, &str) {
+        match self {
+            Change::SetValue { root, path, .. }
+            | Change::DeleteValue { root, path, .. }
+            | Change::DeleteKey { root, path } => (*root, path),
+        }
+    }
+}
+
+/// Reads see `base` with the recorded changes applied on top; writes and
+/// deletions are only recorded.
+pub struct PlanRegistry<'a> {
+    base: &'a dyn RegistryBackend,
+    changes: RefCell<Vec<Change>>,
+}
+
+fn normalize(path: &str) -> String {
+    path.trim_matches('\\').to_string()
+}
+
+fn same(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+/// The path of `path` below `ancestor`, if it is `ancestor` or inside it.
+fn relative<'p>(ancestor: &str, path: &'p str) -> Option<&'p str> {
+    if ancestor.is_empty() {
+        return Some(path);
+    }
+    let head = path.get(..ancestor.len())?;
+    if !same(head, ancestor) {
+        return None;
+    }
+    match &path[ancestor.len()..] {
+        "" => Some(""),
+        rest => rest.strip_prefix('\\'),
+    }
+}
+
+/// Physical locations backing `root\path`, in lookup order.
+fn locations(root: Root, path: &str) -> Vec<(Root, String)> {
+    let path = normalize(path);
+    match root {
+        Root::ClassesRoot => {
+            let classes = if path.is_empty() {
+                CLASSES.to_string()
+            } else {
+                format!("{}\\{}", CLASSES, path)
+            };
+            vec![
+                (Root::CurrentUser, classes.clone()),
+                (Root::LocalMachine, classes),
+            ]
+        }
+        root => vec![(root, path)],
+    }
+}
+
+impl<'a> PlanRegistry<'a> {
+    pub fn new(base: &'a dyn RegistryBackend) -> Self {
+        PlanRegistry {
+            base,
+            changes: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn record(&self, change: Change) {
+        self.changes.borrow_mut().push(change);
+    }
+
+    /// What the recorded changes say about one value: `Some(Some(_))` if set,
+    /// `Some(None)` if deleted, `None` if untouched.
+    fn recorded_value(
+        &self,
+        root: Root,
+        path: &str,
+        name: &str,
+    ) -> Option<Option<RegValue>> {
+        for change in self.changes.borrow().iter().rev() {
+            match change {
+                Change::SetValue {
+                    root: r,
+                    path: p,
+                    name: n,
+                    value,
+                } if *r == root && same(p, path) && same(n, name) => {
+                    return Some(Some(value.clone()));
+                }
+                Change::DeleteValue {
+                    root: r,
+                    path: p,
+                    name: n,
+                } if *r == root && same(p, path) && same(n, name) => {
+                    return Some(None);
+                }
+                Change::DeleteKey { root: r, path: p }
+                    if *r == root && relative(p, path).is_some() =>
+                {
+                    return Some(None);
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// What the recorded changes say about one key's existence.
+    fn recorded_key(&self, root: Root, path: &str) -> Option<bool> {
+        for change in self.changes.borrow().iter().rev() {
+            let (r, p) = change.location();
+            if r != root {
+                continue;
+            }
+            match change {
+                Change::SetValue { .. } if relative(path, p).is_some() => {
+                    return Some(true);
+                }
+                Change::DeleteKey { .. } if relative(p, path).is_some() => {
+                    return Some(false);
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Index just past the last recorded deletion of `path` or an ancestor,
+    /// and whether there was one.
+    fn last_key_deletion(&self, root: Root, path: &str) -> (usize, bool) {
+        let changes = self.changes.borrow();
+        match changes.iter().rposition(|change| {
+            matches!(change, Change::DeleteKey { root: r, path: p }
+                if *r == root && relative(p, path).is_some())
+        }) {
+            Some(idx) => (idx + 1, true),
+            None => (0, false),
+        }
+    }
+
+    /// Merge a list of names from several locations, case-insensitively,
+    /// keeping the first spelling.
+    fn merge(names: &mut Vec<String>, name: String) {
+        if !names.iter().any(|n| same(n, &name)) {
+            names.push(name);
+        }
+    }
+
+    /// Render the recorded changes as a Registry Editor 5.00 file.
+    ///
+    /// # Arguments
+    ///
+    /// * `self`: The plan to render.
+    ///
+    /// returns: String
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let plan = PlanRegistry::new(&registry);
+    /// plan.write_string(Root::CurrentUser, "Software\\X", "", "a \"b\"");
+    /// assert!(plan.to_reg().contains(r#"@="a \"b\"""#));
+    /// ```
+    pub fn to_reg(&self) -> String {
+        let mut out = String::from("Windows Registry Editor Version 5.00\r\n");
+        let mut current: Option<(Root, String)> = None;
+
+        for change in self.changes.borrow().iter() {
+            let (root, path) = change.location();
+            if let Change::DeleteKey { .. } = change {
+                let _ =
+                    write!(out, "\r\n[-{}\\{}]\r\n", root.long_name(), path);
+                current = None;
+                continue;
+            }
+            let in_key = current
+                .as_ref()
+                .is_some_and(|(r, p)| *r == root && same(p, path));
+            if !in_key {
+                let _ = write!(out, "\r\n[{}\\{}]\r\n", root.long_name(), path);
+                current = Some((root, path.to_string()));
+            }
+            let (name, data) = match change {
+                Change::SetValue { name, value, .. } => {
+                    (name, render_value(value))
+                }
+                Change::DeleteValue { name, .. } => (name, "-".to_string()),
+                Change::DeleteKey { .. } => unreachable!("handled above"),
+            };
+            let name = if name.is_empty() {
+                "@".to_string()
+            } else {
+                format!("\"{}\"", escape(name))
+            };
+            let _ = write!(out, "{}={}\r\n", name, data);
+        }
+        out
+    }
+
+    /// Render the recorded changes as a Registry Editor 5.00 file the way
+    /// `regedit` writes them: UTF-16LE with a byte order mark. Without one,
+    /// `regedit` reads the file as ANSI and garbles non-ASCII paths.
+    ///
+    /// # Arguments
+    ///
+    /// * `self`: The plan to render.
+    ///
+    /// returns: Vec<u8>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// fs::write("winbang.reg", plan.to_reg_bytes())?;
+    /// ```
+    pub fn to_reg_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xFE];
+        for unit in self.to_reg().encode_utf16() {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// Escape `\` and `"` for a quoted `.reg` string.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn render_value(value: &RegValue) -> String {
+    match value {
+        RegValue::Sz(s) => format!("\"{}\"", escape(s)),
+        RegValue::Dword(n) => format!("dword:{:08x}", n),
+        RegValue::ExpandSz(s) => render_hex("hex(2)", &utf16_nul(s)),
+        RegValue::MultiSz(items) => {
+            let mut units = Vec::new();
+            for item in items {
+                units.extend(item.encode_utf16());
+                units.push(0);
+            }
+            units.push(0);
+            render_hex("hex(7)", &units)
+        }
+    }
+}
+
+fn utf16_nul(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain([0]).collect()
+}
+
+/// Hex data as `regedit` writes it: comma-separated bytes, wrapped with `\`
+/// continuations.
+fn render_hex(kind: &str, units: &[u16]) -> String {
+    const BYTES_PER_LINE: usize = 24;
+    let bytes: Vec<String> = units
+        .iter()
+        .flat_map(|unit| unit.to_le_bytes())
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let lines: Vec<String> =
+        bytes.chunks(BYTES_PER_LINE).map(|c| c.join(",")).collect();
+    format!("{}:{}", kind, lines.join(",\\\r\n  "))
+}
+
+impl RegistryBackend for PlanRegistry<'_> {
+    fn read_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> Option<RegValue> {
+        locations(root, key_path).into_iter().find_map(|(r, p)| {
+            match self.recorded_value(r, &p, value_name) {
+                Some(value) => value,
+                None => self.base.read_value(r, &p, value_name),
+            }
+        })
+    }
+
+    fn write_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+        value: &RegValue,
+    ) -> bool {
+        let (root, path) = locations(root, key_path).remove(0);
+        self.record(Change::SetValue {
+            root,
+            path,
+            name: value_name.to_string(),
+            value: value.clone(),
+        });
+        true
+    }
+
+    fn value_exists(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> bool {
+        locations(root, key_path).into_iter().any(|(r, p)| {
+            match self.recorded_value(r, &p, value_name) {
+                Some(value) => value.is_some(),
+                None => self.base.value_exists(r, &p, value_name),
+            }
+        })
+    }
+
+    fn key_exists(&self, root: Root, key_path: &str) -> bool {
+        locations(root, key_path).into_iter().any(|(r, p)| {
+            self.recorded_key(r, &p)
+                .unwrap_or_else(|| self.base.key_exists(r, &p))
+        })
+    }
+
+    fn subkeys(&self, root: Root, key_path: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        for (r, p) in locations(root, key_path) {
+            let (start, deleted) = self.last_key_deletion(r, &p);
+            let mut here = if deleted {
+                Vec::new()
+            } else {
+                self.base.subkeys(r, &p)
+            };
+            for change in &self.changes.borrow()[start..] {
+                let (cr, cp) = change.location();
+                let Some(rest) = relative(&p, cp).filter(|_| cr == r) else {
+                    continue;
+                };
+                let Some(child) =
+                    rest.split('\\').next().filter(|c| !c.is_empty())
+                else {
+                    continue;
+                };
+                match change {
+                    Change::DeleteKey { .. } if rest == child => {
+                        here.retain(|n| !same(n, child));
+                    }
+                    Change::SetValue { .. } => {
+                        Self::merge(&mut here, child.to_string());
+                    }
+                    _ => {}
+                }
+            }
+            for name in here {
+                Self::merge(&mut names, name);
+            }
+        }
+        names
+    }
+
+    fn value_names(&self, root: Root, key_path: &str) -> Vec<String> {
+        let mut names = Vec::new();
+        for (r, p) in locations(root, key_path) {
+            let (start, deleted) = self.last_key_deletion(r, &p);
+            let mut here = if deleted {
+                Vec::new()
+            } else {
+                self.base.value_names(r, &p)
+            };
+            for change in &self.changes.borrow()[start..] {
+                match change {
+                    Change::SetValue {
+                        root: cr,
+                        path: cp,
+                        name,
+                        ..
+                    } if *cr == r && same(cp, &p) => {
+                        Self::merge(&mut here, name.clone());
+                    }
+                    Change::DeleteValue {
+                        root: cr,
+                        path: cp,
+                        name,
+                    } if *cr == r && same(cp, &p) => {
+                        here.retain(|n| !same(n, name));
+                    }
+                    _ => {}
+                }
+            }
+            for name in here {
+                Self::merge(&mut names, name);
+            }
+        }
+        names
+    }
+
+    fn delete_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> bool {
+        let Some((root, path)) = locations(root, key_path)
+            .into_iter()
+            .find(|(r, p)| self.value_exists(*r, p, value_name))
+        else {
+            return false;
+        };
+        self.record(Change::DeleteValue {
+            root,
+            path,
+            name: value_name.to_string(),
+        });
+        true
+    }
+
+    fn delete_tree(&self, root: Root, key_path: &str) -> bool {
+        let Some((root, path)) = locations(root, key_path)
+            .into_iter()
+            .find(|(r, p)| self.key_exists(*r, p))
+        else {
+            return false;
+        };
+        self.record(Change::DeleteKey { root, path });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{MemoryRegistry, regfile};
+
+    #[test]
+    fn reads_see_recorded_changes_but_base_is_untouched() {
+        let base = MemoryRegistry::new();
+        base.write_string(Root::CurrentUser, "Software\\A", "keep", "1");
+        base.write_string(Root::CurrentUser, "Software\\A\\B", "x", "2");
+
+        let plan = PlanRegistry::new(&base);
+        plan.write_string(Root::CurrentUser, "Software\\Classes\\.x", "", "X");
+        plan.delete_tree(Root::CurrentUser, "Software\\A\\B");
+        plan.write_string(Root::CurrentUser, "Software\\A\\C", "y", "3");
+
+        assert_eq!(
+            plan.read_string(Root::ClassesRoot, ".x", "").as_deref(),
+            Some("X")
+        );
+        assert!(!plan.key_exists(Root::CurrentUser, "Software\\A\\B"));
+        assert_eq!(plan.subkeys(Root::CurrentUser, "Software\\A"), ["C"]);
+        assert_eq!(
+            plan.value_names(Root::CurrentUser, "Software\\A"),
+            ["keep"]
+        );
+
+        assert!(!base.key_exists(Root::ClassesRoot, ".x"));
+        assert!(base.key_exists(Root::CurrentUser, "Software\\A\\B"));
+        assert_eq!(plan.changes.borrow().len(), 3);
+    }
+
+    #[test]
+    fn renders_a_loadable_reg_file() {
+        let base = MemoryRegistry::new();
+        base.write_string(Root::CurrentUser, "Software\\Old", "", "x");
+        base.write_string(Root::CurrentUser, "Software\\K", "gone", "x");
+
+        let plan = PlanRegistry::new(&base);
+        let key = "Software\\K";
+        plan.write_string(Root::CurrentUser, key, "", r#""C:\a b\x.exe" "%1""#);
+        plan.write_string(Root::CurrentUser, key, "Path", "C:\\Ünïcødé\\x.exe");
+        plan.write_value(
+            Root::CurrentUser,
+            key,
+            "Icon",
+            &RegValue::ExpandSz("%SystemRoot%\\a.dll,-68".to_string()),
+        );
+        plan.delete_value(Root::CurrentUser, key, "gone");
+        plan.delete_tree(Root::CurrentUser, "Software\\Old");
+
+        let bytes = plan.to_reg_bytes();
+        assert_eq!(bytes[..2], [0xFF, 0xFE]);
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        let text = String::from_utf16(&units).unwrap();
+        assert!(
+            text.contains("@=\"\\\"C:\\\\a b\\\\x.exe\\\" \\\"%1\\\"\"\r\n")
+        );
+        assert!(text.contains("[-HKEY_CURRENT_USER\\Software\\Old]"));
+
+        let replay = MemoryRegistry::new();
+        replay.write_string(Root::CurrentUser, "Software\\Old", "", "x");
+        replay.write_string(Root::CurrentUser, key, "gone", "x");
+        regfile::load(&replay, &text).unwrap();
+        for name in ["", "Icon", "Path", "gone"] {
+            assert_eq!(
+                replay.read_value(Root::CurrentUser, key, name),
+                plan.read_value(Root::CurrentUser, key, name)
+            );
+        }
+        assert!(!replay.key_exists(Root::CurrentUser, "Software\\Old"));
+    }
+}
@@ -0,0 +1,283 @@
+//! Loader for `.reg` files (the `regedit` export format), used to replay
+//! recorded registry fixtures into a `RegistryBackend`.
+
+use super::{RegValue, RegistryBackend, Root};
+
+/// Apply the contents of a `.reg` file to `registry`.
+///
+/// Supports `[KEY]` and `[-KEY]` headers under HKCR, HKCU and HKLM; string,
+/// `dword:`, `hex(2):` (REG_EXPAND_SZ) and `hex(7):` (REG_MULTI_SZ) values;
+/// `"name"=-` deletions; `;` comments and `\` line continuations. Other
+/// value types are skipped, and a key is only created once a value or
+/// subkey is written to it.
+///
+/// # Arguments
+///
+/// * `registry`: Backend to write into.
+/// * `text`: The file contents, already decoded from UTF-16 if needed.
+///
+/// returns: Result<(), String>
+///
+/// # Examples
+///
+/// ```
+/// let reg = MemoryRegistry::new();
+/// load(&reg, include_str!("../../test_files/registry/python.reg"))?;
+/// ```
+pub fn load(registry: &dyn RegistryBackend, text: &str) -> Result<(), String> {
+    let mut current: Option<(Root, String)> = None;
+
+    for (number, line) in logical_lines(text) {
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with(';')
+            || line.starts_with("REGEDIT4")
+            || line.starts_with("Windows Registry Editor")
+        {
+            continue;
+        }
+
+        let fail = |msg: &str| format!("line {}: {}", number, msg);
+
+        if let Some(header) = line.strip_prefix('[') {
+            let header = header
+                .strip_suffix(']')
+                .ok_or_else(|| fail("unterminated key header"))?;
+            if let Some(deleted) = header.strip_prefix('-') {
+                let (root, path) = Root::split_path(deleted)
+                    .ok_or_else(|| fail("unknown root"))?;
+                registry.delete_tree(root, &path);
+                current = None;
+            } else {
+                current = Some(
+                    Root::split_path(header)
+                        .ok_or_else(|| fail("unknown root"))?,
+                );
+            }
+            continue;
+        }
+
+        let (root, path) = current
+            .as_ref()
+            .ok_or_else(|| fail("value outside a key"))?;
+        let (name, data) =
+            split_assignment(line).ok_or_else(|| fail("malformed value"))?;
+
+        if data == "-" {
+            registry.delete_value(*root, path, &name);
+            continue;
+        }
+        if let Some(value) = parse_data(data).map_err(|e| fail(&e))? {
+            registry.write_value(*root, path, &name, &value);
+        }
+    }
+    Ok(())
+}
+
+/// Join `\`-continued lines, keeping the number of the first physical line.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut out: Vec<(usize, String)> = Vec::new();
+    let mut continuing = false;
+
+    for (idx, raw) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+        let trimmed = raw.trim_end();
+        let (body, continues) = match trimmed.strip_suffix('\\') {
+            Some(body) if is_hex_line(out.last(), continuing, trimmed) => {
+                (body, true)
+            }
+            _ => (trimmed, false),
+        };
+        if continuing {
+            if let Some((_, last)) = out.last_mut() {
+                last.push_str(body.trim_start());
+            }
+        } else {
+            out.push((idx + 1, body.to_string()));
+        }
+        continuing = continues;
+    }
+    out
+}
+
+/// Only hex data is wrapped by `regedit`; a trailing backslash anywhere else
+/// is part of the line.
+fn is_hex_line(
+    last: Option<&(usize, String)>,
+    continuing: bool,
+    line: &str,
+) -> bool {
+    if continuing {
+        return last.is_some();
+    }
+    split_assignment(line).is_some_and(|(_, data)| data.starts_with("hex"))
+}
+
+/// Split `"name"=data` or `@=data` into the value name and the raw data.
+fn split_assignment(line: &str) -> Option<(String, &str)> {
+    if let Some(rest) = line.strip_prefix('@') {
+        return Some((String::new(), rest.trim_start().strip_prefix('=')?));
+    }
+    let (name, rest) = parse_quoted(line)?;
+    Some((name, rest.trim_start().strip_prefix('=')?.trim_start()))
+}
+
+/// Parse a leading `"..."` string with `\\` and `\"` escapes, returning the
+/// unescaped text and the remainder after the closing quote.
+fn parse_quoted(s: &str) -> Option<(String, &str)> {
+    let body = s.strip_prefix('"')?;
+    let mut out = String::new();
+    let mut chars = body.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => out.push(chars.next()?.1),
+            '"' => return Some((out, &body[idx + 1..])),
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+fn parse_data(data: &str) -> Result<Option<RegValue>, String> {
+    if data.starts_with('"') {
+        let (s, rest) =
+            parse_quoted(data).ok_or("unterminated string value")?;
+        if !rest.trim().is_empty() {
+            return Err("trailing text after string value".to_string());
+        }
+        return Ok(Some(RegValue::Sz(s)));
+    }
+    if let Some(hex) = data.strip_prefix("dword:") {
+        return u32::from_str_radix(hex.trim(), 16)
+            .map(|n| Some(RegValue::Dword(n)))
+            .map_err(|_| format!("bad dword {:?}", hex));
+    }
+
+    let Some((kind, bytes)) = data.split_once(':') else {
+        return Err(format!("unrecognized data {:?}", data));
+    };
+    match kind.to_ascii_lowercase().as_str() {
+        "hex(2)" => {
+            let units = utf16_units(bytes)?;
+            Ok(Some(RegValue::ExpandSz(decode_utf16(&units)?)))
+        }
+        "hex(7)" => {
+            let units = utf16_units(bytes)?;
+            let joined = decode_utf16(&units)?;
+            let items = joined
+                .split('\u{0}')
+                .filter(|s| !s.is_empty())
+                .map(str::to_string)
+                .collect();
+            Ok(Some(RegValue::MultiSz(items)))
+        }
+        // Binary and other raw types have no `RegValue` representation.
+        _ => Ok(None),
+    }
+}
+
+fn utf16_units(hex: &str) -> Result<Vec<u16>, String> {
+    let bytes = hex
+        .split(',')
+        .map(str::trim)
+        .filter(|b| !b.is_empty())
+        .map(|b| {
+            u8::from_str_radix(b, 16).map_err(|_| format!("bad byte {b:?}"))
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    if bytes.len() % 2 != 0 {
+        return Err("odd number of bytes in UTF-16 data".to_string());
+    }
+    Ok(bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect())
+}
+
+/// Decode UTF-16 data, dropping the terminating NUL.
+fn decode_utf16(units: &[u16]) -> Result<String, String> {
+    let units = units.strip_suffix(&[0]).unwrap_or(units);
+    String::from_utf16(units).map_err(|_| "invalid UTF-16 data".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::MemoryRegistry;
+
+    #[test]
+    fn loads_typed_values() {
+        let reg = MemoryRegistry::new();
+        let text = r#"Windows Registry Editor Version 5.00
+
+; comment
+[HKEY_CURRENT_USER\Software\Test]
+@="default \"quoted\" C:\\dir"
+"Count"=dword:0000002a
+"Path"=hex(2):25,00,53,00,79,00,73,00,74,00,65,00,6d,00,52,00,6f,00,6f,00,\
+  74,00,25,00,00,00
+"List"=hex(7):61,00,00,00,62,00,00,00,00,00
+"Blob"=hex:01,02
+"#;
+        load(&reg, text).unwrap();
+
+        let key = "Software\\Test";
+        assert_eq!(
+            reg.read_string(Root::CurrentUser, key, "").as_deref(),
+            Some(r#"default "quoted" C:\dir"#)
+        );
+        assert_eq!(
+            reg.read_value(Root::CurrentUser, key, "count"),
+            Some(RegValue::Dword(42))
+        );
+        assert_eq!(
+            reg.read_value(Root::CurrentUser, key, "Path"),
+            Some(RegValue::ExpandSz("%SystemRoot%".to_string()))
+        );
+        assert_eq!(
+            reg.read_value(Root::CurrentUser, key, "List"),
+            Some(RegValue::MultiSz(vec!["a".to_string(), "b".to_string()]))
+        );
+        assert!(!reg.value_exists(Root::CurrentUser, key, "Blob"));
+    }
+
+    #[test]
+    fn applies_deletions() {
+        let reg = MemoryRegistry::new();
+        load(
+            &reg,
+            "[HKCU\\A\\B]\n\"x\"=\"1\"\n\"y\"=\"2\"\n[HKCU\\A\\C]\n\"z\"=\"3\"\n\
+             [HKCU\\A\\B]\n\"x\"=-\n[-HKCU\\A\\C]\n",
+        )
+        .unwrap();
+        assert_eq!(reg.value_names(Root::CurrentUser, "A\\B"), ["y"]);
+        assert!(!reg.key_exists(Root::CurrentUser, "A\\C"));
+    }
+
+    #[test]
+    fn machine_classes_show_through_classes_root() {
+        let reg = MemoryRegistry::new();
+        load(
+            &reg,
+            "[HKEY_LOCAL_MACHINE\\Software\\Classes\\.py]\n@=\"Python.File\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            reg.read_string(Root::ClassesRoot, ".py", "").as_deref(),
+            Some("Python.File")
+        );
+    }
+
+    #[test]
+    fn reports_malformed_lines() {
+        let reg = MemoryRegistry::new();
+        assert_eq!(
+            load(&reg, "\"x\"=\"1\"\n"),
+            Err("line 1: value outside a key".to_string())
+        );
+        assert_eq!(
+            load(&reg, "[HKEY_USERS\\x]\n"),
+            Err("line 1: unknown root".to_string())
+        );
+        assert!(load(&reg, "[HKCU\\x]\n\"n\"=dword:zz\n").is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use std::ffi::OsString;
+use std::iter::once;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::slice;
+
+use windows::Win32::Foundation::{ERROR_MORE_DATA, ERROR_SUCCESS};
+use windows::Win32::System::Registry::{
+    HKEY, HKEY_CLASSES_ROOT, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, KEY_READ,
+    KEY_WRITE, REG_DWORD, REG_EXPAND_SZ, REG_MULTI_SZ, REG_OPTION_NON_VOLATILE,
+    REG_SZ, REG_VALUE_TYPE, RegCloseKey, RegCreateKeyExW, RegDeleteTreeW,
+    RegDeleteValueW, RegEnumKeyExW, RegEnumValueW, RegOpenKeyExW,
+    RegQueryValueExW, RegSetValueExW,
+};
+use windows::core::{PCWSTR, PWSTR};
+
+use super::{RegValue, RegistryBackend, Root};
+
+/// The real Windows registry.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Win32Registry;
+
+impl Root {
+    fn hkey(self) -> HKEY {
+        match self {
+            Root::ClassesRoot => HKEY_CLASSES_ROOT,
+            Root::CurrentUser => HKEY_CURRENT_USER,
+            Root::LocalMachine => HKEY_LOCAL_MACHINE,
+        }
+    }
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(once(0)).collect()
+}
+
+fn to_wide_os(s: &str) -> Vec<u16> {
+    std::ffi::OsStr::new(s)
+        .encode_wide()
+        .chain(once(0))
+        .collect()
+}
+
+fn from_wide(wide: &[u16]) -> String {
+    OsString::from_wide(wide).to_string_lossy().into_owned()
+}
+
+struct OwnedKey(HKEY);
+
+impl Drop for OwnedKey {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = RegCloseKey(self.0);
+        }
+    }
+}
+
+fn open_read(root: Root, path: &str) -> Option<OwnedKey> {
+    let wpath = to_wide_os(path);
+    let mut hkey = HKEY::default();
+    let status = unsafe {
+        RegOpenKeyExW(
+            root.hkey(),
+            PCWSTR(wpath.as_ptr()),
+            Some(0),
+            KEY_READ,
+            &mut hkey,
+        )
+    };
+    if status == ERROR_SUCCESS {
+        Some(OwnedKey(hkey))
+    } else {
+        None
+    }
+}
+
+fn open_write(root: Root, path: &str) -> Option<OwnedKey> {
+    let wpath = to_wide_os(path);
+    let mut hkey = HKEY::default();
+    let status = unsafe {
+        RegOpenKeyExW(
+            root.hkey(),
+            PCWSTR(wpath.as_ptr()),
+            Some(0),
+            KEY_READ | KEY_WRITE,
+            &mut hkey,
+        )
+    };
+    if status == ERROR_SUCCESS {
+        Some(OwnedKey(hkey))
+    } else {
+        None
+    }
+}
+
+fn create_or_open_write(root: Root, path: &str) -> Option<OwnedKey> {
+    let wpath = to_wide_os(path);
+    let mut hkey = HKEY::default();
+    let status = unsafe {
+        RegCreateKeyExW(
+            root.hkey(),
+            PCWSTR(wpath.as_ptr()),
+            None,
+            PCWSTR::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_READ | KEY_WRITE,
+            None,
+            &mut hkey,
+            None,
+        )
+    };
+    if status == ERROR_SUCCESS {
+        Some(OwnedKey(hkey))
+    } else {
+        None
+    }
+}
+
+/// Decode REG_SZ-style data: UTF-16 with (optional) trailing NULs.
+fn decode_string(wide: &[u16]) -> String {
+    // Strip trailing NULs (the registry includes them in the byte count).
+    let trimmed: &[u16] = match wide.iter().rposition(|&c| c != 0) {
+        Some(idx) => &wide[..=idx],
+        None => &[],
+    };
+    from_wide(trimmed)
+}
+
+/// Decode REG_MULTI_SZ data: NUL-separated strings ending in a double NUL.
+fn decode_multi_string(wide: &[u16]) -> Vec<String> {
+    let mut parts: Vec<String> =
+        wide.split(|&c| c == 0).map(from_wide).collect();
+    while parts.last().is_some_and(|s| s.is_empty()) {
+        parts.pop();
+    }
+    parts
+}
+
+/// Enumerate names with `RegEnumKeyExW` or `RegEnumValueW`, which share the
+/// (index, buffer, length) calling pattern.
+fn enumerate_names(
+    mut query: impl FnMut(u32, &mut [u16], &mut u32) -> u32,
+) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut buf = vec![0u16; 256];
+    let mut index = 0;
+    loop {
+        let mut len = buf.len() as u32;
+        let status = query(index, &mut buf, &mut len);
+        if status == ERROR_MORE_DATA.0 && buf.len() < 32_768 {
+            buf.resize(32_768, 0);
+            continue;
+        }
+        if status != ERROR_SUCCESS.0 {
+            return names;
+        }
+        names.push(from_wide(&buf[..len as usize]));
+        index += 1;
+    }
+}
+
+impl RegistryBackend for Win32Registry {
+    fn read_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> Option<RegValue> {
+        let key = open_read(root, key_path)?;
+        let wname = to_wide(value_name);
+        let mut value_type = REG_VALUE_TYPE::default();
+        let mut data_len: u32 = 0;
+
+        // First call: discover required buffer size.
+        let status = unsafe {
+            RegQueryValueExW(
+                key.0,
+                PCWSTR(wname.as_ptr()),
+                None,
+                Some(&mut value_type),
+                None,
+                Some(&mut data_len),
+            )
+        };
+        if status != ERROR_SUCCESS {
+            return None;
+        }
+        if ![REG_SZ, REG_EXPAND_SZ, REG_MULTI_SZ, REG_DWORD]
+            .contains(&value_type)
+        {
+            return None;
+        }
+
+        // Allocate as u16 so the buffer is suitably aligned for UTF-16.
+        let mut buf = vec![0u16; (data_len as usize).div_ceil(2).max(2)];
+        let mut data_len2 = (buf.len() * 2) as u32;
+        let status = unsafe {
+            RegQueryValueExW(
+                key.0,
+                PCWSTR(wname.as_ptr()),
+                None,
+                Some(&mut value_type),
+                Some(buf.as_mut_ptr() as *mut u8),
+                Some(&mut data_len2),
+            )
+        };
+        if status != ERROR_SUCCESS {
+            return None;
+        }
+
+        let wide = &buf[..(data_len2 as usize) / 2];
+        match value_type {
+            REG_SZ => Some(RegValue::Sz(decode_string(wide))),
+            REG_EXPAND_SZ => Some(RegValue::ExpandSz(decode_string(wide))),
+            REG_MULTI_SZ => Some(RegValue::MultiSz(decode_multi_string(wide))),
+            REG_DWORD if data_len2 == 4 => {
+                let bytes = unsafe {
+                    slice::from_raw_parts(buf.as_ptr() as *const u8, 4)
+                };
+                Some(RegValue::Dword(u32::from_le_bytes(
+                    bytes.try_into().ok()?,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    fn write_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+        value: &RegValue,
+    ) -> bool {
+        let Some(key) = create_or_open_write(root, key_path) else {
+            return false;
+        };
+        let wname = to_wide(value_name);
+
+        let (value_type, wdata): (REG_VALUE_TYPE, Vec<u16>) = match value {
+            RegValue::Sz(s) => (REG_SZ, to_wide(s)),
+            RegValue::ExpandSz(s) => (REG_EXPAND_SZ, to_wide(s)),
+            RegValue::MultiSz(parts) => {
+                let mut wide: Vec<u16> =
+                    parts.iter().flat_map(|p| to_wide(p)).collect();
+                wide.push(0);
+                (REG_MULTI_SZ, wide)
+            }
+            RegValue::Dword(n) => {
+                let bytes = n.to_le_bytes();
+                (
+                    REG_DWORD,
+                    vec![
+                        u16::from_le_bytes([bytes[0], bytes[1]]),
+                        u16::from_le_bytes([bytes[2], bytes[3]]),
+                    ],
+                )
+            }
+        };
+
+        let byte_len = wdata.len() * 2;
+        let bytes = unsafe {
+            slice::from_raw_parts(wdata.as_ptr() as *const u8, byte_len)
+        };
+        let status = unsafe {
+            RegSetValueExW(
+                key.0,
+                PCWSTR(wname.as_ptr()),
+                None,
+                value_type,
+                Some(bytes),
+            )
+        };
+        status == ERROR_SUCCESS
+    }
+
+    fn value_exists(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> bool {
+        let Some(key) = open_read(root, key_path) else {
+            return false;
+        };
+        let wname = to_wide(value_name);
+        let mut value_type = REG_VALUE_TYPE::default();
+        let mut data_len: u32 = 0;
+        let status = unsafe {
+            RegQueryValueExW(
+                key.0,
+                PCWSTR(wname.as_ptr()),
+                None,
+                Some(&mut value_type),
+                None,
+                Some(&mut data_len),
+            )
+        };
+        status == ERROR_SUCCESS
+    }
+
+    fn key_exists(&self, root: Root, key_path: &str) -> bool {
+        open_read(root, key_path).is_some()
+    }
+
+    fn subkeys(&self, root: Root, key_path: &str) -> Vec<String> {
+        let Some(key) = open_read(root, key_path) else {
+            return Vec::new();
+        };
+        enumerate_names(|index, buf, len| unsafe {
+            RegEnumKeyExW(
+                key.0,
+                index,
+                Some(PWSTR(buf.as_mut_ptr())),
+                len,
+                None,
+                None,
+                None,
+                None,
+            )
+            .0
+        })
+    }
+
+    fn value_names(&self, root: Root, key_path: &str) -> Vec<String> {
+        let Some(key) = open_read(root, key_path) else {
+            return Vec::new();
+        };
+        enumerate_names(|index, buf, len| unsafe {
+            RegEnumValueW(
+                key.0,
+                index,
+                Some(PWSTR(buf.as_mut_ptr())),
+                len,
+                None,
+                None,
+                None,
+                None,
+            )
+            .0
+        })
+    }
+
+    fn delete_value(
+        &self,
+        root: Root,
+        key_path: &str,
+        value_name: &str,
+    ) -> bool {
+        let Some(key) = open_write(root, key_path) else {
+            return false;
+        };
+        let wname = to_wide(value_name);
+        let status = unsafe { RegDeleteValueW(key.0, PCWSTR(wname.as_ptr())) };
+        status == ERROR_SUCCESS
+    }
+
+    fn delete_tree(&self, root: Root, key_path: &str) -> bool {
+        if key_path.trim_matches('\\').is_empty() {
+            return false;
+        }
+        let wpath = to_wide_os(key_path);
+        let status =
+            unsafe { RegDeleteTreeW(root.hkey(), PCWSTR(wpath.as_ptr())) };
+        status == ERROR_SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_then_read_default_value() {
+        let reg = Win32Registry;
+        let key = "Software\\Classes\\winbang-test-registry\\sub";
+        assert!(reg.write_string(Root::CurrentUser, key, "", "hello"));
+        let got = reg.read_string(Root::CurrentUser, key, "");
+        assert_eq!(got.as_deref(), Some("hello"));
+        // Clean up: delete the test key tree.
+        reg.delete_tree(
+            Root::CurrentUser,
+            "Software\\Classes\\winbang-test-registry",
+        );
+    }
+
+    #[test]
+    fn value_exists_round_trip() {
+        let reg = Win32Registry;
+        let key = "Software\\Classes\\winbang-test-registry-exists";
+        assert!(!reg.value_exists(Root::CurrentUser, key, ""));
+        assert!(reg.write_string(Root::CurrentUser, key, "", "x"));
+        assert!(reg.value_exists(Root::CurrentUser, key, ""));
+        reg.delete_tree(Root::CurrentUser, key);
+    }
+
+    #[test]
+    fn typed_values_and_enumeration() {
+        let reg = Win32Registry;
+        let key = "Software\\Classes\\winbang-test-registry-typed";
+        let values = [
+            ("sz", RegValue::Sz("a".to_string())),
+            ("expand", RegValue::ExpandSz("%SystemRoot%".to_string())),
+            (
+                "multi",
+                RegValue::MultiSz(vec!["a".to_string(), "b".to_string()]),
+            ),
+            ("dword", RegValue::Dword(0xDEAD_BEEF)),
+        ];
+        for (name, value) in &values {
+            assert!(reg.write_value(Root::CurrentUser, key, name, value));
+            assert_eq!(
+                reg.read_value(Root::CurrentUser, key, name).as_ref(),
+                Some(value)
+            );
+        }
+        assert!(reg.write_string(
+            Root::CurrentUser,
+            &format!("{key}\\sub"),
+            "",
+            ""
+        ));
+        assert_eq!(reg.value_names(Root::CurrentUser, key).len(), values.len());
+        assert_eq!(reg.subkeys(Root::CurrentUser, key), ["sub"]);
+
+        assert!(reg.delete_value(Root::CurrentUser, key, "sz"));
+        assert!(!reg.value_exists(Root::CurrentUser, key, "sz"));
+        assert!(reg.delete_tree(Root::CurrentUser, key));
+        assert!(!reg.key_exists(Root::CurrentUser, key));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::config::FileAssociation;
-use crate::log_debug;
 use crate::platform::resolve_executable;
+use crate::{log_debug, log_trace, log_warn};
+use std::collections::BTreeMap;
 use std::io::BufRead;
 use std::path::{Path, PathBuf};
 use std::{fs, io};
@@ -40,7 +41,7 @@ pub(crate) fn get_script_metadata(
     let file_size = fs::metadata(script_path)
         .map(|m| m.len())
         .unwrap_or_default();
-    let shebang = read_shebang(&*script_pbuf);
+    let shebang = read_shebang(&script_pbuf);
 
     let extension = script_pbuf
         .extension()
@@ -93,11 +94,7 @@ pub(crate) fn get_script_metadata(
             view_runtime: None,
             extension: None,
             default_operation: None,
-            verb_edit: None,
-            verb_print: None,
-            verb_printto: None,
-            verb_runas: None,
-            verb_uiaccess: None,
+            verbs: BTreeMap::new(),
         });
     }
 
@@ -108,7 +105,7 @@ pub(crate) fn get_script_metadata(
         file_size,
     };
 
-    log_debug!(&format!("Script metadata: {:?}", metadata));
+    log_debug!("Script metadata: {:?}", metadata);
     metadata
 }
 
@@ -136,19 +133,19 @@ pub(crate) fn read_shebang(path: &Path) -> Option<String> {
 
     let line = first_line.trim();
 
-    log_debug!(&format!("Shebang line: {:?}", line));
+    log_trace!("Shebang line: {:?}", line);
 
     const ALLOWED_PREFIXES: [&str; 2] = ["#!", "//!"];
     let prefix = ALLOWED_PREFIXES.iter().find(|p| line.starts_with(*p))?;
 
-    log_debug!(&format!("Found prefix: {:?}", prefix));
+    log_trace!("Found prefix: {:?}", prefix);
 
     let line = line.strip_prefix(prefix)?.trim();
 
-    log_debug!(&format!("Shebang line after prefix: {:?}", line));
+    log_trace!("Shebang line after prefix: {:?}", line);
 
     if line.is_empty() {
-        log_debug!("Error: Shebang line is empty after prefix");
+        log_debug!("Shebang line is empty after prefix");
         None // Empty shebang line
     } else {
         Some(line.to_string())
@@ -176,7 +173,7 @@ pub(crate) fn read_shebang(path: &Path) -> Option<String> {
 pub(crate) fn get_interpreter(
     shebang: &str,
 ) -> Option<(String, Option<String>)> {
-    let mut parts = shebang.trim_start_matches("#!").trim().split_whitespace();
+    let mut parts = shebang.trim_start_matches("#!").split_whitespace();
 
     let interpreter = parts.next()?;
     let arg = parts.next();
@@ -188,7 +185,7 @@ pub(crate) fn get_interpreter(
     if basename == "env" && arg == Some("-S") {
         let remaining: Vec<&str> = parts.collect();
         if remaining.is_empty() {
-            log_debug!("Error: env -S requires an interpreter");
+            log_warn!("env -S requires an interpreter");
             return None;
         }
 
@@ -200,17 +197,18 @@ pub(crate) fn get_interpreter(
         };
 
         if resolve_executable(env_interpreter).is_some() {
-            log_debug!(&format!(
+            log_debug!(
                 "Found env -S interpreter in PATH: {:?}, args: {:?}",
-                env_interpreter, env_args
-            ));
+                env_interpreter,
+                env_args
+            );
             return Some((env_interpreter.to_string(), env_args));
         }
 
-        log_debug!(&format!(
-            "Error: env -S interpreter not found in PATH: {:?}",
+        log_warn!(
+            "env -S interpreter not found in PATH: {:?}",
             env_interpreter
-        ));
+        );
         return None;
     }
 
@@ -219,17 +217,14 @@ pub(crate) fn get_interpreter(
         if let Some(arg) = arg {
             // Check for extra arguments (not allowed without -S flag)
             if parts.next().is_some() {
-                log_debug!(
-                    "Error: Too many parts in env interpreter (use -S flag for multiple args)"
+                log_warn!(
+                    "Too many parts in env interpreter (use -S flag for multiple args)"
                 );
                 return None;
             }
 
             if resolve_executable(arg).is_some() {
-                log_debug!(&format!(
-                    "Found env interpreter in PATH: {:?}",
-                    arg
-                ));
+                log_debug!("Found env interpreter in PATH: {:?}", arg);
                 return Some((arg.to_string(), None));
             }
         }
@@ -238,29 +233,27 @@ pub(crate) fn get_interpreter(
     }
 
     if parts.next().is_some() {
-        log_debug!("Error: Too many parts in interpreter");
+        log_warn!("Too many parts in interpreter");
         return None;
     }
 
     // If the interpreter is an absolute path, check if it exists (it probably won't)
     if path.exists() {
         let name = path.file_name()?.to_string_lossy();
-        log_debug!(&format!("Found interpreter: {:?}, arg: {:?}", name, arg));
+        log_debug!("Found interpreter: {:?}, arg: {:?}", name, arg);
         return Some((name.into_owned(), arg.map(|s| s.to_string())));
     }
 
     if resolve_executable(&basename).is_some() {
-        log_debug!(&format!(
-            "Found interpreter in PATH: {:?}, arg: {:?}",
-            basename, arg
-        ));
+        log_debug!("Found interpreter in PATH: {:?}, arg: {:?}", basename, arg);
         return Some((basename, arg.map(|s| s.to_string())));
     }
 
-    log_debug!(&format!(
-        "Error: Interpreter not found in PATH, returning basename: {:?} with arg: {:?}",
-        basename, arg
-    ));
+    log_debug!(
+        "Interpreter not found in PATH, returning basename: {:?} with arg: {:?}",
+        basename,
+        arg
+    );
 
     None
 }
@@ -0,0 +1,231 @@
+//! `winbang setup extensionless`: the `.` -> `No Extension` association that
+//! sends files without an extension to Winbang.
+
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::cmdline::launches;
+use crate::install::Scope;
+use crate::manifest;
+use crate::progid::NO_EXTENSION_PROGID;
+use crate::registry::{RegValue, RegistryBackend, Root};
+
+/// The `imageres.dll` icon shown for associated files.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Icon {
+    #[default]
+    Script,
+    App,
+    File,
+}
+
+impl Icon {
+    /// The `DefaultIcon` resource string for the icon.
+    pub(crate) fn resource(self) -> String {
+        let index = match self {
+            Icon::Script => 68,
+            Icon::App => 15,
+            Icon::File => 102,
+        };
+        format!("%SystemRoot%\\System32\\imageres.dll,-{}", index)
+    }
+}
+
+const CLASSES: &str = "Software\\Classes";
+
+/// Register files without an extension with Winbang: point `.` at the
+/// `No Extension` ProgID, and give that ProgID an `open` command that runs
+/// `exe` and the chosen `DefaultIcon`. Returns false if a write failed, e.g.
+/// for machine scope without elevation.
+///
+/// # Arguments
+///
+/// * `registry`: Registry to write.
+/// * `exe`: Path to Winbang's executable.
+/// * `scope`: Per-user or machine-wide.
+/// * `icon`: Icon for extensionless files.
+///
+/// returns: bool
+///
+/// # Examples
+///
+/// ```
+/// setup_extensionless(&registry, &env::current_exe()?, Scope::User, Icon::Script);
+/// ```
+pub(crate) fn setup_extensionless(
+    registry: &dyn RegistryBackend,
+    exe: &Path,
+    scope: Scope,
+    icon: Icon,
+) -> bool {
+    let root = scope.root();
+    let progid = format!("{}\\{}", CLASSES, NO_EXTENSION_PROGID);
+    let command = format!("\"{}\" \"%1\" %*", exe.display());
+
+    // `.` may hold other software's settings; only its default is ours,
+    // and uninstall puts back any default it replaces.
+    let dot = format!("{}\\.", CLASSES);
+    manifest::track_replaced_default(registry, root, &dot);
+    let mut ok = registry.write_string(root, &dot, "", NO_EXTENSION_PROGID);
+
+    let values = [
+        (
+            format!("{}\\shell\\open\\command", progid),
+            RegValue::Sz(command),
+        ),
+        (
+            format!("{}\\DefaultIcon", progid),
+            RegValue::ExpandSz(icon.resource()),
+        ),
+    ];
+    for (key, value) in &values {
+        manifest::track_value(registry, root, key, "");
+        ok &= registry.write_value(root, key, "", value);
+    }
+    ok
+}
+
+/// Check, through the merged HKCR view Explorer uses, that extensionless
+/// files open with `exe_name`. Returns the problems found.
+///
+/// # Arguments
+///
+/// * `registry`: Registry to read.
+/// * `exe_name`: Winbang's exe file name.
+///
+/// returns: Vec<String>
+///
+/// # Examples
+///
+/// ```
+/// for problem in verify_extensionless(&registry, "winbang.exe") {
+///     eprintln!("{}", problem);
+/// }
+/// ```
+pub(crate) fn verify_extensionless(
+    registry: &dyn RegistryBackend,
+    exe_name: &str,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    let progid = registry
+        .read_string(Root::ClassesRoot, ".", "")
+        .filter(|p| !p.is_empty())
+        .unwrap_or_else(|| NO_EXTENSION_PROGID.to_string());
+    if !progid.eq_ignore_ascii_case(NO_EXTENSION_PROGID) {
+        problems.push(format!(
+            "HKCR\\. points at {:?}, which takes precedence (per-user \
+             settings override machine-wide ones)",
+            progid
+        ));
+    }
+
+    let command_key = format!("{}\\shell\\open\\command", progid);
+    match registry.read_string(Root::ClassesRoot, &command_key, "") {
+        Some(command) if launches(&command, exe_name) => {}
+        Some(command) => problems.push(format!(
+            "HKCR\\{} runs {:?}, not {}",
+            command_key, command, exe_name
+        )),
+        None => problems.push(format!("HKCR\\{} is missing", command_key)),
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{MemoryRegistry, PlanRegistry, regfile};
+
+    fn exe() -> &'static Path {
+        Path::new("C:/Tools/winbang.exe")
+    }
+
+    #[test]
+    fn sets_up_and_verifies_per_user() {
+        let reg = MemoryRegistry::new();
+        assert!(setup_extensionless(&reg, exe(), Scope::User, Icon::App));
+
+        assert!(verify_extensionless(&reg, "winbang.exe").is_empty());
+        assert_eq!(
+            reg.read_value(
+                Root::CurrentUser,
+                "Software\\Classes\\No Extension\\DefaultIcon",
+                ""
+            ),
+            Some(RegValue::ExpandSz(
+                "%SystemRoot%\\System32\\imageres.dll,-15".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn per_user_settings_shadow_machine_scope() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(Root::CurrentUser, "Software\\Classes\\.", "", "Txt");
+        setup_extensionless(&reg, exe(), Scope::Machine, Icon::Script);
+
+        assert_eq!(
+            reg.read_string(Root::LocalMachine, "Software\\Classes\\.", "")
+                .as_deref(),
+            Some("No Extension")
+        );
+        let problems = verify_extensionless(&reg, "winbang.exe");
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+    }
+
+    #[test]
+    fn uninstall_removes_the_setup() {
+        let reg = MemoryRegistry::new();
+        setup_extensionless(&reg, exe(), Scope::User, Icon::Script);
+        for entry in manifest::removals(&reg, Root::CurrentUser) {
+            manifest::remove(&reg, &entry);
+        }
+        assert!(!reg.value_exists(Root::ClassesRoot, ".", ""));
+        assert!(!reg.key_exists(Root::ClassesRoot, "No Extension"));
+    }
+
+    #[test]
+    fn uninstall_restores_a_replaced_default() {
+        let reg = MemoryRegistry::new();
+        let dot = "Software\\Classes\\.";
+        reg.write_string(Root::CurrentUser, dot, "", "Txt");
+        reg.write_string(Root::CurrentUser, dot, "Other", "kept");
+        setup_extensionless(&reg, exe(), Scope::User, Icon::Script);
+        setup_extensionless(&reg, exe(), Scope::User, Icon::App);
+        assert!(verify_extensionless(&reg, "winbang.exe").is_empty());
+
+        let removals = manifest::removals(&reg, Root::CurrentUser);
+        assert!(
+            removals
+                .iter()
+                .any(|e| e.to_string().ends_with(", back to \"Txt\""))
+        );
+        for entry in &removals {
+            assert!(manifest::remove(&reg, entry));
+        }
+        assert_eq!(
+            reg.read_string(Root::CurrentUser, dot, "").as_deref(),
+            Some("Txt")
+        );
+        assert!(reg.value_exists(Root::CurrentUser, dot, "Other"));
+        assert!(!reg.key_exists(Root::ClassesRoot, "No Extension"));
+        assert!(manifest::removals(&reg, Root::CurrentUser).is_empty());
+    }
+
+    #[test]
+    fn emitted_reg_file_reproduces_the_setup() {
+        let base = MemoryRegistry::new();
+        let plan = PlanRegistry::new(&base);
+        setup_extensionless(&plan, exe(), Scope::Machine, Icon::File);
+        assert!(!base.key_exists(Root::ClassesRoot, "."));
+
+        let replay = MemoryRegistry::new();
+        regfile::load(&replay, &plan.to_reg()).unwrap();
+        assert!(verify_extensionless(&replay, "winbang.exe").is_empty());
+        assert!(
+            plan.to_reg()
+                .contains("[HKEY_LOCAL_MACHINE\\Software\\Classes\\.]")
+        );
+    }
+}
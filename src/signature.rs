@@ -0,0 +1,386 @@
+//! Minisign signatures on scripts, checked against the `trusted_keys` of a
+//! `[policy]`.
+//!
+//! A signature comes from a sidecar next to the script (`build.sh.minisig`
+//! or `build.sh.sig`) or from a comment block at its end, which signs
+//! everything before it:
+//!
+//! ```text
+//! # -----BEGIN MINISIGN SIGNATURE-----
+//! # untrusted comment: signature from minisign secret key
+//! # RUQ...
+//! # trusted comment: timestamp:1760745600 file:build.sh
+//! # 9Jq...
+//! # -----END MINISIGN SIGNATURE-----
+//! ```
+//!
+//! Each line of the block carries the same comment prefix as the BEGIN
+//! line, which must be one of the usual line comment markers. Nothing signs
+//! the block itself, so it must hold exactly the four lines of a `.minisig`
+//! file, with minisign's default untrusted comment.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use minisign_verify::{PublicKey, Signature as Minisig};
+
+const BEGIN: &str = "-----BEGIN MINISIGN SIGNATURE-----";
+const END: &str = "-----END MINISIGN SIGNATURE-----";
+
+/// Sidecar extensions, in the order they are looked for.
+const SIDECARS: [&str; 2] = ["minisig", "sig"];
+
+/// Line comment markers a signature block may sit behind.
+const COMMENT_MARKERS: [&str; 8] =
+    ["#", "//", "--", ";", "'", "%", "::", "REM"];
+
+/// The untrusted comment `minisign -S` writes, the only one a block may
+/// carry.
+const UNTRUSTED_COMMENT: &str =
+    "untrusted comment: signature from minisign secret key";
+
+/// A script's signature, checked against a set of keys.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Signature {
+    /// No signature was found.
+    Unsigned,
+    /// Signed by one of the keys.
+    Valid {
+        /// The key that verified it, as configured.
+        key: String,
+        /// Where the signature was found.
+        from: String,
+        /// The signature's trusted comment.
+        comment: String,
+    },
+    /// A signature that doesn't verify: why not.
+    Invalid(String),
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Signature::Unsigned => f.write_str("unsigned"),
+            Signature::Valid { key, from, comment } => {
+                write!(f, "valid, key {} ({}; {})", key, from, comment)
+            }
+            Signature::Invalid(reason) => write!(f, "invalid: {}", reason),
+        }
+    }
+}
+
+/// A script's contents and its signature, read once and verified against
+/// each policy's keys.
+#[derive(Default)]
+pub(crate) struct SignedScript {
+    /// The signed bytes: the whole script, or what precedes a signature
+    /// block.
+    body: Vec<u8>,
+    /// The signature and where it came from, or why it can't be decoded.
+    signature: Option<(Result<Minisig, String>, String)>,
+}
+
+impl SignedScript {
+    /// Read `script` and find its signature.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: Script to read.
+    ///
+    /// returns: SignedScript
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let signed = SignedScript::load(Path::new("deploy.sh"));
+    /// println!("{}", signed.verify(&policy.trusted_keys));
+    /// ```
+    pub(crate) fn load(script: &Path) -> SignedScript {
+        let Ok(contents) = fs::read(script) else {
+            return SignedScript::default();
+        };
+
+        for ext in SIDECARS {
+            let sidecar = sidecar_path(script, ext);
+            if let Ok(text) = fs::read_to_string(&sidecar) {
+                let name = sidecar
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                return SignedScript {
+                    body: contents,
+                    signature: Some((decode(&text), name)),
+                };
+            }
+        }
+
+        match split_block(&contents) {
+            Some((body, block)) => SignedScript {
+                body: body.to_vec(),
+                signature: Some((
+                    block.and_then(|block| decode(&block)),
+                    "comment block".to_string(),
+                )),
+            },
+            None => SignedScript {
+                body: contents,
+                signature: None,
+            },
+        }
+    }
+
+    /// Check the signature against `keys`, minisign public keys in base64
+    /// (`RW...`). Keys that don't parse are skipped.
+    pub(crate) fn verify(&self, keys: &[String]) -> Signature {
+        let Some((signature, from)) = &self.signature else {
+            return Signature::Unsigned;
+        };
+        let signature = match signature {
+            Ok(signature) => signature,
+            Err(reason) => return Signature::Invalid(reason.clone()),
+        };
+
+        let mut reason = "no trusted keys are configured".to_string();
+        for key in keys {
+            let Ok(public) = PublicKey::from_base64(key.trim()) else {
+                continue;
+            };
+            match public.verify(&self.body, signature, false) {
+                Ok(()) => {
+                    return Signature::Valid {
+                        key: key.trim().to_string(),
+                        from: from.clone(),
+                        comment: signature.trusted_comment().to_string(),
+                    };
+                }
+                Err(minisign_verify::Error::UnexpectedKeyId) => {
+                    reason = "signed by a key that isn't trusted".to_string();
+                }
+                Err(_) => {
+                    return Signature::Invalid(format!(
+                        "{} doesn't match the script",
+                        from
+                    ));
+                }
+            }
+        }
+        Signature::Invalid(reason)
+    }
+}
+
+fn sidecar_path(script: &Path, ext: &str) -> PathBuf {
+    let mut path = script.as_os_str().to_os_string();
+    path.push(".");
+    path.push(ext);
+    PathBuf::from(path)
+}
+
+fn decode(text: &str) -> Result<Minisig, String> {
+    Minisig::decode(text.trim_start_matches('\u{feff}'))
+        .map_err(|e| format!("malformed signature: {}", e))
+}
+
+/// Split a script at a trailing signature block into the signed body and
+/// the signature text with its comment prefix removed. Only whitespace may
+/// follow the block, since nothing after it is signed; anything else, or a
+/// block that could hide code, makes the signature text an error, so the
+/// script reads as tampered with.
+fn split_block(contents: &[u8]) -> Option<(&[u8], Result<String, String>)> {
+    let text = std::str::from_utf8(contents).ok()?;
+    let begin = text.rfind(BEGIN)?;
+    let line_start = text[..begin].rfind('\n').map_or(0, |i| i + 1);
+    let prefix = &text[line_start..begin];
+
+    let mut lines = text[line_start..]
+        .split_inclusive('\n')
+        .map(|line| line.trim_end_matches(['\r', '\n']));
+    let mut framed = Vec::new();
+    for line in lines.by_ref() {
+        framed.push(line);
+        if line.contains(END) {
+            let block = if lines.all(|rest| rest.trim().is_empty()) {
+                unframe(prefix, &framed)
+            } else {
+                Err("content after the signature block".to_string())
+            };
+            return Some((&contents[..line_start], block));
+        }
+    }
+    None
+}
+
+/// Strip `prefix` from the lines of a block, BEGIN and END included, and
+/// return the `.minisig` text between them.
+fn unframe(prefix: &str, framed: &[&str]) -> Result<String, String> {
+    let marker = prefix.trim();
+    if !COMMENT_MARKERS
+        .iter()
+        .any(|m| m.eq_ignore_ascii_case(marker))
+    {
+        return Err("the signature block isn't behind a comment".to_string());
+    }
+
+    let mut inner = Vec::with_capacity(framed.len());
+    for line in framed {
+        let Some(line) = line.strip_prefix(prefix) else {
+            return Err(format!(
+                "a signature block line doesn't start with \"{}\"",
+                marker
+            ));
+        };
+        inner.push(line.trim());
+    }
+    if inner.len() != 6 {
+        return Err(format!(
+            "the signature block has {} lines instead of 4",
+            inner.len().saturating_sub(2)
+        ));
+    }
+    let [BEGIN, untrusted, key_line, trusted, global, END] = inner[..] else {
+        return Err("malformed signature block".to_string());
+    };
+    if untrusted != UNTRUSTED_COMMENT {
+        return Err("the signature block has a nonstandard untrusted comment"
+            .to_string());
+    }
+    Ok([untrusted, key_line, trusted, global].join("\n") + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn key() -> Vec<String> {
+        let public = include_str!("../test_files/signed/test.pub");
+        vec![public.lines().nth(1).unwrap().to_string()]
+    }
+
+    #[test]
+    fn verifies_sidecars_and_comment_blocks() {
+        let dir = Path::new("test_files/signed");
+        let sidecar = SignedScript::load(&dir.join("deploy.sh")).verify(&key());
+        assert!(matches!(
+            &sidecar,
+            Signature::Valid { from, comment, .. }
+                if from == "deploy.sh.minisig" && comment.ends_with("deploy.sh")
+        ));
+
+        let inline = SignedScript::load(&dir.join("inline.py"));
+        assert!(matches!(
+            inline.verify(&key()),
+            Signature::Valid { from, .. } if from == "comment block"
+        ));
+        assert_eq!(
+            inline.verify(&[]),
+            Signature::Invalid("no trusted keys are configured".to_string())
+        );
+        let other = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+        assert_eq!(
+            inline.verify(&[other.to_string()]),
+            Signature::Invalid(
+                "signed by a key that isn't trusted".to_string()
+            )
+        );
+
+        let tampered = SignedScript::load(&dir.join("tampered.py"));
+        assert_eq!(
+            tampered.verify(&key()),
+            Signature::Invalid(
+                "comment block doesn't match the script".to_string()
+            )
+        );
+        let unsigned = SignedScript::load(Path::new("test_files/test.rb"));
+        assert_eq!(unsigned.verify(&key()), Signature::Unsigned);
+    }
+
+    /// Verify a copy of `inline.py` edited by `edit`.
+    fn verify_edited(edit: impl Fn(&str) -> String) -> Signature {
+        let signed = fs::read_to_string("test_files/signed/inline.py").unwrap();
+        static EDITS: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "winbang-signature-{}-{}",
+            std::process::id(),
+            EDITS.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("inline.py");
+        fs::write(&script, edit(&signed)).unwrap();
+        let signature = SignedScript::load(&script).verify(&key());
+        fs::remove_dir_all(&dir).unwrap();
+        signature
+    }
+
+    fn invalid(reason: &str) -> Signature {
+        Signature::Invalid(reason.to_string())
+    }
+
+    #[test]
+    fn comment_blocks_take_any_prefix() {
+        for prefix in ["REM ", "// ", "  --"] {
+            let signature = verify_edited(|text| {
+                text.replace("\n# ", &format!("\n{prefix}"))
+            });
+            assert!(matches!(signature, Signature::Valid { .. }), "{prefix}");
+        }
+        assert_eq!(
+            split_block(b"# -----BEGIN MINISIGN SIGNATURE-----\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn content_after_the_block_is_invalid() {
+        assert_eq!(
+            verify_edited(|text| format!(
+                "{text}import os; os.system('calc')\n"
+            )),
+            invalid("content after the signature block")
+        );
+        assert!(matches!(
+            verify_edited(|text| format!("{text}\n  \n")),
+            Signature::Valid { .. }
+        ));
+    }
+
+    #[test]
+    fn blocks_that_could_hide_code_are_invalid() {
+        assert_eq!(
+            verify_edited(|text| text.replace(
+                "# untrusted",
+                "import os; os.system('calc') # untrusted"
+            )),
+            invalid("a signature block line doesn't start with \"#\"")
+        );
+        assert_eq!(
+            verify_edited(|text| text.replace(
+                "# trusted comment",
+                "import sys; print('injected')\n# trusted comment"
+            )),
+            invalid("a signature block line doesn't start with \"#\"")
+        );
+        assert_eq!(
+            verify_edited(|text| text.replace(
+                "# trusted comment",
+                "# import sys\n# trusted comment"
+            )),
+            invalid("the signature block has 5 lines instead of 4")
+        );
+        assert_eq!(
+            verify_edited(|text| text.replace("\n# ", "\n")),
+            invalid("the signature block isn't behind a comment")
+        );
+        assert_eq!(
+            verify_edited(|text| text.replace("\n# ", "\nprint(1) # ")),
+            invalid("the signature block isn't behind a comment")
+        );
+        assert_eq!(
+            verify_edited(|text| text.replace(
+                "minisign secret key",
+                "minisign secret key'); import os #"
+            )),
+            invalid("the signature block has a nonstandard untrusted comment")
+        );
+    }
+}
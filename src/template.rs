@@ -0,0 +1,745 @@
+use crate::log_warn;
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+
+/// Environment-dependent inputs for placeholder filters. Everything else in
+/// this module is pure string manipulation.
+pub(crate) struct FilterContext {
+    /// Directory `|relative` paths are computed against.
+    pub(crate) cwd: Option<PathBuf>,
+    /// Resolves a path to its 8.3 short form, if the volume has one.
+    pub(crate) short_path: fn(&str) -> Option<String>,
+}
+
+impl FilterContext {
+    /// Context for the running process: real cwd and real 8.3 lookups.
+    pub(crate) fn current() -> Self {
+        FilterContext {
+            cwd: env::current_dir().ok(),
+            short_path: crate::platform::short_path_name,
+        }
+    }
+}
+
+/// Expand `@{name}` and `@{name|filter|...}` placeholders in a string.
+///
+/// Filters are applied left to right to the variable's raw value. A bare
+/// `@{script}` keeps its historical double-backslash rendering; with any
+/// filter the filter output is used verbatim. Placeholders naming an unknown
+/// variable or filter are left untouched.
+///
+/// # Arguments
+///
+/// * `s`: String containing placeholders.
+/// * `vars`: HashMap of raw variable values.
+/// * `ctx`: Filter context.
+///
+/// returns: String
+///
+/// # Examples
+///
+/// ```
+/// let mut vars = HashMap::new();
+/// vars.insert("script", "C:\\x\\test.sh".to_string());
+/// let result = expand_placeholders("@{script|msys}", &vars, &ctx);
+/// assert_eq!(result, "/c/x/test.sh");
+/// ```
+pub(crate) fn expand_placeholders(
+    s: &str,
+    vars: &HashMap<&str, String>,
+    ctx: &FilterContext,
+) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find("@{") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            result.push_str(&rest[start..]);
+            return result;
+        };
+
+        let body = &after[..end];
+        match expand_one(body, vars, ctx) {
+            Some(expanded) => result.push_str(&expanded),
+            None => {
+                log_warn!("Leaving placeholder unexpanded: @{{{}}}", body);
+                result.push_str(&rest[start..start + 2 + end + 1]);
+            }
+        }
+        rest = &after[end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn expand_one(
+    body: &str,
+    vars: &HashMap<&str, String>,
+    ctx: &FilterContext,
+) -> Option<String> {
+    let mut parts = body.split('|').map(str::trim);
+    let name = parts.next()?;
+    let raw = vars.get(name)?;
+    let filters: Vec<&str> = parts.collect();
+
+    if filters.is_empty() {
+        return Some(render_bare(name, raw));
+    }
+
+    let mut value = raw.clone();
+    for filter in filters {
+        value = apply_filter(filter, &value, ctx)?;
+    }
+    Some(value)
+}
+
+/// Unfiltered rendering. `@{script}` has always been substituted with doubled
+/// backslashes, so existing `exec_argv_override` strings keep working.
+fn render_bare(name: &str, raw: &str) -> String {
+    if name == "script" {
+        raw.replace('\\', "\\\\")
+    } else {
+        raw.to_string()
+    }
+}
+
+/// Apply a single named filter. Returns `None` for unknown filters, and for
+/// escaping filters whose target syntax cannot represent the value.
+pub(crate) fn apply_filter(
+    filter: &str,
+    value: &str,
+    ctx: &FilterContext,
+) -> Option<String> {
+    let out = match filter {
+        "msys" => to_msys(value),
+        "cygwin" => to_cygwin(value),
+        "wsl" => to_wsl(value),
+        "short" => (ctx.short_path)(value).unwrap_or_else(|| value.to_string()),
+        "dir" => parent_dir(value),
+        "stem" => file_stem(value),
+        "ext" => extension(value),
+        "sh" => quote_sh(value),
+        "pwsh" => quote_pwsh(value),
+        "cmd" => quote_batch_arg(value)?,
+        "relative" => match &ctx.cwd {
+            Some(cwd) => relative_to(value, &cwd.to_string_lossy()),
+            None => value.to_string(),
+        },
+        _ => return None,
+    };
+    Some(out)
+}
+
+fn is_sep(c: char) -> bool {
+    c == '\\' || c == '/'
+}
+
+/// Strip a `\\?\` (or `\\?\UNC\`) verbatim prefix so the other helpers see
+/// an ordinary path.
+fn strip_verbatim(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else if let Some(rest) = path.strip_prefix(r"\\?\") {
+        rest.to_string()
+    } else {
+        path.to_string()
+    }
+}
+
+/// Split `C:\rest` into (`c`, `\rest`). Returns `None` for paths without a
+/// drive letter.
+fn split_drive(path: &str) -> Option<(char, &str)> {
+    let mut chars = path.chars();
+    let letter = chars.next()?;
+    if letter.is_ascii_alphabetic() && chars.next() == Some(':') {
+        Some((letter.to_ascii_lowercase(), &path[2..]))
+    } else {
+        None
+    }
+}
+
+/// Convert a Windows path to a POSIX path with the given drive mount prefix
+/// (`/` for MSYS2, `/cygdrive/` for Cygwin, `/mnt/` for WSL).
+fn to_posix(path: &str, mount: &str) -> String {
+    let path = strip_verbatim(path);
+    if let Some((letter, rest)) = split_drive(&path) {
+        let rest = rest.replace('\\', "/");
+        let rest = rest.trim_start_matches('/');
+        if rest.is_empty() {
+            format!("{}{}", mount, letter)
+        } else {
+            format!("{}{}/{}", mount, letter, rest)
+        }
+    } else {
+        // UNC (`\\server\share`) and relative paths only need their
+        // separators flipped.
+        path.replace('\\', "/")
+    }
+}
+
+/// `C:\x\y` → `/c/x/y`
+pub(crate) fn to_msys(path: &str) -> String {
+    to_posix(path, "/")
+}
+
+/// `C:\x\y` → `/cygdrive/c/x/y`
+pub(crate) fn to_cygwin(path: &str) -> String {
+    to_posix(path, "/cygdrive/")
+}
+
+/// `C:\x\y` → `/mnt/c/x/y`
+pub(crate) fn to_wsl(path: &str) -> String {
+    to_posix(path, "/mnt/")
+}
+
+/// Index of the byte just past the path's root (`C:\`, `\\server\share\`,
+/// `\`), or 0 for relative paths.
+fn root_len(path: &str) -> usize {
+    if let Some((_, rest)) = split_drive(path) {
+        return if rest.starts_with(is_sep) { 3 } else { 2 };
+    }
+    let bytes = path.as_bytes();
+    if bytes.len() >= 2 && is_sep(bytes[0] as char) && is_sep(bytes[1] as char)
+    {
+        // \\server\share\ — the root spans the first two components.
+        let mut seps = 0;
+        for (i, c) in path.char_indices().skip(2) {
+            if is_sep(c) {
+                seps += 1;
+                if seps == 2 {
+                    return i + 1;
+                }
+            }
+        }
+        return path.len();
+    }
+    if path.starts_with(is_sep) { 1 } else { 0 }
+}
+
+/// Parent directory. Roots are their own parent; a bare file name yields `.`.
+pub(crate) fn parent_dir(path: &str) -> String {
+    let root = root_len(path);
+    let trimmed = path.trim_end_matches(is_sep);
+    if trimmed.len() <= root {
+        return path.to_string();
+    }
+    match trimmed[root..].rfind(is_sep) {
+        Some(idx) => {
+            let dir = trimmed[..root + idx].trim_end_matches(is_sep);
+            if dir.len() < root {
+                path[..root].to_string()
+            } else {
+                dir.to_string()
+            }
+        }
+        None if root > 0 => path[..root].to_string(),
+        None => ".".to_string(),
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    let root = root_len(path);
+    let trimmed = path.trim_end_matches(is_sep);
+    if trimmed.len() <= root {
+        return "";
+    }
+    match trimmed.rfind(is_sep) {
+        Some(idx) => &trimmed[idx + 1..],
+        None => &trimmed[root..],
+    }
+}
+
+/// Split a file name at its last dot. Leading-dot names (`.bashrc`) have no
+/// extension, matching `std::path::Path`.
+fn split_ext(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(0) | None => (name, ""),
+        Some(idx) => (&name[..idx], &name[idx + 1..]),
+    }
+}
+
+/// File name without its final extension.
+pub(crate) fn file_stem(path: &str) -> String {
+    split_ext(file_name(path)).0.to_string()
+}
+
+/// Final extension without the dot, or an empty string.
+pub(crate) fn extension(path: &str) -> String {
+    split_ext(file_name(path)).1.to_string()
+}
+
+fn components(path: &str) -> Vec<&str> {
+    path.split(is_sep)
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect()
+}
+
+/// Express `path` relative to `base`. Paths on a different root (another
+/// drive or share) are returned unchanged, since no relative form exists.
+pub(crate) fn relative_to(path: &str, base: &str) -> String {
+    let path = strip_verbatim(path);
+    let base = strip_verbatim(base);
+    let (path_root, base_root) = (root_len(&path), root_len(&base));
+
+    if path_root == 0 {
+        return path;
+    }
+    let same_root = path[..path_root]
+        .replace('/', "\\")
+        .eq_ignore_ascii_case(&base[..base_root].replace('/', "\\"));
+    if !same_root {
+        return path;
+    }
+
+    let target = components(&path[path_root..]);
+    let from = components(&base[base_root..]);
+    let common = target
+        .iter()
+        .zip(&from)
+        .take_while(|(a, b)| a.eq_ignore_ascii_case(b))
+        .count();
+
+    let mut parts: Vec<&str> = vec![".."; from.len() - common];
+    parts.extend(&target[common..]);
+
+    if parts.is_empty() {
+        ".".to_string()
+    } else {
+        parts.join("\\")
+    }
+}
+
+/// Quote a value as a single POSIX shell word. Safe words are left bare;
+/// anything else is single-quoted, which suppresses every expansion (`$(`,
+/// backticks, globs, `!`).
+pub(crate) fn quote_sh(value: &str) -> String {
+    let is_safe =
+        |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !value.is_empty() && value.chars().all(is_safe) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Quote a value as a PowerShell verbatim (single-quoted) string. PowerShell
+/// also accepts the typographic single quotes as delimiters, so those are
+/// doubled too.
+pub(crate) fn quote_pwsh(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('\'');
+    for c in value.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}')
+        {
+            out.push(c);
+        }
+        out.push(c);
+    }
+    out.push('\'');
+    out
+}
+
+/// Quote a value as one argument on a cmd.exe command line, such as the one
+/// Windows builds when running a `.bat`/`.cmd` file.
+///
+/// The argument is double-quoted so `&|<>^()` lose their meaning, embedded
+/// quotes are doubled, and `%` is rewritten to `%%cd:~,%` (an always-empty
+/// substring expansion) so no environment variable can be expanded. The
+/// result still follows `CommandLineToArgvW` rules for whatever program the
+/// batch file hands it to. Returns `None` for line breaks and NULs, which
+/// cmd.exe has no way to carry.
+pub(crate) fn quote_batch_arg(value: &str) -> Option<String> {
+    if value.contains(['\r', '\n', '\0']) {
+        return None;
+    }
+    let is_safe =
+        |c: char| c.is_ascii_alphanumeric() || "_-.:/\\@+".contains(c);
+    if !value.is_empty() && value.chars().all(is_safe) {
+        return Some(value.to_string());
+    }
+
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    let mut backslashes = 0;
+    for c in value.chars() {
+        match c {
+            '\\' => {
+                backslashes += 1;
+                continue;
+            }
+            '"' => {
+                // Backslashes before a quote must be doubled to stay literal.
+                out.extend(std::iter::repeat_n('\\', backslashes * 2));
+                out.push_str("\"\"");
+            }
+            '%' => {
+                out.extend(std::iter::repeat_n('\\', backslashes));
+                out.push_str("%%cd:~,%");
+            }
+            _ => {
+                out.extend(std::iter::repeat_n('\\', backslashes));
+                out.push(c);
+            }
+        }
+        backslashes = 0;
+    }
+    // Trailing backslashes precede the closing quote.
+    out.extend(std::iter::repeat_n('\\', backslashes * 2));
+    out.push('"');
+    Some(out)
+}
+
+/// Whether a resolved runtime is a batch file, which Windows runs through
+/// cmd.exe and whose arguments therefore need `quote_batch_arg`.
+pub(crate) fn is_batch_file(path: &str) -> bool {
+    let ext = extension(path);
+    ext.eq_ignore_ascii_case("bat") || ext.eq_ignore_ascii_case("cmd")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> FilterContext {
+        FilterContext {
+            cwd: Some(PathBuf::from(r"C:\work\repo")),
+            short_path: |p| {
+                (p == r"C:\Program Files\x.sh")
+                    .then(|| r"C:\PROGRA~1\x.sh".to_string())
+            },
+        }
+    }
+
+    fn vars(script: &str) -> HashMap<&'static str, String> {
+        let mut vars = HashMap::new();
+        vars.insert("script", script.to_string());
+        vars.insert("script_unix", script.replace('\\', "/"));
+        vars
+    }
+
+    #[test]
+    fn bare_script_keeps_double_backslashes() {
+        let out = expand_placeholders("@{script}", &vars(r"C:\a\b.sh"), &ctx());
+        assert_eq!(out, r"C:\\a\\b.sh");
+    }
+
+    #[test]
+    fn bare_script_unix() {
+        let out =
+            expand_placeholders("@{script_unix}", &vars(r"C:\a\b.sh"), &ctx());
+        assert_eq!(out, "C:/a/b.sh");
+    }
+
+    #[test]
+    fn posix_filters() {
+        let v = vars(r"C:\Users\me\test.sh");
+        let c = ctx();
+        assert_eq!(
+            expand_placeholders("@{script|msys}", &v, &c),
+            "/c/Users/me/test.sh"
+        );
+        assert_eq!(
+            expand_placeholders("@{script|cygwin}", &v, &c),
+            "/cygdrive/c/Users/me/test.sh"
+        );
+        assert_eq!(
+            expand_placeholders("@{script|wsl}", &v, &c),
+            "/mnt/c/Users/me/test.sh"
+        );
+    }
+
+    #[test]
+    fn posix_filters_edge_cases() {
+        assert_eq!(to_msys(r"D:\"), "/d");
+        assert_eq!(to_msys(r"\\?\C:\x\y"), "/c/x/y");
+        assert_eq!(to_msys(r"\\server\share\x"), "//server/share/x");
+        assert_eq!(to_msys(r"\\?\UNC\server\share\x"), "//server/share/x");
+        assert_eq!(to_wsl(r"sub\x.sh"), "sub/x.sh");
+        assert_eq!(to_cygwin("C:/mixed\\seps"), "/cygdrive/c/mixed/seps");
+    }
+
+    #[test]
+    fn name_filters() {
+        let v = vars(r"C:\dir\archive.tar.gz");
+        let c = ctx();
+        assert_eq!(expand_placeholders("@{script|dir}", &v, &c), r"C:\dir");
+        assert_eq!(
+            expand_placeholders("@{script|stem}", &v, &c),
+            "archive.tar"
+        );
+        assert_eq!(expand_placeholders("@{script|ext}", &v, &c), "gz");
+    }
+
+    #[test]
+    fn name_filters_edge_cases() {
+        assert_eq!(parent_dir(r"C:\x.sh"), r"C:\");
+        assert_eq!(parent_dir(r"C:\"), r"C:\");
+        assert_eq!(parent_dir("x.sh"), ".");
+        assert_eq!(parent_dir(r"\\srv\share\x.sh"), r"\\srv\share\");
+        assert_eq!(parent_dir("C:/a/b/"), "C:/a");
+        assert_eq!(file_stem(r"C:\x\.bashrc"), ".bashrc");
+        assert_eq!(extension(r"C:\x\.bashrc"), "");
+        assert_eq!(extension(r"C:\x\script"), "");
+        assert_eq!(file_stem(r"C:\x\script"), "script");
+    }
+
+    #[test]
+    fn chained_filters() {
+        let v = vars(r"C:\Users\me\test.sh");
+        assert_eq!(
+            expand_placeholders("@{script|dir|msys}", &v, &ctx()),
+            "/c/Users/me"
+        );
+    }
+
+    #[test]
+    fn relative_filter() {
+        let c = ctx();
+        assert_eq!(
+            expand_placeholders(
+                "@{script|relative}",
+                &vars(r"C:\work\repo\bin\x.sh"),
+                &c
+            ),
+            r"bin\x.sh"
+        );
+        assert_eq!(
+            expand_placeholders(
+                "@{script|relative}",
+                &vars(r"c:\WORK\other\x.sh"),
+                &c
+            ),
+            r"..\other\x.sh"
+        );
+        assert_eq!(
+            expand_placeholders("@{script|relative}", &vars(r"D:\x.sh"), &c),
+            r"D:\x.sh"
+        );
+        assert_eq!(relative_to(r"C:\work", r"C:\work"), ".");
+    }
+
+    #[test]
+    fn short_filter() {
+        let c = ctx();
+        assert_eq!(
+            expand_placeholders(
+                "@{script|short}",
+                &vars(r"C:\Program Files\x.sh"),
+                &c
+            ),
+            r"C:\PROGRA~1\x.sh"
+        );
+        // No 8.3 name available: the long path is used as-is.
+        assert_eq!(
+            expand_placeholders(
+                "@{script|short}",
+                &vars(r"C:\long name\x.sh"),
+                &c
+            ),
+            r"C:\long name\x.sh"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_left_alone() {
+        let v = vars(r"C:\x.sh");
+        let c = ctx();
+        assert_eq!(expand_placeholders("@{nope}", &v, &c), "@{nope}");
+        assert_eq!(
+            expand_placeholders("@{script|nope}", &v, &c),
+            "@{script|nope}"
+        );
+        assert_eq!(expand_placeholders("@{script", &v, &c), "@{script");
+    }
+
+    #[test]
+    fn placeholders_embedded_in_text() {
+        let out = expand_placeholders(
+            "--file=@{script|msys} --dir @{script|dir}",
+            &vars(r"C:\a\b.sh"),
+            &ctx(),
+        );
+        assert_eq!(out, r"--file=/c/a/b.sh --dir C:\a");
+    }
+
+    /// File names chosen to break naive quoting in sh, cmd.exe and PowerShell.
+    const ADVERSARIAL: &[&str] = &[
+        "plain.sh",
+        "with space.sh",
+        "$(rm -rf ~).sh",
+        "`whoami`.sh",
+        "$HOME ${IFS}.sh",
+        "it's.sh",
+        "say \"hi\".sh",
+        "a&b|c<d>e^f.sh",
+        "%PATH%.sh",
+        "100%",
+        "!USERNAME!.sh",
+        "semi;colon,comma=eq.sh",
+        "(paren) [glob] *?.sh",
+        r"C:\dir with space\",
+        r#"back\slash\\quote\""#,
+        "\u{2018}curly\u{2019} \u{201A}low\u{201B}.ps1",
+        "-n",
+        "",
+        "tab\there",
+        "unicode-\u{e9}-\u{6f22}\u{5b57}",
+    ];
+
+    /// Characters outside quotes and backslash escapes in a POSIX word.
+    fn sh_unquoted(word: &str) -> String {
+        let mut out = String::new();
+        let mut chars = word.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => while chars.next().is_some_and(|c| c != '\'') {},
+                '\\' => {
+                    chars.next();
+                }
+                _ => out.push(c),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn sh_quoting_round_trips_adversarial_names() {
+        for name in ADVERSARIAL {
+            let quoted = quote_sh(name);
+            assert_eq!(
+                shell_words::split(&quoted).unwrap(),
+                vec![name.to_string()],
+                "{quoted}"
+            );
+            assert!(
+                sh_unquoted(&quoted)
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric()
+                        || "_@%+=:,./-".contains(c)),
+                "unquoted metacharacters in {quoted}"
+            );
+        }
+    }
+
+    /// Decode a PowerShell single-quoted string, requiring it to span the
+    /// whole input.
+    fn pwsh_unquote(quoted: &str) -> Option<String> {
+        let is_quote = |c: char| {
+            matches!(
+                c,
+                '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}'
+            )
+        };
+        let mut chars = quoted.chars().peekable();
+        if !chars.next().is_some_and(is_quote) {
+            return None;
+        }
+        let mut out = String::new();
+        while let Some(c) = chars.next() {
+            if is_quote(c) {
+                match chars.peek() {
+                    Some(&next) if is_quote(next) => {
+                        out.push(next);
+                        chars.next();
+                    }
+                    // Closing quote must be the last character.
+                    Some(_) => return None,
+                    None => return Some(out),
+                }
+            } else {
+                out.push(c);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn pwsh_quoting_round_trips_adversarial_names() {
+        for name in ADVERSARIAL {
+            let quoted = quote_pwsh(name);
+            assert_eq!(
+                pwsh_unquote(&quoted).as_deref(),
+                Some(*name),
+                "{quoted}"
+            );
+        }
+    }
+
+    /// Model what cmd.exe does to one argument, then how the program it
+    /// launches splits the result with `CommandLineToArgvW` rules. Fails if
+    /// anything could be expanded or interpreted by cmd.exe along the way.
+    fn cmd_round_trip(quoted: &str) -> Vec<String> {
+        // Percent expansion: the only `%` allowed is the `%%cd:~,%` escape.
+        let mut expanded = String::new();
+        let mut rest = quoted;
+        while let Some(idx) = rest.find('%') {
+            expanded.push_str(&rest[..idx]);
+            let tail = &rest[idx..];
+            assert!(tail.starts_with("%%cd:~,%"), "raw % in {quoted}");
+            expanded.push('%');
+            rest = &tail["%%cd:~,%".len()..];
+        }
+        expanded.push_str(rest);
+
+        // Special characters: every metacharacter must be inside quotes.
+        let mut in_quotes = false;
+        for c in expanded.chars() {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            } else if "&|<>^()!".contains(c) {
+                assert!(in_quotes, "unquoted {c:?} in {quoted}");
+            }
+        }
+        assert!(!in_quotes, "unbalanced quotes in {quoted}");
+
+        crate::cmdline::split_args(&expanded)
+    }
+
+    #[test]
+    fn batch_quoting_round_trips_adversarial_names() {
+        for name in ADVERSARIAL {
+            let quoted = quote_batch_arg(name).unwrap();
+            assert_eq!(
+                cmd_round_trip(&quoted),
+                vec![name.to_string()],
+                "{quoted}"
+            );
+        }
+    }
+
+    #[test]
+    fn batch_quoting_rejects_line_breaks() {
+        assert_eq!(quote_batch_arg("a\r\nb"), None);
+        assert_eq!(quote_batch_arg("a\nb"), None);
+        assert_eq!(quote_batch_arg("a\0b"), None);
+    }
+
+    #[test]
+    fn escaping_filters_chain_after_path_filters() {
+        let v = vars(r"C:\it's $(here)\x.sh");
+        let c = ctx();
+        assert_eq!(
+            expand_placeholders("@{script|msys|sh}", &v, &c),
+            r"'/c/it'\''s $(here)/x.sh'"
+        );
+        assert_eq!(
+            expand_placeholders("@{script|pwsh}", &v, &c),
+            r"'C:\it''s $(here)\x.sh'"
+        );
+        assert_eq!(
+            expand_placeholders("@{script|cmd}", &vars(r"C:\a&b\%x%.sh"), &c),
+            r#""C:\a&b\%%cd:~,%x%%cd:~,%.sh""#
+        );
+    }
+
+    #[test]
+    fn detects_batch_files() {
+        assert!(is_batch_file(r"C:\msys64\msys2_shell.cmd"));
+        assert!(is_batch_file(r"C:\tools\RUN.BAT"));
+        assert!(!is_batch_file(r"C:\tools\bash.exe"));
+        assert!(!is_batch_file(r"C:\tools\cmd"));
+    }
+}
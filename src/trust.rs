@@ -0,0 +1,298 @@
+//! The trust store: the SHA-256 of every script the user chose to run from
+//! the prompt, in `%APPDATA%\Winbang\trust.toml`. Unchanged scripts then run
+//! without prompting; changed ones prompt again with a summary of what
+//! changed, diffed against a copy of the approved version kept in
+//! `%APPDATA%\Winbang\trusted`.
+
+use std::path::{self, Path, PathBuf};
+use std::{env, fs, io};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::{ChangeTag, TextDiff};
+
+use crate::location::canonical_path;
+use crate::log_info;
+use crate::policy::normalize_path;
+
+/// Largest script whose approved copy is kept for diffing.
+const SNAPSHOT_LIMIT: u64 = 1024 * 1024;
+
+/// Trust store file, `%APPDATA%\Winbang\trust.toml`. Fails when `APPDATA`
+/// isn't set, rather than keeping a store in the current directory.
+pub(crate) fn store_path() -> io::Result<PathBuf> {
+    let appdata = env::var_os("APPDATA")
+        .filter(|a| !a.is_empty())
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "%APPDATA% is not set")
+        })?;
+    Ok(PathBuf::from(appdata).join("Winbang").join("trust.toml"))
+}
+
+/// The scripts the user trusts, as loaded from the store file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub(crate) struct TrustStore {
+    #[serde(default, rename = "script")]
+    pub(crate) scripts: Vec<TrustedScript>,
+    /// The file this store was read from and is saved to.
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+/// One trusted script.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub(crate) struct TrustedScript {
+    /// Canonical path to the script.
+    pub(crate) path: PathBuf,
+    /// SHA-256 of its contents when trusted, in lowercase hex.
+    pub(crate) sha256: String,
+}
+
+/// A script's standing in the trust store.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Trust {
+    /// Trusted and unchanged.
+    Trusted,
+    /// Trusted once, but changed since: a summary of the changes.
+    Changed(String),
+    /// Never trusted.
+    Unknown,
+}
+
+impl TrustStore {
+    /// Load the store, or an empty one if the file doesn't exist yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`: Store file, usually `store_path()`.
+    ///
+    /// returns: Result<TrustStore, Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let store = TrustStore::load(&store_path()?)?;
+    /// ```
+    pub(crate) fn load(path: &Path) -> io::Result<TrustStore> {
+        let mut store = match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), e),
+                )
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                TrustStore::default()
+            }
+            Err(e) => return Err(e),
+        };
+        store.path = path.to_path_buf();
+        Ok(store)
+    }
+
+    /// Write the store back to the file it was loaded from.
+    pub(crate) fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string(self).map_err(io::Error::other)?;
+        fs::write(&self.path, text)
+    }
+
+    /// Check `script` against the store.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: Script about to run.
+    ///
+    /// returns: Result<Trust, Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// if store.check(&script.file_path)? == Trust::Trusted {
+    ///     command.spawn()?;
+    /// }
+    /// ```
+    pub(crate) fn check(&self, script: &Path) -> io::Result<Trust> {
+        let Some(entry) = self.find(script) else {
+            return Ok(Trust::Unknown);
+        };
+        let contents = fs::read(script)?;
+        if sha256_hex(&contents) == entry.sha256 {
+            return Ok(Trust::Trusted);
+        }
+
+        let summary = match fs::read(self.snapshot(&entry.sha256)) {
+            Ok(approved) => diff_summary(
+                &String::from_utf8_lossy(&approved),
+                &String::from_utf8_lossy(&contents),
+            ),
+            Err(_) => "its contents differ from the version you ran".into(),
+        };
+        Ok(Trust::Changed(summary))
+    }
+
+    /// Trust `script` as it is now, replacing any earlier entry. Call
+    /// `save` to keep the change.
+    ///
+    /// # Arguments
+    ///
+    /// * `script`: Script to trust.
+    ///
+    /// returns: Result<TrustedScript, Error>
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// store.add(Path::new("build.sh"))?;
+    /// store.save()?;
+    /// ```
+    pub(crate) fn add(&mut self, script: &Path) -> io::Result<TrustedScript> {
+        let contents = fs::read(script)?;
+        let entry = TrustedScript {
+            path: script_key(script),
+            sha256: sha256_hex(&contents),
+        };
+        self.revoke(script);
+
+        if contents.len() as u64 <= SNAPSHOT_LIMIT {
+            let snapshot = self.snapshot(&entry.sha256);
+            if let Some(dir) = snapshot.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(snapshot, &contents)?;
+        }
+        log_info!("Trusted {:?}", entry);
+        self.scripts.push(entry.clone());
+        Ok(entry)
+    }
+
+    /// Stop trusting `script`, returning its entry if it was trusted. Call
+    /// `save` to keep the change.
+    pub(crate) fn revoke(&mut self, script: &Path) -> Option<TrustedScript> {
+        let key = normalize_path(&script_key(script).to_string_lossy());
+        let index = self.scripts.iter().position(|entry| {
+            normalize_path(&entry.path.to_string_lossy()) == key
+        })?;
+        let entry = self.scripts.remove(index);
+        if !self.scripts.iter().any(|e| e.sha256 == entry.sha256) {
+            let _ = fs::remove_file(self.snapshot(&entry.sha256));
+        }
+        Some(entry)
+    }
+
+    /// The entry for `script`, if it is trusted.
+    pub(crate) fn find(&self, script: &Path) -> Option<&TrustedScript> {
+        let key = normalize_path(&script_key(script).to_string_lossy());
+        self.scripts
+            .iter()
+            .find(|entry| normalize_path(&entry.path.to_string_lossy()) == key)
+    }
+
+    fn snapshot(&self, sha256: &str) -> PathBuf {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        dir.join("trusted").join(sha256)
+    }
+}
+
+/// The path a script is stored under: canonical, so links to a trusted
+/// script aren't separate entries, or absolute if it can't be resolved.
+fn script_key(script: &Path) -> PathBuf {
+    canonical_path(script).unwrap_or_else(|| {
+        path::absolute(script).unwrap_or_else(|_| script.to_path_buf())
+    })
+}
+
+/// SHA-256 of `bytes` in lowercase hex.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A one-line summary of the line changes from `old` to `new`, e.g.
+/// `2 lines added, 1 removed (at lines 4, 12)`.
+fn diff_summary(old: &str, new: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let (mut added, mut removed) = (0, 0);
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => added += 1,
+            ChangeTag::Delete => removed += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+    if added + removed == 0 {
+        return "only line endings or encoding changed".to_string();
+    }
+
+    let hunks = diff.grouped_ops(0);
+    let mut at: Vec<String> = hunks
+        .iter()
+        .take(5)
+        .filter_map(|ops| ops.first())
+        .map(|op| (op.new_range().start + 1).to_string())
+        .collect();
+    if hunks.len() > at.len() {
+        at.push("...".to_string());
+    }
+    let plural = |n: usize| if n == 1 { "line" } else { "lines" };
+    format!(
+        "{} {} added, {} removed (at {} {})",
+        added,
+        plural(added),
+        removed,
+        plural(hunks.len()),
+        at.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approved_scripts_stay_trusted_until_changed() {
+        let root = env::temp_dir()
+            .join(format!("winbang-trust-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let script = root.join("build.sh");
+        fs::write(&script, "echo one\necho two\necho three\n").unwrap();
+        let store_file = root.join("Winbang").join("trust.toml");
+
+        let mut store = TrustStore::load(&store_file).unwrap();
+        assert_eq!(store.check(&script).unwrap(), Trust::Unknown);
+        let entry = store.add(&script).unwrap();
+        store.save().unwrap();
+        let snapshot = root.join("Winbang").join("trusted").join(&entry.sha256);
+        assert!(snapshot.exists());
+
+        let mut store = TrustStore::load(&store_file).unwrap();
+        assert_eq!(store.scripts, vec![entry]);
+        assert_eq!(store.check(&script).unwrap(), Trust::Trusted);
+
+        fs::write(&script, "echo one\necho 2\necho three\nrm -rf /\n").unwrap();
+        assert_eq!(
+            store.check(&script).unwrap(),
+            Trust::Changed(
+                "2 lines added, 1 removed (at lines 2, 4)".to_string()
+            )
+        );
+
+        assert!(store.revoke(&script).is_some());
+        assert!(store.revoke(&script).is_none());
+        assert_eq!(store.check(&script).unwrap(), Trust::Unknown);
+        assert!(!snapshot.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn hashes_match_sha256() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -1,304 +1,874 @@
-#![cfg(target_os = "windows")]
-
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::{env, fmt, fs};
 
-use crate::config::{Config, FileAssociation};
-use crate::install::self_progid;
+use crate::actions::run_action;
+use crate::audit::{self, Operation};
+use crate::cmdline::{escape_quoted, launches, parse_command, quote_arg};
+use crate::config::{Config, FileAssociation, VerbConfig, VerbFallback};
+use crate::dispatch::{resolve_view_runtime, run_to_exit};
+use crate::env_expand::{current_env, expand_env_vars};
+use crate::error::WinbangError;
+use crate::install::{progid_for, self_exe_basename};
 use crate::log_debug;
-use crate::registry::{Root, read_string};
-
-/// Result of dispatching a verb. The numeric value is propagated as the
-/// process exit code so callers (e.g. AHK's ShellExecuteEx fallback chain)
-/// can react meaningfully.
-pub enum DispatchOutcome {
-    /// The verb's command was resolved and the child process ran. Carries the
-    /// child's exit code.
-    Ran(i32),
-    /// The verb could not be resolved; the caller should fall back to its own
-    /// default. Carries a suggested exit code (always non-zero).
-    Unresolved,
-}
+use crate::policy::check_launch;
+use crate::progid::handler_keys;
+use crate::registry::{RegValue, RegistryBackend, Root};
+use crate::script::{ScriptMetadata, get_script_metadata};
+use crate::zone::NtfsStreams;
 
-impl DispatchOutcome {
-    pub fn exit_code(&self) -> i32 {
-        match self {
-            DispatchOutcome::Ran(code) => *code,
-            DispatchOutcome::Unresolved => 1,
-        }
-    }
-}
-
-/// Dispatch a non-Open shell verb by resolving the underlying ProgID's
-/// command (or a config override) and spawning it.
+/// Dispatch a non-Open shell verb: run its configured built-in action, or
+/// resolve a command (from config or the underlying ProgID) and spawn it.
+/// Returns the child's exit code. The error's exit code tells callers such
+/// as AHK's ShellExecuteEx fallback chain why nothing ran: a verb with no
+/// handler is `WinbangError::Unresolved`, which they can take as a cue to
+/// fall back to their own default.
 pub fn dispatch_verb(
+    registry: &dyn RegistryBackend,
     verb: &str,
     file_path: &Path,
     extra_args: &[String],
     config: &Config,
-) -> DispatchOutcome {
-    log_debug!(&format!(
+) -> Result<i32, WinbangError> {
+    log_debug!(
         "dispatch_verb: verb={:?}, file={:?}, extra={:?}",
-        verb, file_path, extra_args
-    ));
+        verb,
+        file_path,
+        extra_args
+    );
+
+    let assoc = association_for(config, file_path);
+    if let Some(assoc) = &assoc {
+        audit::record_association(assoc);
+    }
+    if let Some(action) =
+        configured_verb(config, assoc.as_ref(), verb).and_then(|v| v.action)
+    {
+        return run_action(action, file_path, extra_args, config);
+    }
+
+    let self_exe = self_exe_basename();
+    match resolve_template(
+        registry,
+        verb,
+        file_path,
+        assoc.as_ref(),
+        config,
+        self_exe.as_deref(),
+    ) {
+        Some(template) => {
+            spawn_template(&template, file_path, extra_args, config)
+        }
+        None => Err(WinbangError::Unresolved(format!(
+            "no handler for the {:?} verb on {}",
+            verb,
+            file_path.display()
+        ))),
+    }
+}
 
-    let extension = file_path
+/// Find the command template for a verb: its configured template (see
+/// `configured_verb`), else the first `shell\<verb>\command` along Explorer's
+/// handler chain for the extension (see `progid::handler_keys`), else the
+/// verb's fallback (by default, the first `open` command on the chain).
+/// Files without an extension use the `.` key's chain, and when that has no
+/// `open` command either, a script with a known association opens in its
+/// viewer instead.
+/// `self_exe` is Winbang's own exe name; its ProgID and any command that
+/// launches it are never used as a handler.
+fn resolve_template(
+    registry: &dyn RegistryBackend,
+    verb: &str,
+    file_path: &Path,
+    assoc: Option<&FileAssociation>,
+    config: &Config,
+    self_exe: Option<&str>,
+) -> Option<String> {
+    let ext = file_path
         .extension()
         .and_then(|s| s.to_str())
-        .map(|s| s.to_ascii_lowercase());
-
-    // Step 2: config override per-(extension, verb).
-    if let Some(ref ext) = extension {
-        if let Some(assoc) = find_association(config, ext) {
-            if let Some(template) = override_for_verb(&assoc, verb) {
-                log_debug!(&format!(
-                    "dispatch_verb: using config override for .{}/{} -> {}",
-                    ext, verb, template
-                ));
-                return spawn_template(&template, file_path, extra_args);
-            }
-        }
+        .map(|s| s.to_ascii_lowercase())
+        .unwrap_or_default();
+    let verb_config = configured_verb(config, assoc, verb);
+
+    // Step 2: config template per-(extension, verb).
+    if let Some(template) = verb_config.and_then(|v| v.template.clone()) {
+        log_debug!(
+            "dispatch_verb: using config template for .{}/{} -> {}",
+            ext,
+            verb,
+            template
+        );
+        return Some(template);
     }
+    let fallback = verb_config.map(|v| v.fallback).unwrap_or_default();
 
-    // Step 3: resolve the underlying ProgID.
-    let Some(ext) = extension else {
-        log_debug!("dispatch_verb: no extension on file path, cannot resolve");
-        return DispatchOutcome::Unresolved;
-    };
+    // Step 3: collect the keys that can supply verbs for the extension.
+    if self_exe.is_none() {
+        log_debug!(
+            "dispatch_verb: could not determine self exe; skipping self-loop guard"
+        );
+    }
+    let self_id = self_exe.map(progid_for);
+    let keys = handler_keys(registry, &ext, self_id.as_deref());
+    log_debug!("dispatch_verb: handler chain for .{}: {:?}", ext, keys);
 
-    let progid = match resolve_underlying_progid(&ext) {
-        Some(p) => p,
-        None => {
-            log_debug!(&format!(
-                "dispatch_verb: no underlying ProgID for .{}",
-                ext
-            ));
-            return DispatchOutcome::Unresolved;
-        }
+    // Step 4: read the verb's command, with open-verb fallback.
+    let find = |verb: &str| {
+        keys.iter().find_map(|key| {
+            let command = read_verb_command(registry, key, verb)?;
+            if self_exe.is_some_and(|exe| launches(&command, exe)) {
+                log_debug!(
+                    "dispatch_verb: {}\\shell\\{} launches Winbang itself; \
+                    skipping",
+                    key,
+                    verb
+                );
+                return None;
+            }
+            Some(command)
+        })
     };
 
-    if let Some(self_id) = self_progid() {
-        if progid.eq_ignore_ascii_case(&self_id) {
-            log_debug!(&format!(
-                "dispatch_verb: underlying ProgID for .{} is Winbang itself \
-                ({}); refusing to self-loop. Use UserChoice to set Winbang \
-                as the handler instead of overwriting HKCR\\.{}",
-                ext, progid, ext
-            ));
-            return DispatchOutcome::Unresolved;
-        }
-    } else {
+    let template = find(verb).or_else(|| {
         log_debug!(
-            "dispatch_verb: could not determine self ProgID; skipping self-loop guard"
+            "dispatch_verb: no shell\\{}\\command for .{}; fallback {:?}",
+            verb,
+            ext,
+            fallback
         );
+        match fallback {
+            VerbFallback::Open => find("open").or_else(|| {
+                (ext.is_empty() && assoc.is_some())
+                    .then(|| editor_template(file_path, assoc, config))
+            }),
+            VerbFallback::Editor => {
+                Some(editor_template(file_path, assoc, config))
+            }
+            VerbFallback::None => None,
+        }
+    });
+    if template.is_none() {
+        log_debug!("dispatch_verb: no handler for .{}", ext);
     }
+    template
+}
 
-    // Step 4: read the verb's command, with open-verb fallback.
-    let template = match read_verb_command(&progid, verb) {
-        Some(t) => t,
+/// The association whose verbs apply to a file: the one for its extension,
+/// or for a file without one, the one its shebang matches.
+fn association_for(
+    config: &Config,
+    file_path: &Path,
+) -> Option<FileAssociation> {
+    match file_path.extension().and_then(|s| s.to_str()) {
+        Some(ext) => find_association(config, ext).cloned(),
         None => {
-            log_debug!(&format!(
-                "dispatch_verb: no shell\\{}\\command on {}; trying open",
-                verb, progid
-            ));
-            match read_verb_command(&progid, "open") {
-                Some(t) => t,
-                None => {
-                    log_debug!(&format!(
-                        "dispatch_verb: no open-verb fallback on {} either",
-                        progid
-                    ));
-                    return DispatchOutcome::Unresolved;
-                }
-            }
+            get_script_metadata(
+                &file_path.to_string_lossy().to_string(),
+                config.file_associations.as_deref().unwrap_or(&[]),
+            )
+            .association
         }
-    };
+    }
+}
 
-    spawn_template(&template, file_path, extra_args)
+/// The config entry for a verb on this file: the one in its association,
+/// else the `[context_menu]` one.
+fn configured_verb<'a>(
+    config: &'a Config,
+    assoc: Option<&'a FileAssociation>,
+    verb: &str,
+) -> Option<&'a VerbConfig> {
+    assoc
+        .and_then(|assoc| assoc.verb(verb))
+        .or_else(|| config.context_menu_verb(verb))
 }
 
 fn find_association<'a>(
     config: &'a Config,
     extension: &str,
 ) -> Option<&'a FileAssociation> {
-    config
-        .file_associations
-        .as_deref()?
-        .iter()
-        .find(|a| a.extension.as_deref().map(|e| e.eq_ignore_ascii_case(extension)).unwrap_or(false))
+    config.file_associations.as_deref()?.iter().find(|a| {
+        a.extension
+            .as_deref()
+            .map(|e| e.eq_ignore_ascii_case(extension))
+            .unwrap_or(false)
+    })
 }
 
-fn override_for_verb(assoc: &FileAssociation, verb: &str) -> Option<String> {
-    match verb.to_ascii_lowercase().as_str() {
-        "edit" => assoc.verb_edit.clone(),
-        "print" => assoc.verb_print.clone(),
-        "printto" => assoc.verb_printto.clone(),
-        "runas" => assoc.verb_runas.clone(),
-        "uiaccess" => assoc.verb_uiaccess.clone(),
-        _ => None,
-    }
-}
-
-fn resolve_underlying_progid(ext_lower: &str) -> Option<String> {
-    let ext_key = format!(".{}", ext_lower);
-    let default = read_string(Root::ClassesRoot, &ext_key, "");
-    if let Some(v) = default.as_deref() {
-        if !v.is_empty() {
-            return Some(v.to_string());
-        }
-    }
-    // OpenWithProgids fallback: take any one entry.
-    let openwith_key = format!(".{}\\OpenWithProgids", ext_lower);
-    crate::registry::first_value_name(Root::ClassesRoot, &openwith_key)
+/// A command template that opens the file in the viewer the `open` path
+/// would use for it.
+fn editor_template(
+    file_path: &Path,
+    assoc: Option<&FileAssociation>,
+    config: &Config,
+) -> String {
+    let script = ScriptMetadata {
+        shebang_arg: None,
+        association: assoc.cloned(),
+        file_path: file_path.to_path_buf(),
+        file_size: fs::metadata(file_path).map(|m| m.len()).unwrap_or_default(),
+    };
+    format!(
+        "{} \"%1\"",
+        quote_arg(&resolve_view_runtime(&script, config))
+    )
 }
 
-fn read_verb_command(progid: &str, verb: &str) -> Option<String> {
+fn read_verb_command(
+    registry: &dyn RegistryBackend,
+    progid: &str,
+    verb: &str,
+) -> Option<String> {
     let key = format!("{}\\shell\\{}\\command", progid, verb);
-    let val = read_string(Root::ClassesRoot, &key, "")?;
+    let val = match registry.read_value(Root::ClassesRoot, &key, "")? {
+        RegValue::Sz(s) => s,
+        RegValue::ExpandSz(s) => expand_env_vars(&s, &current_env()),
+        _ => return None,
+    };
     if val.is_empty() { None } else { Some(val) }
 }
 
-/// Expand Windows %-tokens and spawn the resulting argv.
+/// Expand Windows %-tokens and spawn the resulting command line, split with
+/// `CommandLineToArgvW` rules rather than POSIX ones. The program it names
+/// is checked against policy like any interpreter.
 fn spawn_template(
     template: &str,
     file_path: &Path,
     extra_args: &[String],
-) -> DispatchOutcome {
-    let expanded = expand_tokens(template, file_path, extra_args);
-    log_debug!(&format!("spawn_template: expanded={:?}", expanded));
-
-    let argv = match shell_words::split(&expanded) {
-        Ok(v) => v,
-        Err(e) => {
-            log_debug!(&format!(
-                "spawn_template: shell_words::split failed: {}",
-                e
-            ));
-            return DispatchOutcome::Unresolved;
-        }
-    };
+    config: &Config,
+) -> Result<i32, WinbangError> {
+    let working_dir = env::current_dir().unwrap_or_default();
+    let expanded = expand_tokens(template, file_path, extra_args, &working_dir)
+        .map_err(|e| {
+            log_debug!("spawn_template: {} in {:?}", e, template);
+            WinbangError::Unresolved(format!("{} in {:?}", e, template))
+        })?;
+    log_debug!("spawn_template: expanded={:?}", expanded);
 
-    if argv.is_empty() {
-        log_debug!("spawn_template: empty argv after split");
-        return DispatchOutcome::Unresolved;
-    }
+    let Some(parsed) = parse_command(&expanded, |p| Path::new(p).is_file())
+    else {
+        log_debug!("spawn_template: empty command line");
+        return Err(WinbangError::Unresolved(format!(
+            "empty verb command {:?}",
+            template
+        )));
+    };
 
-    let exe = PathBuf::from(&argv[0]);
+    check_launch(config, &parsed.program, file_path, &NtfsStreams)
+        .map_err(|blocked| WinbangError::blocked(file_path, blocked))?;
+    let exe = PathBuf::from(&parsed.program);
     let mut cmd = Command::new(&exe);
-    cmd.args(&argv[1..]);
+    cmd.args(&parsed.args);
     cmd.stdin(Stdio::inherit())
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit());
+    audit::record_command(&cmd);
+    audit::record_operation(Operation::Execute);
 
-    match cmd.spawn() {
-        Ok(mut child) => match child.wait() {
-            Ok(status) => DispatchOutcome::Ran(status.code().unwrap_or(0)),
-            Err(e) => {
-                log_debug!(&format!("spawn_template: wait() failed: {}", e));
-                DispatchOutcome::Unresolved
-            }
-        },
-        Err(e) => {
-            log_debug!(&format!(
-                "spawn_template: spawn failed for {:?}: {}",
-                exe, e
-            ));
-            DispatchOutcome::Unresolved
-        }
+    run_to_exit(&mut cmd).inspect_err(|e| {
+        log_debug!("spawn_template: {:?} failed: {}", exe, e);
+    })
+}
+
+/// A `%` token in a verb command that Winbang cannot expand.
+#[derive(Debug, PartialEq)]
+pub struct UnsupportedToken(pub char);
+
+impl fmt::Display for UnsupportedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported shell verb token %{}", self.0)
     }
 }
 
+/// `SW_SHOWNORMAL`, substituted for `%S`.
+const SHOW_NORMAL: &str = "1";
+
 /// Expand Windows shell %-tokens in a command template.
 ///
-/// Handles `%1`, `%L`, `%V` → file path; `%2` → first extra arg; `%*` →
-/// space-joined extra args. Tokens are quoted when needed. Unrecognized
-/// `%` sequences are left as-is, matching Windows' tolerant behavior.
+/// * `%0`, `%1`, `%L`, `%D`, `%V` → the file path;
+/// * `%I` → the file path too: Winbang only handles file system items, whose
+///   item ID list stands for their path;
+/// * `%2`…`%9` → the corresponding extra argument (empty if absent);
+/// * `%*`, `%~` → every extra argument;
+/// * `%W` → `working_dir`; `%S` → the show command; `%H` → hotkey (`0`);
+/// * `%%` → a literal `%`.
+///
+/// Values are quoted per argument: outside template quotes they go through
+/// `quote_arg`, inside them through `escape_quoted`, so paths and extra
+/// arguments with blanks or quotes survive `CommandLineToArgvW` splitting.
+/// Letter tokens are case-insensitive. A `%` followed by a blank, quote or
+/// the end of the string is literal. Any other token is an error.
 fn expand_tokens(
     template: &str,
     file_path: &Path,
     extra_args: &[String],
-) -> String {
-    let file_str = file_path.to_string_lossy().to_string();
-    let extra_joined =
-        extra_args.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(" ");
-    let extra_first = extra_args.first().map(|s| s.as_str()).unwrap_or("");
+    working_dir: &Path,
+) -> Result<String, UnsupportedToken> {
+    let file_str = file_path.to_string_lossy();
+    let working_str = working_dir.to_string_lossy();
 
     let mut out = String::with_capacity(template.len());
+    let mut in_quotes = false;
     let mut chars = template.chars().peekable();
+
+    // Append one value, quoted to match where it lands in the template.
+    let push = |out: &mut String, value: &str, in_quotes: bool| {
+        if in_quotes {
+            out.push_str(&escape_quoted(value));
+        } else {
+            out.push_str(&quote_arg(value));
+        }
+    };
+
     while let Some(c) = chars.next() {
         if c != '%' {
+            if c == '"' {
+                in_quotes = !in_quotes;
+            }
             out.push(c);
             continue;
         }
-        match chars.peek() {
-            Some('1') | Some('L') | Some('V') => {
-                chars.next();
-                out.push_str(&file_str);
-            }
-            Some('2') => {
-                chars.next();
-                out.push_str(extra_first);
-            }
-            Some('*') => {
-                chars.next();
-                out.push_str(&extra_joined);
+
+        let Some(&token) = chars.peek() else {
+            out.push('%');
+            continue;
+        };
+        if token == '"' || token.is_whitespace() {
+            out.push('%');
+            continue;
+        }
+        chars.next();
+
+        match token.to_ascii_uppercase() {
+            '0' | '1' | 'L' | 'D' | 'V' | 'I' => {
+                push(&mut out, &file_str, in_quotes)
             }
-            Some('%') => {
-                // %% → literal %
-                chars.next();
-                out.push('%');
+            d @ '2'..='9' => {
+                let idx = d as usize - '2' as usize;
+                let value = extra_args.get(idx).map(String::as_str);
+                push(&mut out, value.unwrap_or(""), in_quotes);
             }
-            _ => {
-                out.push('%');
+            '*' | '~' => {
+                let quoted: Vec<String> = extra_args
+                    .iter()
+                    .map(|arg| {
+                        if in_quotes {
+                            escape_quoted(arg)
+                        } else {
+                            quote_arg(arg)
+                        }
+                    })
+                    .collect();
+                out.push_str(&quoted.join(" "));
             }
+            'W' => push(&mut out, &working_str, in_quotes),
+            'S' => out.push_str(SHOW_NORMAL),
+            'H' => out.push('0'),
+            '%' => out.push('%'),
+            _ => return Err(UnsupportedToken(token)),
         }
     }
-    out
+    Ok(out)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::VerbAction;
+    use crate::registry::{MemoryRegistry, regfile};
+
+    fn expand(
+        template: &str,
+        file: &str,
+        extra: &[&str],
+    ) -> Result<String, UnsupportedToken> {
+        let extra: Vec<String> = extra.iter().map(|s| s.to_string()).collect();
+        expand_tokens(template, Path::new(file), &extra, Path::new("C:\\cwd"))
+    }
 
     #[test]
     fn expand_percent_one() {
-        let out = expand_tokens(
-            "\"%1\" /flag",
-            Path::new("C:\\foo bar\\baz.ahk"),
-            &[],
+        let out = expand("\"%1\" /flag", "C:\\foo bar\\baz.ahk", &[]);
+        assert_eq!(out.unwrap(), "\"C:\\foo bar\\baz.ahk\" /flag");
+    }
+
+    #[test]
+    fn expand_unquoted_percent_one_is_quoted() {
+        let out = expand("notepad.exe %1", "C:\\foo bar\\baz.txt", &[]);
+        assert_eq!(out.unwrap(), "notepad.exe \"C:\\foo bar\\baz.txt\"");
+    }
+
+    #[test]
+    fn expand_file_token_aliases() {
+        let out = expand("%0 %L %l %D %V %I %i", "C:\\x.ahk", &[]);
+        assert_eq!(
+            out.unwrap(),
+            "C:\\x.ahk C:\\x.ahk C:\\x.ahk C:\\x.ahk C:\\x.ahk C:\\x.ahk \
+             C:\\x.ahk"
         );
-        assert_eq!(out, "\"C:\\foo bar\\baz.ahk\" /flag");
     }
 
     #[test]
     fn expand_percent_star() {
-        let out = expand_tokens(
-            "\"%1\" %*",
-            Path::new("C:\\x.ahk"),
-            &["a".to_string(), "b".to_string()],
+        let out = expand("\"%1\" %*", "C:\\x.ahk", &["a", "b"]);
+        assert_eq!(out.unwrap(), "\"C:\\x.ahk\" a b");
+    }
+
+    #[test]
+    fn expand_percent_star_quotes_each_argument() {
+        let out = expand("app %*", "C:\\x", &["two words", "say \"hi\"", ""]);
+        let out = out.unwrap();
+        assert_eq!(out, "app \"two words\" \"say \\\"hi\\\"\" \"\"");
+        assert_eq!(
+            crate::cmdline::split_command_line(&out),
+            vec!["app", "two words", "say \"hi\"", ""]
         );
-        assert_eq!(out, "\"C:\\x.ahk\" a b");
+    }
+
+    #[test]
+    fn expand_percent_tilde() {
+        let out = expand("app %~", "C:\\x", &["a b", "c"]);
+        assert_eq!(out.unwrap(), "app \"a b\" c");
     }
 
     #[test]
     fn expand_printto_percent_two() {
-        let out = expand_tokens(
-            "\"%1\" \"%2\"",
-            Path::new("C:\\x.doc"),
-            &["My Printer".to_string()],
+        let out = expand("\"%1\" \"%2\"", "C:\\x.doc", &["My Printer"]);
+        assert_eq!(out.unwrap(), "\"C:\\x.doc\" \"My Printer\"");
+    }
+
+    #[test]
+    fn expand_numbered_arguments() {
+        let out = expand("%3 %2 %9", "a", &["two", "three"]);
+        assert_eq!(out.unwrap(), "three two \"\"");
+    }
+
+    #[test]
+    fn expand_context_tokens() {
+        let out = expand("app /dir:\"%W\" /show %S /key %H", "a", &[]);
+        assert_eq!(out.unwrap(), "app /dir:\"C:\\cwd\" /show 1 /key 0");
+    }
+
+    #[test]
+    fn expand_trailing_backslash_inside_quotes() {
+        let out = expand("app \"%2\" next", "a", &["C:\\dir\\"]).unwrap();
+        assert_eq!(
+            crate::cmdline::split_command_line(&out),
+            vec!["app", "C:\\dir\\", "next"]
         );
-        assert_eq!(out, "\"C:\\x.doc\" \"My Printer\"");
     }
 
     #[test]
     fn expand_double_percent_literal() {
-        let out = expand_tokens("100%% done %1", Path::new("a"), &[]);
-        assert_eq!(out, "100% done a");
+        let out = expand("100%% done %1", "a", &[]);
+        assert_eq!(out.unwrap(), "100% done a");
+    }
+
+    #[test]
+    fn expand_lone_percent_is_literal() {
+        assert_eq!(expand("100% done", "a", &[]).unwrap(), "100% done");
+        assert_eq!(expand("50%", "a", &[]).unwrap(), "50%");
     }
 
     #[test]
-    fn expand_unrecognized_passthrough() {
-        let out = expand_tokens("%Z stays", Path::new("a"), &[]);
-        assert_eq!(out, "%Z stays");
+    fn expand_unsupported_token_is_an_error() {
+        assert_eq!(expand("%Z stays", "a", &[]), Err(UnsupportedToken('Z')));
+        assert_eq!(expand("app %J", "a", &[]), Err(UnsupportedToken('J')));
+    }
+
+    fn config() -> Config {
+        toml::from_str("").unwrap()
+    }
+
+    fn python_registry() -> MemoryRegistry {
+        let reg = MemoryRegistry::new();
+        reg.write_string(Root::ClassesRoot, ".py", "", "Python.File");
+        reg.write_string(
+            Root::ClassesRoot,
+            "Python.File\\shell\\open\\command",
+            "",
+            "\"C:\\Python\\py.exe\" \"%1\" %*",
+        );
+        reg.write_string(
+            Root::ClassesRoot,
+            "Python.File\\shell\\Edit with IDLE\\command",
+            "",
+            "idle.exe \"%1\"",
+        );
+        reg
+    }
+
+    fn resolve(reg: &MemoryRegistry, verb: &str, file: &str) -> Option<String> {
+        let config = config();
+        let assoc = association_for(&config, Path::new(file));
+        resolve_template(
+            reg,
+            verb,
+            Path::new(file),
+            assoc.as_ref(),
+            &config,
+            Some("winbang.exe"),
+        )
+    }
+
+    fn fixture(text: &str) -> MemoryRegistry {
+        let reg = MemoryRegistry::new();
+        regfile::load(&reg, text).unwrap();
+        reg
+    }
+
+    #[test]
+    fn resolves_verb_on_underlying_progid() {
+        let reg = python_registry();
+        assert_eq!(
+            resolve(&reg, "Edit with IDLE", "x.py").as_deref(),
+            Some("idle.exe \"%1\"")
+        );
+    }
+
+    #[test]
+    fn missing_verb_falls_back_to_open() {
+        let reg = python_registry();
+        assert_eq!(
+            resolve(&reg, "print", "x.PY").as_deref(),
+            Some("\"C:\\Python\\py.exe\" \"%1\" %*")
+        );
+    }
+
+    #[test]
+    fn falls_back_to_open_with_progids() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(
+            Root::ClassesRoot,
+            ".ahk\\OpenWithProgids",
+            "AutoHotkey",
+            "",
+        );
+        reg.write_string(
+            Root::ClassesRoot,
+            "AutoHotkey\\shell\\edit\\command",
+            "",
+            "notepad.exe %1",
+        );
+        assert_eq!(
+            resolve(&reg, "edit", "x.ahk").as_deref(),
+            Some("notepad.exe %1")
+        );
+    }
+
+    #[test]
+    fn refuses_to_resolve_to_itself() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(
+            Root::ClassesRoot,
+            ".sh",
+            "",
+            "Applications\\winbang.exe",
+        );
+        reg.write_string(
+            Root::ClassesRoot,
+            "Applications\\winbang.exe\\shell\\open\\command",
+            "",
+            "winbang.exe %1",
+        );
+        assert_eq!(resolve(&reg, "edit", "x.sh"), None);
+    }
+
+    #[test]
+    fn skips_other_progids_that_launch_winbang() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(Root::ClassesRoot, ".sh", "", "sh_auto_file");
+        reg.write_string(
+            Root::ClassesRoot,
+            "sh_auto_file\\shell\\open\\command",
+            "",
+            "\"C:\\Tools\\WinBang.exe\" \"%1\"",
+        );
+        reg.write_string(
+            Root::ClassesRoot,
+            "*\\shell\\open\\command",
+            "",
+            "viewer.exe %1",
+        );
+        assert_eq!(
+            resolve(&reg, "edit", "x.sh").as_deref(),
+            Some("viewer.exe %1")
+        );
+    }
+
+    fn resolve_with(
+        reg: &MemoryRegistry,
+        config: &str,
+        verb: &str,
+        file: &str,
+    ) -> Option<String> {
+        let config: Config = toml::from_str(config).unwrap();
+        let assoc = association_for(&config, Path::new(file));
+        resolve_template(
+            reg,
+            verb,
+            Path::new(file),
+            assoc.as_ref(),
+            &config,
+            None,
+        )
+    }
+
+    const PY_VERBS: &str = r#"
+        [default]
+        view_runtime = "viewer"
+
+        [[file_associations]]
+        exec_runtime = "python"
+        extension = "py"
+        view_runtime = "thonny"
+        verb_print = "legacy-print %1"
+
+        [file_associations.verbs]
+        lint = "ruff.exe check %1"
+        edit = { label = "Edit", fallback = "editor" }
+        runas = { fallback = "none" }
+        "#;
+
+    #[test]
+    fn config_template_wins_for_any_verb_name() {
+        let reg = python_registry();
+        assert_eq!(
+            resolve_with(&reg, PY_VERBS, "LINT", "x.py").as_deref(),
+            Some("ruff.exe check %1")
+        );
+        assert_eq!(
+            resolve_with(&reg, PY_VERBS, "print", "x.py").as_deref(),
+            Some("legacy-print %1")
+        );
+        // Verbs the association doesn't mention resolve as before.
+        assert_eq!(
+            resolve_with(&reg, PY_VERBS, "Edit with IDLE", "x.py").as_deref(),
+            Some("idle.exe \"%1\"")
+        );
+    }
+
+    #[test]
+    fn context_menu_entries_apply_to_every_file() {
+        let reg = python_registry();
+        let config = r#"
+            [context_menu.lint]
+            template = "lint.exe %1"
+
+            [context_menu.args]
+            action = "run-with-args"
+
+            [[file_associations]]
+            exec_runtime = "python"
+            extension = "py"
+            verbs = { lint = "ruff.exe %1" }
+            "#;
+        assert_eq!(
+            resolve_with(&reg, config, "lint", "x.py").as_deref(),
+            Some("ruff.exe %1")
+        );
+        assert_eq!(
+            resolve_with(&reg, config, "lint", "x.rb").as_deref(),
+            Some("lint.exe %1")
+        );
+
+        let config: Config = toml::from_str(config).unwrap();
+        let args = configured_verb(&config, None, "ARGS");
+        assert_eq!(args.and_then(|v| v.action), Some(VerbAction::RunWithArgs));
+    }
+
+    #[test]
+    fn fallback_policies() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(
+            Root::ClassesRoot,
+            "*\\shell\\open\\command",
+            "",
+            "viewer.exe %1",
+        );
+        assert_eq!(
+            resolve_with(&reg, PY_VERBS, "edit", "x.py").as_deref(),
+            Some("thonny \"%1\"")
+        );
+        assert_eq!(resolve_with(&reg, PY_VERBS, "runas", "x.py"), None);
+        assert_eq!(
+            resolve_with(&reg, PY_VERBS, "unknown", "x.py").as_deref(),
+            Some("viewer.exe %1")
+        );
+
+        // The editor fallback doesn't apply when the registry has the verb.
+        reg.write_string(
+            Root::ClassesRoot,
+            "*\\shell\\edit\\command",
+            "",
+            "edit.exe %1",
+        );
+        assert_eq!(
+            resolve_with(&reg, PY_VERBS, "edit", "x.py").as_deref(),
+            Some("edit.exe %1")
+        );
+    }
+
+    #[test]
+    fn fixture_walks_the_explorer_chain() {
+        let reg = fixture(include_str!("../test_files/registry/python.reg"));
+        // On the default ProgID, past Winbang's UserChoice.
+        assert_eq!(
+            resolve(&reg, "editwithidle", "x.py").as_deref(),
+            Some("\"C:\\Windows\\py.exe\" -m idlelib \"%L\" %*")
+        );
+        // Extension-specific, perceived-type and all-files verbs.
+        assert_eq!(
+            resolve(&reg, "lint", "x.py").as_deref(),
+            Some("ruff.exe check \"%1\"")
+        );
+        assert!(
+            resolve(&reg, "edit", "x.py")
+                .unwrap()
+                .ends_with(r"\system32\NOTEPAD.EXE %1")
+        );
+        assert_eq!(
+            resolve(&reg, "copyaspath", "x.py").as_deref(),
+            Some("copypath.exe \"%1\"")
+        );
+        // Unknown verbs fall back to the first open command.
+        assert_eq!(
+            resolve(&reg, "print", "x.py").as_deref(),
+            Some("\"C:\\Windows\\py.exe\" \"%L\" %*")
+        );
+    }
+
+    #[test]
+    fn fixture_follows_user_choice_curver() {
+        let reg = fixture(include_str!("../test_files/registry/curver.reg"));
+        assert_eq!(
+            resolve(&reg, "open", "x.rb").as_deref(),
+            Some("\"C:\\Ruby33\\bin\\ruby.exe\" \"%1\" %*")
+        );
+        assert_eq!(
+            resolve(&reg, "edit", "x.rb").as_deref(),
+            Some("notepad.exe \"%1\"")
+        );
+    }
+
+    #[test]
+    fn policy_applies_to_verb_commands() {
+        let dir = env::temp_dir()
+            .join(format!("winbang-verb-policy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("x.py");
+        fs::write(&script, "print(1)\n").unwrap();
+        let config: Config =
+            toml::from_str("[policy]\ndeny_interpreters = [\"py\"]").unwrap();
+
+        let result =
+            dispatch_verb(&python_registry(), "runas", &script, &[], &config);
+        let Err(WinbangError::Blocked { blocked, .. }) = result else {
+            panic!("expected the launch to be blocked");
+        };
+        assert!(blocked.reason.contains("py.exe"), "{}", blocked.reason);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unresolved_without_handler() {
+        let reg = python_registry();
+        assert_eq!(resolve(&reg, "edit", "script"), None);
+        assert_eq!(resolve(&reg, "edit", "x.unknown"), None);
+    }
+
+    /// Write an extensionless script whose shebang names the running test
+    /// binary, the one interpreter certain to exist, and return it with the
+    /// interpreter's name.
+    fn extensionless_script(name: &str) -> (PathBuf, String) {
+        let exe = env::current_exe().unwrap();
+        let dir = env::temp_dir().join(format!(
+            "winbang-verb-{}-{}",
+            name,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("script");
+        fs::write(&script, format!("#!{}\n", exe.display())).unwrap();
+        let interpreter = exe.file_name().unwrap().to_string_lossy();
+        (script, interpreter.to_string())
+    }
+
+    #[test]
+    fn extensionless_files_use_shebang_association() {
+        let (script, interpreter) = extensionless_script("shebang");
+        let config = format!(
+            r#"
+            [[file_associations]]
+            exec_runtime = "{}"
+            view_runtime = "viewer"
+            verbs = {{ lint = "lint.exe %1" }}
+            "#,
+            interpreter
+        );
+        let file = script.to_str().unwrap();
+        let reg = MemoryRegistry::new();
+        assert_eq!(
+            resolve_with(&reg, &config, "lint", file).as_deref(),
+            Some("lint.exe %1")
+        );
+        // Nothing registered: the association's viewer opens the script.
+        assert_eq!(
+            resolve_with(&reg, &config, "edit", file).as_deref(),
+            Some("viewer \"%1\"")
+        );
+        // Winbang's own `No Extension` open command is skipped for the
+        // registry's `edit` verb there.
+        reg.write_string(
+            Root::ClassesRoot,
+            "No Extension\\shell\\open\\command",
+            "",
+            "winbang.exe \"%1\"",
+        );
+        reg.write_string(
+            Root::ClassesRoot,
+            "No Extension\\shell\\edit\\command",
+            "",
+            "notepad.exe %1",
+        );
+        assert_eq!(
+            resolve(&reg, "edit", file).as_deref(),
+            Some("notepad.exe %1")
+        );
+        fs::remove_dir_all(script.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn unmatched_extensionless_files_use_no_extension_progid() {
+        let reg = MemoryRegistry::new();
+        reg.write_string(
+            Root::ClassesRoot,
+            "No Extension\\shell\\print\\command",
+            "",
+            "print.exe \"%1\"",
+        );
+        let file =
+            concat!(env!("CARGO_MANIFEST_DIR"), "/test_files/test-invalid");
+        assert_eq!(
+            resolve(&reg, "print", file).as_deref(),
+            Some("print.exe \"%1\"")
+        );
+        assert_eq!(resolve(&reg, "edit", file), None);
+    }
+
+    #[test]
+    fn non_string_command_is_ignored() {
+        let reg = python_registry();
+        reg.write_value(
+            Root::ClassesRoot,
+            "Python.File\\shell\\print\\command",
+            "",
+            &RegValue::Dword(1),
+        );
+        assert_eq!(
+            resolve(&reg, "print", "x.py").as_deref(),
+            Some("\"C:\\Python\\py.exe\" \"%1\" %*")
+        );
     }
 }
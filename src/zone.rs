@@ -0,0 +1,232 @@
+//! Mark of the Web: the `Zone.Identifier` alternate data stream Windows
+//! attaches to downloaded files, recording the security zone they came from
+//! and where.
+//!
+//! Streams are read through `FileStreams`, so the parsing and the decisions
+//! built on it can be tested with fixtures on any OS.
+
+use std::path::Path;
+
+/// Name of the stream holding the Mark of the Web.
+pub(crate) const ZONE_STREAM: &str = "Zone.Identifier";
+
+/// Lowest zone treated as downloaded from the internet (`URLZONE_INTERNET`).
+pub(crate) const INTERNET_ZONE: u32 = 3;
+
+/// Read access to a file's alternate data streams.
+pub(crate) trait FileStreams {
+    /// Contents of the stream `stream` of `file`, if it has one.
+    fn read_stream(&self, file: &Path, stream: &str) -> Option<Vec<u8>>;
+}
+
+/// The NTFS streams of the running system. Off Windows there are none.
+pub(crate) struct NtfsStreams;
+
+impl FileStreams for NtfsStreams {
+    #[cfg(target_os = "windows")]
+    fn read_stream(&self, file: &Path, stream: &str) -> Option<Vec<u8>> {
+        let mut path = file.as_os_str().to_os_string();
+        path.push(":");
+        path.push(stream);
+        std::fs::read(path).ok()
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn read_stream(&self, _file: &Path, _stream: &str) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// The `[ZoneTransfer]` section of a `Zone.Identifier` stream.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct ZoneInfo {
+    /// `0` local machine, `1` intranet, `2` trusted sites, `3` internet,
+    /// `4` restricted sites.
+    pub(crate) zone_id: u32,
+    /// The page that linked to the download.
+    pub(crate) referrer_url: Option<String>,
+    /// Where the file itself was downloaded from.
+    pub(crate) host_url: Option<String>,
+}
+
+impl ZoneInfo {
+    /// Whether the file came from the internet or a restricted site.
+    pub(crate) fn is_internet(&self) -> bool {
+        self.zone_id >= INTERNET_ZONE
+    }
+
+    /// The zone's name as Internet Options shows it.
+    pub(crate) fn zone_name(&self) -> &'static str {
+        match self.zone_id {
+            0 => "Local machine",
+            1 => "Local intranet",
+            2 => "Trusted sites",
+            3 => "Internet",
+            _ => "Restricted sites",
+        }
+    }
+
+    /// `HostUrl` and `ReferrerUrl` as lines for a warning, or a note that
+    /// the source is unknown.
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    pub(crate) fn describe_source(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(url) = &self.host_url {
+            lines.push(format!("Downloaded from: {}", url));
+        }
+        if let Some(url) = &self.referrer_url {
+            lines.push(format!("Linked from: {}", url));
+        }
+        if lines.is_empty() {
+            lines.push("The download location was not recorded.".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+/// Read the Mark of the Web of `file`.
+///
+/// # Arguments
+///
+/// * `streams`: Stream access, usually `NtfsStreams`.
+/// * `file`: File to check.
+///
+/// returns: Option<ZoneInfo>
+///
+/// # Examples
+///
+/// ```
+/// if let Some(zone) = zone_of(&NtfsStreams, Path::new("build.sh")) {
+///     println!("{}", zone.zone_name());
+/// }
+/// ```
+pub(crate) fn zone_of(
+    streams: &dyn FileStreams,
+    file: &Path,
+) -> Option<ZoneInfo> {
+    parse(&decode(&streams.read_stream(file, ZONE_STREAM)?))
+}
+
+/// Parse a `Zone.Identifier` stream. `None` without a valid `ZoneId`.
+pub(crate) fn parse(text: &str) -> Option<ZoneInfo> {
+    let mut zone_id = None;
+    let mut info = ZoneInfo::default();
+    let mut in_section = false;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_section = line.eq_ignore_ascii_case("[ZoneTransfer]");
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if !in_section {
+            continue;
+        }
+        let value = value.trim().to_string();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "zoneid" => zone_id = value.parse().ok(),
+            "referrerurl" if !value.is_empty() => {
+                info.referrer_url = Some(value)
+            }
+            "hosturl" if !value.is_empty() => info.host_url = Some(value),
+            _ => {}
+        }
+    }
+    info.zone_id = zone_id?;
+    Some(info)
+}
+
+/// Stream contents as text: UTF-16 with a byte-order mark, or UTF-8/ANSI.
+fn decode(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// In-memory streams for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub(crate) struct MemoryStreams {
+    streams: std::collections::HashMap<(std::path::PathBuf, String), Vec<u8>>,
+}
+
+#[cfg(test)]
+impl MemoryStreams {
+    /// Give `file` a `Zone.Identifier` stream with `contents`.
+    pub(crate) fn with_zone(mut self, file: &Path, contents: &str) -> Self {
+        self.streams.insert(
+            (file.to_path_buf(), ZONE_STREAM.to_string()),
+            contents.as_bytes().to_vec(),
+        );
+        self
+    }
+}
+
+#[cfg(test)]
+impl FileStreams for MemoryStreams {
+    fn read_stream(&self, file: &Path, stream: &str) -> Option<Vec<u8>> {
+        self.streams
+            .get(&(file.to_path_buf(), stream.to_string()))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERNET: &str = include_str!("../test_files/zone/internet.txt");
+    const INTRANET: &str = include_str!("../test_files/zone/intranet.txt");
+
+    #[test]
+    fn reads_zone_and_urls_from_the_stream() {
+        let (downloaded, shared) = (Path::new("a.sh"), Path::new("b.sh"));
+        let streams = MemoryStreams::default()
+            .with_zone(downloaded, INTERNET)
+            .with_zone(shared, INTRANET);
+
+        let zone = zone_of(&streams, downloaded).unwrap();
+        assert!(zone.is_internet());
+        assert_eq!(zone.zone_name(), "Internet");
+        assert_eq!(
+            zone.host_url.as_deref(),
+            Some("https://example.com/tools/build.sh")
+        );
+        assert_eq!(
+            zone.describe_source(),
+            "Downloaded from: https://example.com/tools/build.sh\n\
+             Linked from: https://example.com/tools/"
+        );
+
+        let zone = zone_of(&streams, shared).unwrap();
+        assert!(!zone.is_internet());
+        assert_eq!(zone.referrer_url, None);
+        assert_eq!(zone_of(&streams, Path::new("c.sh")), None);
+    }
+
+    #[test]
+    fn tolerates_utf16_and_junk() {
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .into_iter()
+            .chain(INTERNET.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(decode(&utf16), INTERNET);
+        assert_eq!(parse("[ZoneTransfer]\nZoneId=x"), None);
+        assert_eq!(parse("[Other]\nZoneId=3"), None);
+        assert_eq!(
+            parse("[ZoneTransfer]\nZoneId=4\nHostUrl=").unwrap(),
+            ZoneInfo {
+                zone_id: 4,
+                ..ZoneInfo::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,8 @@
+#!/usr/bin/env python
+print("pwned")
+# -----BEGIN MINISIGN SIGNATURE-----
+# untrusted comment: signature from minisign secret key
+# RURkWnOJOk8I/ki3RkDKANruGHyyDsDNt9qRQ6NOKK0jHdoqgP4ITyn1cEOpQqivHQyXpLc91CvMfNnZ8/VtaJBul5qn5p6sPQg=
+# trusted comment: timestamp:1760745600	file:inline.py
+# xIQOP3D/Ih0mxjk1sZN/2YlCYKDf+Gi82Z2Pw/NdBwEFxAXfsFuA/YjHetIARlhLwjoIbqFLvci2ko5uouS/DA==
+# -----END MINISIGN SIGNATURE-----